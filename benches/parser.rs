@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use binocular::pickers::parse_rg_output;
+
+/// Number of context lines around each match, as passed to ripgrep.
+const CTX_LINES: u16 = 4;
+
+/// Generates ripgrep `--heading` output with the given number of files, each with
+/// a handful of matches surrounded by context lines.
+fn rg_output(files: usize) -> String {
+    let mut output = String::new();
+    for file in 0..files {
+        output.push_str(&format!("src/module_{}/file_{}.rs\n", file % 10, file));
+        for line in 1..=50 {
+            let separator = if line % 10 == 0 { ':' } else { '-' };
+            output.push_str(&format!(
+                "{}{}    let value_{} = compute(needle, {});\n",
+                line, separator, line, file
+            ));
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_rg_output");
+    for files in [10, 100, 1_000] {
+        let output = rg_output(files);
+        group.throughput(Throughput::Bytes(output.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(files), &output, |b, output| {
+            b.iter(|| {
+                parse_rg_output(black_box(output), CTX_LINES, CTX_LINES, false, usize::MAX).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -1,141 +1,2155 @@
 use anyhow::{Context, Result};
 use crossterm::event::{Event, EventStream, KeyCode, KeyEvent};
-use ratatui::widgets::ListState;
-use tokio::sync::mpsc::{self, Sender};
-use tokio_stream::StreamExt;
+use ratatui::{backend::Backend, text::Text, widgets::ListState};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    env, io, mem,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    process::Command,
+    runtime::Handle,
+    sync::mpsc::{self, Receiver, Sender},
+    time::{self, Instant},
+};
+use tokio_stream::{Stream, StreamExt};
 use tui_input::{backend::crossterm::EventHandler, Input};
 
 use crate::{
-    pickers::{Picker, PickerItem},
-    tui::Tui,
+    clipboard,
+    config::{ConfigAction, PreviewUpdate},
+    git::{self, BlameLine},
+    keys::Action,
+    open,
+    pickers::{
+        ListStyle, ModeFlag, PendingAction, Picker, PickerEvent, PickerItem, SearchContext,
+        SearchOptions,
+    },
+    tui::{self, Menu, Tui, View},
+    watch::Watcher,
+    workspace, Config, History, Scratchpad,
 };
 
 // TODO: Tune this?
 const CHANNEL_CAPACITY: usize = 100;
 
+/// How long the selection stays highlighted after moving (when animations are enabled).
+const SELECTION_FLASH_DURATION: Duration = Duration::from_millis(150);
+
+/// How long the selection must stop moving before it's previewed, when the preview
+/// updates on idle.
+const PREVIEW_IDLE_DELAY: Duration = Duration::from_millis(150);
+
+/// How long each frame of the searching spinner shows (when animations are enabled).
+const SPINNER_FRAME_DURATION: Duration = Duration::from_millis(80);
+
+/// A preview kept across frames, with what it was built for (if an item was previewed).
+struct CachedPreview {
+    key: Option<PreviewKey>,
+    text: Text<'static>,
+    title: String,
+}
+
+/// What a preview was built for: the previewed item and the preview modes.
+#[derive(PartialEq)]
+struct PreviewKey {
+    /// The key of the previewed item.
+    item_key: String,
+    /// Whether the preview was a diff.
+    diff: bool,
+    /// Whether the preview was annotated with blame.
+    blame: bool,
+    /// Whether the preview showed the context.
+    context: bool,
+}
+
+/// Maximum number of files whose data (like their diff) is cached, beyond which the
+/// cache starts over.
+const MAX_CACHED_FILES: usize = 256;
+
+/// Data computed in the background for a previewed file (like its diff), with the
+/// file's modification time from before it was computed.
+struct FileData<T> {
+    path: String,
+    modified: Option<SystemTime>,
+    data: Option<T>,
+}
+
+/// Data computed in the background, sent back to the application loop.
+enum Loaded {
+    Diff(FileData<String>),
+    Blame(FileData<Vec<BlameLine>>),
+    Preview(FileData<String>),
+}
+
+/// Data computed in the background for the previewed files, kept until the files are
+/// modified.
+struct FileCache<T> {
+    entries: HashMap<String, (Option<SystemTime>, Option<T>)>,
+    /// The files whose data is being computed.
+    pending: HashSet<String>,
+}
+
+impl<T: Send + 'static> FileCache<T> {
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Returns the data of the file, unless it's missing or the file was modified
+    /// since it was computed.
+    fn get(&self, path: &str) -> Option<&Option<T>> {
+        self.entries
+            .get(path)
+            .filter(|(modified, _)| *modified == file_modified(path))
+            .map(|(_, data)| data)
+    }
+
+    /// Computes the data of the file with `compute` on a blocking thread, unless it's
+    /// already being computed, and sends it to `tx` wrapped by `loaded`. Outside of a
+    /// Tokio runtime, the data is computed right away.
+    fn load(
+        &mut self,
+        path: &str,
+        compute: impl FnOnce(&str) -> Option<T> + Send + 'static,
+        tx: &Sender<Loaded>,
+        loaded: fn(FileData<T>) -> Loaded,
+    ) {
+        if !self.pending.insert(path.to_owned()) {
+            return;
+        }
+        let path = path.to_owned();
+        let load = move || {
+            let modified = file_modified(&path);
+            let data = compute(&path);
+            FileData {
+                path,
+                modified,
+                data,
+            }
+        };
+        match Handle::try_current() {
+            Ok(handle) => {
+                let tx = tx.clone();
+                handle.spawn_blocking(move || tx.blocking_send(loaded(load())));
+            }
+            Err(_) => self.insert(load()),
+        }
+    }
+
+    fn insert(&mut self, loaded: FileData<T>) {
+        self.pending.remove(&loaded.path);
+        if self.entries.len() >= MAX_CACHED_FILES {
+            self.entries.clear();
+        }
+        self.entries
+            .insert(loaded.path, (loaded.modified, loaded.data));
+    }
+
+    fn remove(&mut self, path: &str) {
+        self.entries.remove(path);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Returns when the file was last modified, if available.
+fn file_modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
 /// The application state. Abstraction over what's displayed
 /// in the TUI.
-pub struct App<I, P>
+pub struct App<'a, I, P>
 where
     I: PickerItem,
     P: Picker<I>,
 {
+    config: &'a Config,
     picker: P,
     input: Input,
     results: Vec<I>,
+    /// Secondary filter applied to the results in memory.
+    filter: Input,
+    /// Whether key presses edit the filter instead of the search input.
+    filtering: bool,
+    /// Indices of the results that match the filter, in display order.
+    visible: Vec<usize>,
+    /// Incremented whenever the displayed results change.
+    visible_revision: u64,
     state: ListState,
     show_help: bool,
+    /// How many lines the help dialog is scrolled down.
+    help_scroll: u16,
+    show_preview: bool,
+    /// Whether to preview the selected item as a diff against the configured base ref.
+    show_diff: bool,
+    /// Preview scroll offsets of the items scrolled during the current search, by item key.
+    preview_scrolls: HashMap<String, u16>,
+    /// Cache of the diffs computed for each previewed file.
+    diffs: FileCache<String>,
+    /// Cache of the previews built in the background, by file.
+    previews: FileCache<String>,
+    /// Whether to annotate the preview with the last change of each line.
+    show_blame: bool,
+    /// Cache of the blame of each previewed file.
+    blames: FileCache<Vec<BlameLine>>,
+    /// Whether to preview results with their context, instead of only the matched lines.
+    show_context: bool,
+    /// Whether the results display their line number.
+    show_line_numbers: bool,
+    /// The last rendered preview, reused until the selection or the results change.
+    preview_cache: Option<CachedPreview>,
+    /// The query that the current results are for.
+    results_query: Option<String>,
+    /// The query of the last search, which the next results are for.
+    searched_query: Option<String>,
+    /// The events of the last search. Those of previous searches are dropped with their
+    /// receiver, so that their late results don't replace the last search's.
+    search_events: Option<Receiver<PickerEvent<I>>>,
+    /// Status message displayed until the next key press.
+    status: Option<String>,
+    /// When the pending search was dispatched, until its results arrive.
+    search_started_at: Option<Instant>,
+    /// When the selection last moved, used to briefly highlight it.
+    selection_moved_at: Option<Instant>,
+    /// When to preview the selection, if it moved too recently (see
+    /// [PreviewUpdate::OnIdle]).
+    preview_deferred_until: Option<Instant>,
+    /// The last item that was selected, which can be selected again.
+    last_selected: Option<I>,
+    /// A picker action waiting for the user's confirmation.
+    pending_confirm: Option<PendingAction>,
+    /// Whether quitting is waiting for the user's confirmation.
+    confirming_quit: bool,
+    /// The marked items, in the order they were marked.
+    marks: Vec<I>,
+    /// Keys of the items selected since the query was last cleared.
+    visited: HashSet<String>,
+    /// The channel data computed in the background is sent through.
+    loaded_tx: Sender<Loaded>,
+    loaded_rx: Receiver<Loaded>,
+    /// Paths of the files opened during the session, in the order they were first opened.
+    opened: Vec<String>,
+    /// The file the opened files are exported to, if given.
+    workspace: Option<PathBuf>,
+    /// Whether selecting an item exits the application and returns it.
+    return_selection: bool,
+    /// Whether the input title shows the picker's command-line flags.
+    show_flags: bool,
+    /// The directory binocular was launched from.
+    cwd: PathBuf,
+    /// Maximum number of results of the current search, if any.
+    max_results: Option<usize>,
+    /// Number of results left out of the current results by the limit, if any.
+    truncated: Option<usize>,
+    /// Whether the query is searched again when files change.
+    watch: bool,
+    /// The watcher of the current directory, while watching a non-empty query.
+    watcher: Option<Watcher>,
+    /// The watched file of the selected item, and its watcher, when refreshing previews.
+    preview_watcher: Option<(String, Watcher)>,
+    /// The queries searched across sessions.
+    history: History,
+    /// The queries saved for the current project.
+    scratchpad: Scratchpad,
+    /// The menu of queries to search, while it's open.
+    menu: Option<QueryMenu>,
+    /// The name the current query is saved under, while it's being typed.
+    naming: Option<Input>,
+    /// Whether keys navigate the results instead of editing the query.
+    navigating: bool,
+    /// Positions among the visible results of the ones labeled with a digit, while
+    /// navigating.
+    numbered: Vec<usize>,
+}
+
+/// A menu of queries to search, like the recent ones.
+struct QueryMenu {
+    title: &'static str,
+    /// The action opening (and closing) the menu.
+    action: Action,
+    /// The labels of the entries and their queries.
+    entries: Vec<(String, String)>,
+    /// The position of the selected entry.
+    selected: usize,
 }
 
-impl<I, P> App<I, P>
+impl<'a, I, P> App<'a, I, P>
 where
     I: PickerItem,
     P: Picker<I>,
 {
-    /// Initializes a new application.
-    pub fn new(picker: P) -> Self {
-        Self {
+    /// Initializes a new application, searching the current directory.
+    pub fn new(picker: P, config: &'a Config) -> Result<Self> {
+        let picker_id = picker.id();
+        let (loaded_tx, loaded_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let cwd = env::current_dir().context("Failed to get the current directory")?;
+        Ok(Self {
+            config,
             picker,
             input: Input::default(),
             results: Vec::new(),
+            filter: Input::default(),
+            filtering: false,
+            visible: Vec::new(),
+            visible_revision: 0,
             state: ListState::default(),
             show_help: false,
+            help_scroll: 0,
+            show_preview: config.preview_visible(),
+            show_diff: config.preview_diff(),
+            preview_scrolls: HashMap::new(),
+            diffs: FileCache::new(),
+            previews: FileCache::new(),
+            show_blame: config.preview_blame(),
+            blames: FileCache::new(),
+            show_context: true,
+            show_line_numbers: config.grep_line_numbers(),
+            preview_cache: None,
+            results_query: None,
+            searched_query: None,
+            search_events: None,
+            status: None,
+            search_started_at: None,
+            selection_moved_at: None,
+            preview_deferred_until: None,
+            last_selected: None,
+            pending_confirm: None,
+            confirming_quit: false,
+            marks: Vec::new(),
+            visited: HashSet::new(),
+            loaded_tx,
+            loaded_rx,
+            opened: Vec::new(),
+            workspace: None,
+            return_selection: false,
+            show_flags: config.show_flags(),
+            cwd,
+            max_results: config.max_results(picker_id),
+            truncated: None,
+            watch: config.watch(),
+            history: History::default(),
+            scratchpad: Scratchpad::default(),
+            menu: None,
+            naming: None,
+            navigating: false,
+            numbered: Vec::new(),
+            watcher: None,
+            preview_watcher: None,
+        })
+    }
+
+    /// Makes selecting an item exit the application and return it from [App::run],
+    /// instead of letting the picker handle the selection.
+    pub fn return_selection(mut self, return_selection: bool) -> Self {
+        self.return_selection = return_selection;
+        self
+    }
+
+    /// Makes the input title show the command-line flags derived from the picker's
+    /// modes, regardless of the configuration.
+    pub fn show_flags(mut self, show_flags: bool) -> Self {
+        self.show_flags |= show_flags;
+        self
+    }
+
+    /// Makes the application search the query again when files change, regardless of the
+    /// configuration.
+    pub fn watch(mut self, watch: bool) -> Self {
+        self.watch |= watch;
+        self
+    }
+
+    /// Sets the search history, which records the queries whose results are selected,
+    /// offered in the recent searches menu.
+    pub fn history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Sets the queries saved for the current project, offered in the saved queries menu.
+    pub fn scratchpad(mut self, scratchpad: Scratchpad) -> Self {
+        self.scratchpad = scratchpad;
+        self
+    }
+
+    /// Sets the workspace file the opened files are exported to (see
+    /// [App::export_workspace]), instead of `binocular.code-workspace` in the current
+    /// directory.
+    pub fn workspace(mut self, path: Option<PathBuf>) -> Self {
+        self.workspace = path;
+        self
+    }
+
+    /// Seeds the search input with the query, which is searched when the application
+    /// starts.
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.input = Input::new(query.into());
+        self
+    }
+
+    /// Resets the session to the state of a fresh launch: an empty query without results,
+    /// marks or filter, the configured preview, and the picker's launch modes (like its
+    /// scope).
+    pub fn reset(&mut self) {
+        self.picker.reset();
+        self.input = Input::default();
+        self.results.clear();
+        self.filter = Input::default();
+        self.filtering = false;
+        self.set_visible(Vec::new());
+        self.state = ListState::default();
+        self.show_help = false;
+        self.help_scroll = 0;
+        self.show_preview = self.config.preview_visible();
+        self.show_diff = self.config.preview_diff();
+        self.show_blame = self.config.preview_blame();
+        self.show_context = true;
+        self.show_line_numbers = self.config.grep_line_numbers();
+        self.preview_scrolls.clear();
+        self.diffs.clear();
+        self.previews.clear();
+        self.blames.clear();
+        self.preview_cache = None;
+        self.results_query = None;
+        self.searched_query = None;
+        self.search_events = None;
+        self.status = None;
+        self.search_started_at = None;
+        self.selection_moved_at = None;
+        self.preview_deferred_until = None;
+        self.last_selected = None;
+        self.pending_confirm = None;
+        self.confirming_quit = false;
+        self.marks.clear();
+        self.visited.clear();
+        self.opened.clear();
+        self.max_results = self.config.max_results(self.picker.id());
+        self.truncated = None;
+        self.watcher = None;
+        self.preview_watcher = None;
+        self.menu = None;
+        self.naming = None;
+        self.navigating = false;
+        self.numbered.clear();
+    }
+
+    /// Searches the current input to completion, without any UI. Returns the number of
+    /// results.
+    pub async fn search_initial(&mut self) -> usize {
+        self.search();
+        // The picker drops its sender once the search is done.
+        while let Some(event) = self.next_search_event().await {
+            self.handle_picker_event(event);
+        }
+        self.visible.len()
+    }
+
+    /// Returns the current search query, like the final one once [App::run] returns.
+    pub fn search_query(&self) -> &str {
+        self.input.value()
+    }
+
+    /// Exports the files opened during the session to the workspace file, returning its
+    /// path. A `.code-workspace` file is written as a VS Code workspace, and any other as
+    /// a plain list of paths.
+    pub fn export_workspace(&self) -> Result<&Path> {
+        let path = self
+            .workspace
+            .as_deref()
+            .unwrap_or(Path::new(workspace::DEFAULT_WORKSPACE_FILE));
+        workspace::export(path, &self.opened, &self.cwd)?;
+        Ok(path)
+    }
+
+    /// Returns the status message reported by the picker, if any (e.g. a search error).
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    /// Acts on the selected item like pressing enter: returns it when the application
+    /// returns selections (see [App::return_selection]), otherwise lets the picker
+    /// handle it.
+    pub fn accept_selection(&mut self) -> Result<Option<I>> {
+        let Some(index) = self.selected_index() else {
+            return Ok(None);
+        };
+        if self.return_selection {
+            return Ok(Some(self.results.swap_remove(index)));
         }
+        self.picker
+            .handle_selection(&self.results[index], self.config)
+            .context("Failed to process selected item")?;
+        record_opened(&mut self.opened, &self.results[index]);
+        Ok(None)
+    }
+
+    /// Runs the application loop. Returns the marked items, or else the selected one,
+    /// when the application exits on a selection (see [App::return_selection]).
+    pub async fn run(&mut self, tui: &mut Tui<'_>) -> Result<Vec<I>> {
+        self.run_with_events(tui, EventStream::new()).await
     }
 
-    /// Runs the application loop.
-    pub async fn run(&mut self, tui: &mut Tui<'_>) -> Result<()> {
-        let mut reader = EventStream::new();
+    /// Runs the application loop like [App::run], but reads the terminal events from
+    /// `reader` instead of crossterm's event stream. Useful to drive the application
+    /// with synthetic events (e.g. in tests).
+    pub async fn run_with_events<B, S>(
+        &mut self,
+        tui: &mut Tui<'_, B>,
+        mut reader: S,
+    ) -> Result<Vec<I>>
+    where
+        B: Backend,
+        S: Stream<Item = io::Result<Event>> + Unpin,
+    {
         let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
 
+        // Search the seeded query, unless it was already searched.
+        if !self.input.value().is_empty()
+            && self.results_query.as_deref() != Some(self.input.value())
+        {
+            self.search();
+        }
+
         loop {
             // Render the terminal UI.
-            tui.render(
-                &self.input,
-                &self.results,
-                &mut self.state,
-                self.show_help,
-                self.picker.preview_title(),
-                self.picker.name(),
-            )
-            .context("Failed to render application window")?;
+            self.watch_selected_file();
+            let (preview, preview_title) = self.preview();
+            let preview_scroll = self.clamp_preview_scroll(preview.lines.len());
+            let input_title = self.input_title();
+            let mut modes = self.picker.modes();
+            if self.watcher.is_some() {
+                modes.push(ModeFlag("watch".to_owned()));
+            }
+            let marked = self.marks.iter().map(|item| item.key()).collect::<Vec<_>>();
+            let flash_deadline = self
+                .selection_moved_at
+                .map(|moved_at| moved_at + SELECTION_FLASH_DURATION);
+            let (searching, spinner_deadline) = self.spinner(Instant::now());
+            let preview_deadline = self.preview_deferred_until;
+            self.numbered = tui
+                .render(
+                    View {
+                        input: match &self.naming {
+                            Some(name) => name,
+                            None if self.filtering => &self.filter,
+                            None => &self.input,
+                        },
+                        results: &self.results,
+                        visible: &self.visible,
+                        visible_revision: self.visible_revision,
+                        line_numbers: self.show_line_numbers,
+                        show_help: self.show_help,
+                        help_scroll: self.help_scroll,
+                        layout: self
+                            .config
+                            .layout(self.picker.id())
+                            .unwrap_or_else(|| self.picker.layout()),
+                        menu: self.menu.as_ref().map(|menu| Menu {
+                            title: menu.title,
+                            entries: &menu.entries,
+                            selected: menu.selected,
+                        }),
+                        show_preview: self.show_preview,
+                        preview,
+                        preview_scroll,
+                        preview_title: &preview_title,
+                        input_title: &input_title,
+                        prompt_icon: self.picker.icon(),
+                        prompt_color: self.picker.prompt_color(self.config),
+                        modes: &modes,
+                        marked: &marked,
+                        visited: &self.visited,
+                        confirm: match (&self.pending_confirm, self.confirming_quit) {
+                            (Some(action), _) => Some(action.prompt()),
+                            (None, true) => Some("Quit and discard the marked results?"),
+                            (None, false) => None,
+                        },
+                        status: self.status.as_deref(),
+                        truncated: self.truncated,
+                        flash_selection: flash_deadline.is_some(),
+                        numbered: self.navigating,
+                        searching,
+                    },
+                    &mut self.state,
+                )
+                .context("Failed to render application window")?;
 
             tokio::select! {
-                Some(event) = reader.next() => {
+                // Handle the picker's events first, so that key presses apply to the
+                // latest results.
+                biased;
+
+                // Received something from the picker, update the results.
+                event = async { self.search_events.as_mut()?.recv().await },
+                    if self.search_events.is_some() => {
+                    match event {
+                        Some(event) => self.handle_picker_event(event),
+                        // The search is done.
+                        None => self.search_events = None,
+                    }
+                }
+                Some(event) = rx.recv() => self.handle_picker_event(event),
+                // Files changed, search again.
+                Some(()) = async { self.watcher.as_mut()?.changed().await },
+                    if self.watcher.is_some() => {
+                    self.search();
+                }
+                // The selected item's file changed, refresh its preview.
+                Some(()) = async { self.preview_watcher.as_mut()?.1.changed().await },
+                    if self.preview_watcher.is_some() => {
+                    self.refresh_preview();
+                }
+                // Data computed in the background, preview it.
+                Some(loaded) = self.loaded_rx.recv() => self.handle_loaded(loaded),
+                event = reader.next() => {
+                    // The terminal events ended (like when the terminal closed), quit.
+                    let Some(event) = event else {
+                        break;
+                    };
                     if let Event::Key(key) = event.context("Failed to read terminal event")? {
-                        if key.code == KeyCode::Esc {
+                        if let Some(action) = self.pending_confirm.take() {
+                            self.handle_confirmation(action, key);
+                            continue;
+                        }
+                        if self.confirming_quit {
+                            self.confirming_quit = false;
+                            if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                                break;
+                            }
+                            self.status = Some("Cancelled".to_owned());
+                            continue;
+                        }
+
+                        match self.config.action(&key) {
+                            // Close the query menu.
+                            Some(Action::Quit) if self.menu.is_some() => {
+                                self.menu = None;
+                                continue;
+                            }
+                            // Stop naming the query without saving it.
+                            Some(Action::Quit) if self.naming.is_some() => {
+                                self.naming = None;
+                                continue;
+                            }
+                            // Leave the navigation mode.
+                            Some(Action::Quit) if self.navigating => {
+                                self.navigating = false;
+                                continue;
+                            }
+                            // Close the filter.
+                            Some(Action::Quit) if self.filtering => {
+                                self.filtering = false;
+                                self.filter = Input::default();
+                                self.apply_filter();
+                                continue;
+                            }
+                            // Ask before discarding the marks.
+                            Some(Action::Quit)
+                                if self.config.confirm_quit() && !self.marks.is_empty() =>
+                            {
+                                self.confirming_quit = true;
+                                continue;
+                            }
                             // Exit the application.
-                            break;
+                            Some(Action::Quit) => break,
+                            // Exit the application, returning the marked items or else the
+                            // selected one.
+                            Some(Action::Select)
+                                if self.return_selection
+                                    && !self.show_help
+                                    && self.menu.is_none()
+                                    && self.naming.is_none() =>
+                            {
+                                if !self.marks.is_empty() {
+                                    self.record_query();
+                                    return Ok(mem::take(&mut self.marks));
+                                }
+                                if let Some(index) = self.selected_index() {
+                                    self.record_query();
+                                    return Ok(vec![self.results.swap_remove(index)]);
+                                }
+                                continue;
+                            }
+                            _ => {}
                         }
 
                         self.handle_key_event(key, tx.clone()).context("Failed to handle key event")?;
                     }
                 }
-                // Received something from the picker, update the results.
-                Some(results) = rx.recv() => self.handle_results(results),
+                // Stop highlighting the selection.
+                _ = time::sleep_until(flash_deadline.unwrap_or_else(Instant::now)),
+                    if flash_deadline.is_some() => {
+                    self.selection_moved_at = None;
+                }
+                // The selection stopped moving, preview it.
+                _ = time::sleep_until(preview_deadline.unwrap_or_else(Instant::now)),
+                    if preview_deadline.is_some() => {
+                    self.preview_deferred_until = None;
+                }
+                // Show (or animate) the searching spinner.
+                _ = time::sleep_until(spinner_deadline.unwrap_or_else(Instant::now)),
+                    if spinner_deadline.is_some() => {}
                 else => break
             }
         }
 
-        Ok(())
+        Ok(Vec::new())
+    }
+
+    /// Resolves the action waiting for confirmation: `y` runs it, `n` or quitting cancels it,
+    /// and any other key keeps waiting.
+    fn handle_confirmation(&mut self, action: PendingAction, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('y' | 'Y') => {
+                if let Err(err) = action.run() {
+                    self.status = Some(format!("{:#}", err));
+                }
+            }
+            KeyCode::Char('n' | 'N') => self.status = Some("Cancelled".to_owned()),
+            _ if self.config.action(&key) == Some(Action::Quit) => {
+                self.status = Some("Cancelled".to_owned())
+            }
+            _ => self.pending_confirm = Some(action),
+        }
+    }
+
+    /// Updates the query menu based on the key press.
+    fn handle_menu_key_event(&mut self, key: KeyEvent) {
+        let Some(menu) = &mut self.menu else {
+            return;
+        };
+        match self.config.action(&key) {
+            Some(Action::SelectPrevious) => {
+                menu.selected = menu
+                    .selected
+                    .checked_sub(1)
+                    .unwrap_or(menu.entries.len() - 1);
+            }
+            Some(Action::SelectNext) => menu.selected = (menu.selected + 1) % menu.entries.len(),
+            Some(Action::Select) => {
+                // Search the picked query.
+                let (_, query) = menu.entries.swap_remove(menu.selected);
+                self.input = Input::new(query);
+                self.menu = None;
+                self.filtering = false;
+                self.max_results = self.config.max_results(self.picker.id());
+                self.search();
+            }
+            Some(action) if action == menu.action => self.menu = None,
+            _ => {}
+        }
+    }
+
+    /// Updates the name of the query being saved based on the key press, saving it
+    /// once the name is accepted.
+    fn handle_naming_key_event(&mut self, key: KeyEvent) {
+        let Some(name) = &mut self.naming else {
+            return;
+        };
+        if self.config.action(&key) != Some(Action::Select) {
+            name.handle_event(&Event::Key(key));
+            return;
+        }
+
+        let name = name.value().trim().to_owned();
+        if name.is_empty() {
+            return;
+        }
+        self.naming = None;
+        self.status = Some(
+            match self
+                .scratchpad
+                .save(self.picker.id(), &name, self.input.value())
+            {
+                Ok(()) => format!("Saved the query as `{}`", name),
+                Err(err) => format!("{:#}", err),
+            },
+        );
+    }
+
+    /// Runs a user-defined action on the selected item in the background, reporting
+    /// its failure in the status line.
+    fn run_custom_action(&self, name: &str, action: &ConfigAction, tx: Sender<PickerEvent<I>>) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+        let (file, line) = item.location().map_or((String::new(), 0), |location| {
+            (location.path.to_owned(), *location.lines.start())
+        });
+        let command = open::shell_command(
+            &action.command,
+            &[
+                ("file", &file),
+                ("line", &line.to_string()),
+                ("text", &item.text()),
+            ],
+        );
+        let mut command = match command {
+            Ok(command) => Command::from(command),
+            Err(err) => {
+                let _ = tx.try_send(PickerEvent::Status(format!(
+                    "The action `{}` failed: {:#}",
+                    name, err
+                )));
+                return;
+            }
+        };
+        command
+            .current_dir(&self.cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        let name = name.to_owned();
+        tokio::spawn(async move {
+            let error = match command.output().await {
+                Ok(output) if output.status.success() => return,
+                Ok(output) => {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    stderr
+                        .lines()
+                        .next()
+                        .map_or_else(|| output.status.to_string(), str::to_owned)
+                }
+                Err(err) => err.to_string(),
+            };
+            let _ = tx
+                .send(PickerEvent::Status(format!(
+                    "The action `{}` failed: {}",
+                    name, error
+                )))
+                .await;
+        });
+    }
+
+    /// Records the current query in the history, after selecting one of its results.
+    fn record_query(&mut self) {
+        let query = self.input.value();
+        if query.is_empty() {
+            return;
+        }
+        if let Err(err) = self.history.record(self.picker.id(), query) {
+            self.status = Some(format!("{:#}", err));
+        }
+    }
+
+    /// Returns the index of the selected item within the results, if there are any.
+    fn selected_index(&self) -> Option<usize> {
+        self.visible
+            .get(self.state.selected().unwrap_or(0))
+            .copied()
+    }
+
+    /// Returns the selected item, if any.
+    fn selected_item(&self) -> Option<&I> {
+        self.selected_index().map(|index| &self.results[index])
+    }
+
+    /// Searches for the current input, inline if the picker can do it synchronously.
+    fn search(&mut self) {
+        // Only non-empty queries are watched.
+        match (self.watch, self.input.value().is_empty()) {
+            (true, false) if self.watcher.is_none() => match Watcher::new(self.cwd.clone()) {
+                Ok(watcher) => self.watcher = Some(watcher),
+                // Stop watching instead of failing again on every search.
+                Err(err) => {
+                    self.watch = false;
+                    self.status = Some(format!("{:#}", err));
+                }
+            },
+            (_, true) => self.watcher = None,
+            _ => {}
+        }
+
+        let context = self.search_context();
+        self.searched_query = Some(context.query.clone());
+        self.search_events = None;
+        match self.picker.try_handle_input_sync(&context) {
+            Some(events) => events
+                .into_iter()
+                .for_each(|event| self.handle_picker_event(event)),
+            None => {
+                let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+                self.search_events = Some(rx);
+                self.search_started_at = Some(Instant::now());
+                self.picker.handle_input_change(context, tx);
+            }
+        }
+    }
+
+    /// Returns the next event of the last search, or `None` once it's done.
+    async fn next_search_event(&mut self) -> Option<PickerEvent<I>> {
+        self.search_events.as_mut()?.recv().await
+    }
+
+    /// Returns the frame of the searching spinner at `now`, shown once the pending search
+    /// runs longer than the configured delay, and when to render it next.
+    fn spinner(&self, now: Instant) -> (Option<usize>, Option<Instant>) {
+        let Some(started_at) = self.search_started_at else {
+            return (None, None);
+        };
+        let shown_at = started_at + self.config.spinner_delay();
+        if now < shown_at {
+            return (None, Some(shown_at));
+        }
+        if !self.config.animations() {
+            return (Some(0), None);
+        }
+        let frame = (now - shown_at).as_millis() / SPINNER_FRAME_DURATION.as_millis();
+        let next_frame = shown_at + SPINNER_FRAME_DURATION * (frame as u32 + 1);
+        (Some(frame as usize), Some(next_frame))
+    }
+
+    /// Handles an event sent by the picker.
+    fn handle_picker_event(&mut self, event: PickerEvent<I>) {
+        match event {
+            PickerEvent::Results(results) => {
+                self.search_started_at = None;
+                self.truncated = None;
+                self.handle_results(results);
+                self.warn_broad_query();
+            }
+            PickerEvent::Truncated(count) => {
+                self.truncated = Some(count);
+                self.warn_broad_query();
+            }
+            PickerEvent::Status(status) => self.status = Some(status),
+        }
+    }
+
+    /// Returns the title of the input field: the picker's name, annotated with its
+    /// flags (when shown) and the results filter.
+    fn input_title(&self) -> String {
+        if self.naming.is_some() {
+            return "Save Query As".to_owned();
+        }
+        if self.filtering {
+            return "Filter Results".to_owned();
+        }
+        if self.navigating {
+            return "Navigate Results".to_owned();
+        }
+
+        let mut title = self.picker.name().to_owned();
+        let flags = self.picker.flags();
+        if self.show_flags && !flags.is_empty() {
+            title.push_str(&format!(" [{}]", flags.join(" ")));
+        }
+        if !self.filter.value().is_empty() {
+            title.push_str(&format!(" [filter: {}]", self.filter.value()));
+        }
+        title
+    }
+
+    /// Returns the context of a search for the current input.
+    fn search_context(&self) -> SearchContext {
+        SearchContext {
+            query: self.input.value().to_owned(),
+            options: SearchOptions {
+                context_before: self.config.context_before(self.picker.id()),
+                context_after: self.config.context_after(self.picker.id()),
+                max_results: self.max_results,
+            },
+            cwd: self.cwd.clone(),
+        }
     }
 
     /// Updates the UI based on the key press.
-    fn handle_key_event(&mut self, key: KeyEvent, tx: Sender<Vec<I>>) -> Result<()> {
+    fn handle_key_event(&mut self, key: KeyEvent, tx: Sender<PickerEvent<I>>) -> Result<()> {
+        self.status = None;
+        if self.naming.is_some() {
+            self.handle_naming_key_event(key);
+            return Ok(());
+        }
+        if self.menu.is_some() {
+            self.handle_menu_key_event(key);
+            return Ok(());
+        }
+        // Keys of user-defined actions can't shadow the built-in ones.
+        if let (None, Some((name, action)), false) = (
+            self.config.action(&key),
+            self.config.custom_action(&key),
+            self.show_help,
+        ) {
+            self.run_custom_action(name, action, tx);
+            return Ok(());
+        }
+
         // Note that only some actions are enabled when showing the help dialog.
-        match (key.code, self.show_help) {
+        match (self.config.action(&key), self.show_help) {
             // Select the previous item from the results list.
-            (KeyCode::Up, false) => {
+            (Some(Action::SelectPrevious), false) if !self.visible.is_empty() => {
                 self.state.select(Some(self.state.selected().map_or(0, |i| {
                     if i == 0 {
-                        self.results.len() - 1
+                        self.visible.len() - 1
                     } else {
                         i - 1
                     }
                 })));
+                self.handle_selection_move();
             }
             // Select the next item from the results list.
-            (KeyCode::Down, false) => {
+            (Some(Action::SelectNext), false) if !self.visible.is_empty() => {
                 self.state.select(Some(self.state.selected().map_or(0, |i| {
-                    if i >= self.results.len() - 1 {
+                    if i >= self.visible.len() - 1 {
                         0
                     } else {
                         i + 1
                     }
                 })));
+                self.handle_selection_move();
+            }
+            (Some(Action::Select), false) if !self.marks.is_empty() => {
+                // Handle the marked items.
+                self.filtering = false;
+                for item in &self.marks {
+                    self.picker
+                        .handle_selection(item, self.config)
+                        .context("Failed to process selected item")?;
+                    self.visited.insert(item.key());
+                    record_opened(&mut self.opened, item);
+                    if let Some(notice) = read_only_notice(self.config, item) {
+                        self.status = Some(notice);
+                    }
+                }
+                self.last_selected = self.marks.pop();
+                self.marks.clear();
+                self.record_query();
+            }
+            (Some(Action::Select), false) => {
+                // Handle the selection. While filtering, this is the highlighted item of
+                // the filtered results, and the filter stays applied once accepted.
+                self.filtering = false;
+                if let Some(item) = self.selected_item() {
+                    self.picker
+                        .handle_selection(item, self.config)
+                        .context("Failed to process selected item")?;
+                    let (key, notice) = (item.key(), read_only_notice(self.config, item));
+                    self.last_selected = Some(item.clone());
+                    self.visited.insert(key);
+                    if let Some(item) = &self.last_selected {
+                        record_opened(&mut self.opened, item);
+                    }
+                    self.status = notice;
+                    self.record_query();
+                }
+            }
+            (Some(Action::ToggleMark), false) => {
+                if let Some(item) = self.selected_item() {
+                    let key = item.key();
+                    match self.marks.iter().position(|mark| mark.key() == key) {
+                        Some(i) => {
+                            self.marks.remove(i);
+                        }
+                        None => self.marks.push(item.clone()),
+                    }
+                }
             }
-            (KeyCode::Enter, false) => {
-                // Handle the selection.
-                if let Some(item) = self.results.get(self.state.selected().unwrap_or(0)) {
+            (Some(Action::Reselect), false) => {
+                // Handle the last selection again.
+                if let Some(item) = &self.last_selected {
                     self.picker
-                        .handle_selection(item)
+                        .handle_selection(item, self.config)
                         .context("Failed to process selected item")?;
+                    self.status = read_only_notice(self.config, item);
+                }
+            }
+            (Some(Action::TogglePreview), false) => {
+                // Toggle the preview window.
+                self.show_preview = !self.show_preview;
+            }
+            (Some(Action::RevealFile), false) => {
+                // Reveal the selected item's file in the file manager.
+                if let Some(location) = self.selected_item().and_then(|item| item.location()) {
+                    if let Err(err) = open::reveal(location.path) {
+                        self.status = Some(format!("{:#}", err));
+                    }
+                }
+            }
+            (Some(Action::ScrollPreviewUp), false) => {
+                if let Some(scroll) = self.preview_scroll_mut() {
+                    *scroll = scroll.saturating_sub(1);
                 }
             }
-            (KeyCode::Char('?'), _) => {
+            (Some(Action::ScrollPreviewDown), false) => {
+                // The offset is clamped to the preview's length when rendering.
+                if let Some(scroll) = self.preview_scroll_mut() {
+                    *scroll = scroll.saturating_add(1);
+                }
+            }
+            (Some(Action::CopyLink), false) => {
+                // Copy a link to the selected item, falling back to its `path:line`.
+                if let Some(location) = self.selected_item().and_then(|item| item.location()) {
+                    let link = git::permalink(location.path, &location.lines)
+                        .unwrap_or_else(|| format!("{}:{}", location.path, location.lines.start()));
+                    self.status = Some(match clipboard::copy(&link) {
+                        Ok(()) => format!("Copied {}", link),
+                        Err(err) => format!("{:#}", err),
+                    });
+                }
+            }
+            (Some(Action::CopySnippet), false) => {
+                // Copy the selected item's preview, headed by its `path:line`.
+                if let Some(item) = self.selected_item() {
+                    let snippet = item.preview(self.config);
+                    let (snippet, source) = match item.location() {
+                        Some(location) => {
+                            let source = format!("{}:{}", location.path, location.lines.start());
+                            (format!("{}\n{}", source, snippet), source)
+                        }
+                        None => (snippet, item.key()),
+                    };
+                    self.status = Some(match clipboard::copy(&snippet) {
+                        Ok(()) => format!("Copied the snippet of {}", source),
+                        Err(err) => format!("{:#}", err),
+                    });
+                }
+            }
+            (Some(Action::LoadMore), false) => {
+                // Search again, doubling the limit.
+                if let (Some(_), Some(max_results)) = (self.truncated, self.max_results) {
+                    self.max_results = Some(max_results.saturating_mul(2));
+                    self.search();
+                }
+            }
+            (Some(Action::ToggleDiff), false) => {
+                // Toggle the diff preview.
+                self.show_diff = !self.show_diff;
+            }
+            (Some(Action::ToggleBlame), false) => {
+                // Toggle the blame preview.
+                self.show_blame = !self.show_blame;
+            }
+            (Some(Action::ToggleContext), false) => {
+                // Toggle the context of the preview.
+                self.show_context = !self.show_context;
+            }
+            (Some(Action::ToggleLineNumbers), false) => {
+                // Toggle the line numbers of the results, which doesn't change them.
+                self.show_line_numbers = !self.show_line_numbers;
+            }
+            (Some(Action::ToggleFilter), false) => {
+                // Switch between editing the filter and the search input.
+                self.filtering = !self.filtering;
+                self.navigating = false;
+            }
+            (Some(Action::ToggleNavigation), false) => {
+                // Switch between navigating the results and editing the input.
+                self.navigating = !self.navigating;
+                self.filtering = false;
+            }
+            (None, false) if self.navigating => {
+                // Jump to the result labeled with the digit.
+                let position = match key.code {
+                    KeyCode::Char(digit @ '1'..='9') if key.modifiers.is_empty() => {
+                        self.numbered.get(digit as usize - '1' as usize).copied()
+                    }
+                    _ => None,
+                };
+                if let Some(position) = position {
+                    self.state.select(Some(position));
+                    self.handle_selection_move();
+                }
+            }
+            (Some(Action::ClearQuery), false) if self.filtering => {
+                // Clear the filter.
+                self.filter = Input::default();
+                self.apply_filter();
+            }
+            (Some(Action::ClearQuery), false) => {
+                // Clear the search input and its results.
+                self.input = Input::default();
+                self.results.clear();
+                self.set_visible(Vec::new());
+                self.state = ListState::default();
+                self.max_results = self.config.max_results(self.picker.id());
+                self.visited.clear();
+                self.search();
+            }
+            (Some(Action::ResetSession), false) => {
+                // Start over, searching the empty query to replace any pending results.
+                self.reset();
+                self.search();
+            }
+            (Some(Action::RecentSearches), false) => {
+                // Open the recent searches menu.
+                let queries = self.history.ranked(self.picker.id());
+                if queries.is_empty() {
+                    self.status = Some("No recent searches".to_owned());
+                } else {
+                    self.menu = Some(QueryMenu {
+                        title: "Recent Searches",
+                        action: Action::RecentSearches,
+                        entries: queries
+                            .into_iter()
+                            .map(|query| (query.clone(), query))
+                            .collect(),
+                        selected: 0,
+                    });
+                }
+            }
+            (Some(Action::SavedQueries), false) => {
+                // Open the menu of the project's saved queries.
+                let queries = self.scratchpad.queries(self.picker.id());
+                if queries.is_empty() {
+                    self.status = Some("No saved queries".to_owned());
+                } else {
+                    self.menu = Some(QueryMenu {
+                        title: "Saved Queries",
+                        action: Action::SavedQueries,
+                        entries: queries
+                            .into_iter()
+                            .map(|(name, query)| (format!("{}: {}", name, query), query))
+                            .collect(),
+                        selected: 0,
+                    });
+                }
+            }
+            (Some(Action::SaveQuery), false) => {
+                // Ask for the name to save the query under.
+                if self.input.value().is_empty() {
+                    self.status = Some("No query to save".to_owned());
+                } else {
+                    self.naming = Some(Input::default());
+                }
+            }
+            (Some(Action::ExportWorkspace), false) => {
+                self.status = Some(if self.opened.is_empty() {
+                    "No opened files to export".to_owned()
+                } else {
+                    match self.export_workspace() {
+                        Ok(path) => format!(
+                            "Exported {} opened files to {}",
+                            self.opened.len(),
+                            path.display()
+                        ),
+                        Err(err) => format!("{:#}", err),
+                    }
+                });
+            }
+            (Some(Action::ToggleHelp), _) => {
                 // Toggle the help window.
                 self.show_help = !self.show_help;
+                self.help_scroll = 0;
             }
-            // Handle any other key event as search input.
-            (_, show_help) => {
-                if !show_help {
-                    self.input.handle_event(&Event::Key(key));
-                    self.picker
-                        .handle_input_change(self.input.value().to_owned(), tx);
+            (Some(Action::SelectPrevious | Action::ScrollPreviewUp), true) => {
+                // Scroll the help window up.
+                self.help_scroll = self.help_scroll.saturating_sub(1);
+            }
+            (Some(Action::SelectNext | Action::ScrollPreviewDown), true) => {
+                // Scroll the help window down, the last line staying visible.
+                self.help_scroll = (self.help_scroll + 1).min(tui::HELP_LINES as u16 - 1);
+            }
+            // Handle any other key event as filter input.
+            (_, false) if self.filtering => {
+                if self
+                    .filter
+                    .handle_event(&Event::Key(key))
+                    .is_some_and(|changed| changed.value)
+                {
+                    self.apply_filter();
+                }
+            }
+            // Let the picker handle its own actions, and handle any other key event as search input.
+            (action, false) => {
+                let selected = self.selected_index().map(|index| &self.results[index]);
+                // Destructive actions wait for the user's confirmation.
+                if let Some(pending) =
+                    action.and_then(|action| self.picker.confirm_action(action, selected))
+                {
+                    self.pending_confirm = Some(pending);
+                    return Ok(());
+                }
+                let handled =
+                    action.is_some_and(|action| self.picker.handle_action(action, selected));
+                let input_changed = !handled
+                    && self
+                        .input
+                        .handle_event(&Event::Key(key))
+                        .is_some_and(|changed| changed.value);
+                if input_changed {
+                    // A new query starts with the configured limit again.
+                    self.max_results = self.config.max_results(self.picker.id());
+                }
+                if handled || input_changed {
+                    self.search();
                 }
             }
+            (_, true) => {}
         }
 
         Ok(())
     }
 
-    /// Sets the current search results and resets the list offset.
+    /// Starts the selection animation (if enabled).
+    fn handle_selection_move(&mut self) {
+        if self.config.animations() {
+            self.selection_moved_at = Some(Instant::now());
+        }
+        if self.config.preview_update() == PreviewUpdate::OnIdle {
+            self.preview_deferred_until = Some(Instant::now() + PREVIEW_IDLE_DELAY);
+        }
+    }
+
+    /// Returns the preview of the selected item and the preview's title. Rendering
+    /// happens on every event (like key presses and search updates), so the preview is
+    /// only built again (e.g. reading its file) when the previewed item changes.
+    fn preview(&mut self) -> (Text<'static>, String) {
+        let key = self
+            .selected_item()
+            .filter(|_| self.show_preview)
+            .map(|item| PreviewKey {
+                item_key: item.key(),
+                diff: self.show_diff,
+                blame: self.show_blame,
+                context: self.show_context,
+            });
+        if let Some(cached) = self
+            .preview_cache
+            .as_ref()
+            .filter(|cached| cached.key == key)
+        {
+            return (cached.text.clone(), cached.title.clone());
+        }
+        // Keep the last preview until the selection stops moving.
+        if let Some(cached) = self
+            .preview_cache
+            .as_ref()
+            .filter(|_| self.preview_deferred_until.is_some())
+        {
+            return (cached.text.clone(), cached.title.clone());
+        }
+
+        let (text, title) = self.build_preview();
+        self.preview_cache = Some(CachedPreview {
+            key,
+            text: text.clone(),
+            title: title.clone(),
+        });
+        (text, title)
+    }
+
+    /// Builds the preview of the selected item and the preview's title.
+    /// In diff mode, items within a file are previewed as the diff of the changes
+    /// around them, falling back to the item's own preview if they weren't changed.
+    /// In blame mode, they're previewed with their context lines annotated with their
+    /// last change, falling back to the item's own preview outside git repositories.
+    fn build_preview(&mut self) -> (Text<'static>, String) {
+        let item = self.selected_item().filter(|_| self.show_preview).cloned();
+        let Some(item) = item else {
+            return (Text::default(), self.picker.preview_title().to_owned());
+        };
+
+        if self.show_diff {
+            if let Some(location) = item.location() {
+                let base = self.config.preview_diff_base();
+                if self.diffs.get(location.path).is_none() {
+                    let base_ref = base.to_owned();
+                    self.diffs.load(
+                        location.path,
+                        move |path| git::diff(path, &base_ref, 3),
+                        &self.loaded_tx,
+                        Loaded::Diff,
+                    );
+                }
+                match self.diffs.get(location.path) {
+                    Some(diff) => {
+                        if let Some(preview) = diff
+                            .as_deref()
+                            .and_then(|diff| git::diff_preview(diff, &location.lines))
+                        {
+                            return (
+                                self.format_preview(preview),
+                                format!("{} (diff {})", self.picker.preview_title(), base),
+                            );
+                        }
+                    }
+                    // Show the item's own preview while the diff is computed.
+                    None => {
+                        let preview = self.item_preview(&item).unwrap_or_default();
+                        return (
+                            self.format_preview(Text::from(preview)),
+                            format!("{} (loading diff {})", self.picker.preview_title(), base),
+                        );
+                    }
+                }
+            }
+        }
+
+        if self.show_blame {
+            if let Some(location) = item.location() {
+                let picker = self.picker.id();
+                let (before, after) = (
+                    self.config.context_before(picker),
+                    self.config.context_after(picker),
+                );
+                if self.blames.get(location.path).is_none() {
+                    self.blames
+                        .load(location.path, git::blame, &self.loaded_tx, Loaded::Blame);
+                }
+                match self.blames.get(location.path) {
+                    Some(blame) => {
+                        if let Some(preview) = blame.as_deref().and_then(|blame| {
+                            git::blame_preview(blame, &location.lines, before, after)
+                        }) {
+                            return (
+                                self.format_preview(preview),
+                                format!("{} (blame)", self.picker.preview_title()),
+                            );
+                        }
+                    }
+                    // Show the item's own preview while the blame is computed.
+                    None => {
+                        let preview = self.item_preview(&item).unwrap_or_default();
+                        return (
+                            self.format_preview(Text::from(preview)),
+                            format!("{} (loading blame)", self.picker.preview_title()),
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Some(preview) = item.compact_preview().filter(|_| !self.show_context) {
+            return (
+                self.format_preview(Text::from(preview)),
+                format!("{} (match only)", self.picker.preview_title()),
+            );
+        }
+
+        match self.item_preview(&item) {
+            Some(preview) => (
+                self.format_preview(Text::from(preview)),
+                self.picker.preview_title().to_owned(),
+            ),
+            None => (
+                Text::default(),
+                format!("{} (loading)", self.picker.preview_title()),
+            ),
+        }
+    }
+
+    /// Returns the item's own preview, or `None` while it's built in the background
+    /// (see [PickerItem::preview_loader]).
+    fn item_preview(&mut self, item: &I) -> Option<String> {
+        let Some(loader) = item.preview_loader(self.config) else {
+            return Some(item.preview(self.config));
+        };
+        // Previews are cached by file, or by item for the ones without a file (like the
+        // output of a command).
+        let key = item
+            .location()
+            .map_or_else(|| item.key(), |location| location.path.to_owned());
+        if self.previews.get(&key).is_none() {
+            self.previews.load(
+                &key,
+                move |_| Some(loader()),
+                &self.loaded_tx,
+                Loaded::Preview,
+            );
+        }
+        self.previews
+            .get(&key)
+            .map(|preview| preview.clone().unwrap_or_default())
+    }
+
+    /// Caches the data computed in the background, previewing it if it's for the
+    /// selected item's file.
+    fn handle_loaded(&mut self, loaded: Loaded) {
+        match loaded {
+            Loaded::Diff(diff) => self.diffs.insert(diff),
+            Loaded::Blame(blame) => self.blames.insert(blame),
+            Loaded::Preview(preview) => self.previews.insert(preview),
+        }
+        self.preview_cache = None;
+    }
+
+    /// Watches the selected item's file when refreshing previews, replacing the watcher
+    /// of the previously selected one.
+    fn watch_selected_file(&mut self) {
+        if !self.config.preview_refresh() {
+            return;
+        }
+        let path = self
+            .selected_item()
+            .filter(|_| self.show_preview)
+            .and_then(|item| Some(item.location()?.path.to_owned()));
+        if self.preview_watcher.as_ref().map(|(watched, _)| watched) != path.as_ref() {
+            self.preview_watcher = path.map(|path| {
+                let watcher = Watcher::file(self.cwd.join(&path));
+                (path, watcher)
+            });
+        }
+    }
+
+    /// Refreshes the preview after the selected item's file changed. Previews taken from
+    /// the search (like grep's context) are refreshed by searching again.
+    fn refresh_preview(&mut self) {
+        self.preview_cache = None;
+        if let Some((path, _)) = &self.preview_watcher {
+            self.diffs.remove(path);
+            self.previews.remove(path);
+            self.blames.remove(path);
+        }
+        if !self.input.value().is_empty() {
+            self.search();
+        }
+    }
+
+    /// Returns the preview scroll offset of the selected item, if any.
+    fn preview_scroll_mut(&mut self) -> Option<&mut u16> {
+        let key = self.selected_item()?.key();
+        Some(self.preview_scrolls.entry(key).or_default())
+    }
+
+    /// Returns the preview scroll offset of the selected item, making sure that
+    /// the last line of its preview (with the given number of lines) stays visible.
+    fn clamp_preview_scroll(&mut self, lines: usize) -> u16 {
+        let max = u16::try_from(lines.saturating_sub(1)).unwrap_or(u16::MAX);
+        let key = self.selected_item().map(|item| item.key());
+        key.and_then(|key| self.preview_scrolls.get_mut(&key))
+            .map_or(0, |scroll| {
+                *scroll = (*scroll).min(max);
+                *scroll
+            })
+    }
+
+    /// Expands the tabs in the preview and renders whitespace according to the configuration.
+    fn format_preview(&self, preview: Text<'_>) -> Text<'static> {
+        tui::expand_whitespace(
+            preview,
+            self.config.preview_tab_width(),
+            self.config.preview_render_whitespace(),
+        )
+    }
+
+    /// Sets the current search results. When the results are for the same query as the
+    /// current ones (e.g. the search was re-run after toggling a mode) the selected item
+    /// is preserved if possible, otherwise the first item (or the best scoring one, when
+    /// configured) is selected.
     fn handle_results(&mut self, results: Vec<I>) {
+        // Files might have changed since the last search. Blames are kept until their
+        // file is modified.
+        self.diffs.clear();
+        self.preview_cache = None;
+
+        let query = self
+            .searched_query
+            .clone()
+            .unwrap_or_else(|| self.input.value().to_owned());
+        let same_query = self.results_query.as_ref() == Some(&query);
+        if !same_query {
+            self.preview_scrolls.clear();
+        }
+        let selected_key = self
+            .selected_item()
+            .filter(|_| same_query && self.config.preserve_selection())
+            .map(|item| item.key());
         self.results = results;
-        self.state = ListState::default().with_selected(if self.results.is_empty() {
-            None
-        } else {
-            Some(0)
-        });
+        self.results_query = Some(query);
+        self.set_visible(self.filtered_indices());
+
+        let first = (!self.visible.is_empty()).then(|| self.default_selection());
+        let selected = match selected_key {
+            Some(key) => self
+                .visible
+                .iter()
+                .position(|index| self.results[*index].key() == key)
+                .or(first),
+            None => first,
+        };
+        let offset = if same_query { self.state.offset() } else { 0 };
+        self.state = ListState::default()
+            .with_offset(offset)
+            .with_selected(selected);
+    }
+
+    /// Warns in the status line when the results, including those left out by the limit,
+    /// exceed the configured threshold, which often means the query is a mistake.
+    fn warn_broad_query(&mut self) {
+        let count = self.results.len() + self.truncated.unwrap_or(0);
+        if self
+            .config
+            .warn_results(self.picker.id())
+            .is_some_and(|threshold| count > threshold)
+        {
+            self.status = Some(format!(
+                "Broad query: {} results — consider narrowing it",
+                group_digits(count)
+            ));
+        }
+    }
+
+    /// Returns the position of the visible result selected by default: the best scoring
+    /// one when configured (the first of them on ties), otherwise the first one.
+    fn default_selection(&self) -> usize {
+        if !self.config.select_best() {
+            return 0;
+        }
+        self.visible
+            .iter()
+            .enumerate()
+            .filter_map(|(i, index)| Some((self.results[*index].score()?, i)))
+            .max_by_key(|(score, i)| (*score, Reverse(*i)))
+            .map_or(0, |(_, i)| i)
+    }
+
+    /// Returns the indices of the results that match the filter.
+    fn filtered_indices(&self) -> Vec<usize> {
+        let (filter, case) = (self.filter.value(), self.picker.case());
+        (0..self.results.len())
+            .filter(|index| filter.is_empty() || self.results[*index].matches_filter(filter, case))
+            .collect()
+    }
+
+    /// Replaces the displayed results.
+    fn set_visible(&mut self, visible: Vec<usize>) {
+        self.visible = visible;
+        self.visible_revision += 1;
+    }
+
+    /// Filters the results after the filter changed, selecting the first (or best) match.
+    fn apply_filter(&mut self) {
+        self.set_visible(self.filtered_indices());
+        self.state = ListState::default()
+            .with_selected((!self.visible.is_empty()).then(|| self.default_selection()));
+    }
+}
+
+/// Formats the number with commas separating groups of thousands.
+fn group_digits(number: usize) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Records the item's file among the opened ones, unless it already is.
+fn record_opened<I: PickerItem>(opened: &mut Vec<String>, item: &I) {
+    if let Some(location) = item.location() {
+        if !opened.iter().any(|path| path == location.path) {
+            opened.push(location.path.to_owned());
+        }
+    }
+}
+
+/// Returns a notice for the user if the selected item's file is read-only.
+fn read_only_notice<I: PickerItem>(config: &Config, item: &I) -> Option<String> {
+    open::read_only_notice(config, item.location()?.path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+    use ratatui::{backend::TestBackend, widgets::ListItem};
+    use std::{
+        cell::Cell,
+        iter,
+        sync::{Arc, Mutex},
+    };
+
+    thread_local! {
+        /// How many list items were rendered on the test's thread.
+        static RENDERED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct MockItem(String);
+
+    impl PickerItem for MockItem {
+        fn as_list_item(&self, _config: &Config, _style: ListStyle) -> ListItem<'static> {
+            RENDERED.with(|rendered| rendered.set(rendered.get() + 1));
+            ListItem::new(self.0.clone())
+        }
+
+        fn preview(&self, _config: &Config) -> String {
+            self.0.clone()
+        }
+
+        fn compact_preview(&self) -> Option<String> {
+            Some(self.0.to_uppercase())
+        }
+
+        fn text(&self) -> String {
+            self.0.clone()
+        }
+
+        fn key(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    /// A picker returning one result per character of the query, recording the
+    /// selected items and the ones removed after confirming [Action::ToggleExact].
+    #[derive(Default)]
+    struct MockPicker {
+        selected: Arc<Mutex<Vec<MockItem>>>,
+        removed: Arc<Mutex<Vec<MockItem>>>,
+    }
+
+    impl Picker<MockItem> for MockPicker {
+        fn id(&self) -> &'static str {
+            "mock"
+        }
+
+        fn name(&self) -> &'static str {
+            "Mock"
+        }
+
+        fn preview_title(&self) -> &'static str {
+            "Mock Preview"
+        }
+
+        fn handle_input_change(
+            &mut self,
+            context: SearchContext,
+            sender: Sender<PickerEvent<MockItem>>,
+        ) {
+            let results = context
+                .query
+                .chars()
+                .map(|c| MockItem(c.to_string()))
+                .collect();
+            sender.try_send(PickerEvent::Results(results)).unwrap();
+        }
+
+        fn handle_selection(&self, item: &MockItem, _config: &Config) -> Result<()> {
+            self.selected.lock().unwrap().push(item.clone());
+            Ok(())
+        }
+
+        fn confirm_action(
+            &mut self,
+            action: Action,
+            selected: Option<&MockItem>,
+        ) -> Option<PendingAction> {
+            let item = selected.filter(|_| action == Action::ToggleExact)?.clone();
+            let removed = Arc::clone(&self.removed);
+            Some(PendingAction::new(
+                format!("Remove {}?", item.0),
+                move || {
+                    removed.lock().unwrap().push(item);
+                    Ok(())
+                },
+            ))
+        }
+    }
+
+    fn key(code: KeyCode) -> io::Result<Event> {
+        Ok(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+    }
+
+    #[tokio::test]
+    async fn selects_a_result_from_key_presses() {
+        let config = Config::default();
+        let picker = MockPicker::default();
+        let selected = Arc::clone(&picker.selected);
+        let mut app = App::new(picker, &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('a')),
+            key(KeyCode::Char('b')),
+            key(KeyCode::Down),
+            key(KeyCode::Enter),
+        ]);
+        let selection = app.run_with_events(&mut tui, events).await.unwrap();
+
+        assert!(selection.is_empty());
+        assert_eq!(app.input.value(), "ab");
+        assert_eq!(app.results, [MockItem("a".into()), MockItem("b".into())]);
+        assert_eq!(*selected.lock().unwrap(), [MockItem("b".into())]);
+    }
+
+    #[tokio::test]
+    async fn returns_the_selected_result() {
+        let config = Config::default();
+        let picker = MockPicker::default();
+        let selected = Arc::clone(&picker.selected);
+        let mut app = App::new(picker, &config).unwrap().return_selection(true);
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('x')),
+            key(KeyCode::Char('y')),
+            key(KeyCode::Down),
+            key(KeyCode::Enter),
+        ]);
+        let selection = app.run_with_events(&mut tui, events).await.unwrap();
+
+        assert_eq!(selection, [MockItem("y".into())]);
+        assert!(selected.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn returns_the_marked_results() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config)
+            .unwrap()
+            .return_selection(true);
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('x')),
+            key(KeyCode::Char('y')),
+            key(KeyCode::Char('z')),
+            key(KeyCode::Tab),
+            key(KeyCode::Down),
+            key(KeyCode::Down),
+            key(KeyCode::Tab),
+            key(KeyCode::Up),
+            key(KeyCode::Enter),
+        ]);
+        let selection = app.run_with_events(&mut tui, events).await.unwrap();
+
+        assert_eq!(selection, [MockItem("x".into()), MockItem("z".into())]);
+    }
+
+    #[tokio::test]
+    async fn confirms_quitting_with_marked_results() {
+        let config: Config = serde_json::from_str(r#"{ "confirm_quit": true }"#).unwrap();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('x')),
+            key(KeyCode::Tab),
+            key(KeyCode::Esc),
+            key(KeyCode::Char('n')),
+        ]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        assert_eq!(app.status.as_deref(), Some("Cancelled"));
+        assert!(!app.confirming_quit);
+
+        let events = tokio_stream::iter([key(KeyCode::Esc), key(KeyCode::Char('y'))]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        // Quitting left the input untouched, unlike another key press.
+        assert_eq!(app.input.value(), "x");
+        assert_eq!(app.marks, [MockItem("x".into())]);
+
+        // Quitting without marks doesn't ask.
+        app.marks.clear();
+        let events = tokio_stream::iter([key(KeyCode::Esc), key(KeyCode::Char('y'))]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        assert!(!app.confirming_quit);
+        assert_eq!(app.input.value(), "x");
+    }
+
+    #[tokio::test]
+    async fn confirms_picker_actions() {
+        let config = Config::default();
+        let picker = MockPicker::default();
+        let removed = Arc::clone(&picker.removed);
+        let mut app = App::new(picker, &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+        let remove = || {
+            Ok(Event::Key(KeyEvent::new(
+                KeyCode::Char('x'),
+                KeyModifiers::ALT,
+            )))
+        };
+
+        // Other keys keep waiting for an answer.
+        let events =
+            tokio_stream::iter([key(KeyCode::Char('a')), remove(), key(KeyCode::Char('x'))]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        assert_eq!(
+            app.pending_confirm.as_ref().map(PendingAction::prompt),
+            Some("Remove a?")
+        );
+
+        let events = tokio_stream::iter([key(KeyCode::Char('n'))]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        assert!(app.pending_confirm.is_none());
+        assert_eq!(app.status.as_deref(), Some("Cancelled"));
+        assert!(removed.lock().unwrap().is_empty());
+
+        let events = tokio_stream::iter([remove(), key(KeyCode::Char('y'))]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        assert_eq!(*removed.lock().unwrap(), [MockItem("a".into())]);
+        // The answers weren't typed in the input.
+        assert_eq!(app.input.value(), "a");
+    }
+
+    #[tokio::test]
+    async fn reuses_the_rendered_results() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([key(KeyCode::Char('a')), key(KeyCode::Char('b'))]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        RENDERED.with(|rendered| rendered.set(0));
+
+        // Moving the selection doesn't render the results again.
+        let events = tokio_stream::iter([key(KeyCode::Down), key(KeyCode::Up), key(KeyCode::Down)]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        assert_eq!(RENDERED.with(Cell::get), 0);
+
+        // Changing their style does, once.
+        let events = tokio_stream::iter([
+            Ok(Event::Key(KeyEvent::new(
+                KeyCode::Char('n'),
+                KeyModifiers::ALT,
+            ))),
+            key(KeyCode::Up),
+        ]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+        assert_eq!(RENDERED.with(Cell::get), 2);
+    }
+
+    #[tokio::test]
+    async fn scrolls_the_help() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter(
+            iter::once(key(KeyCode::Char('?')))
+                .chain(iter::repeat_with(|| key(KeyCode::Down)).take(tui::HELP_LINES + 5))
+                .chain([key(KeyCode::Up)]),
+        );
+        app.run_with_events(&mut tui, events).await.unwrap();
+
+        assert!(app.show_help);
+        assert_eq!(app.help_scroll as usize, tui::HELP_LINES - 2);
+        assert!(app.input.value().is_empty());
+    }
+
+    #[tokio::test]
+    async fn names_the_saved_query() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('a')),
+            Ok(Event::Key(KeyEvent::new(
+                KeyCode::Char('w'),
+                KeyModifiers::ALT,
+            ))),
+            key(KeyCode::Char('x')),
+            key(KeyCode::Enter),
+        ]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+
+        // The name isn't typed in the search input, and saving fails without a
+        // configuration directory.
+        assert!(app.naming.is_none());
+        assert_eq!(app.input.value(), "a");
+        assert_eq!(
+            app.status(),
+            Some("Failed to save the query: no configuration directory")
+        );
+    }
+
+    #[tokio::test]
+    async fn resets_the_session() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('a')),
+            key(KeyCode::Char('b')),
+            key(KeyCode::Tab),
+            Ok(Event::Key(KeyEvent::new(
+                KeyCode::Char('f'),
+                KeyModifiers::CONTROL,
+            ))),
+            key(KeyCode::Char('b')),
+            Ok(Event::Key(KeyEvent::new(
+                KeyCode::Char('k'),
+                KeyModifiers::ALT,
+            ))),
+        ]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+
+        assert!(app.input.value().is_empty());
+        assert!(app.results.is_empty());
+        assert!(app.marks.is_empty());
+        assert!(!app.filtering);
+        assert!(app.filter.value().is_empty());
+    }
+
+    #[test]
+    fn delays_the_searching_spinner() {
+        let config: Config =
+            serde_json::from_str(r#"{ "spinner_delay_ms": 100, "animations": true }"#).unwrap();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        let started_at = Instant::now();
+        assert_eq!(app.spinner(started_at), (None, None));
+
+        // Fast searches never show the spinner, which then animates once shown.
+        app.search_started_at = Some(started_at);
+        let shown_at = started_at + Duration::from_millis(100);
+        assert_eq!(app.spinner(started_at), (None, Some(shown_at)));
+        assert_eq!(
+            app.spinner(shown_at + Duration::from_millis(170)),
+            (Some(2), Some(shown_at + Duration::from_millis(240)))
+        );
+
+        app.handle_picker_event(PickerEvent::Results(Vec::new()));
+        assert_eq!(app.spinner(shown_at), (None, None));
+    }
+
+    #[test]
+    fn defers_the_preview_while_moving() {
+        let config: Config =
+            serde_json::from_str(r#"{ "preview": { "update": "on_idle" } }"#).unwrap();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        app.handle_picker_event(PickerEvent::Results(vec![
+            MockItem("a".into()),
+            MockItem("b".into()),
+        ]));
+        assert_eq!(app.preview().0, Text::from("a"));
+
+        app.state.select(Some(1));
+        app.handle_selection_move();
+        assert_eq!(app.preview().0, Text::from("a"));
+        app.preview_deferred_until = None;
+        assert_eq!(app.preview().0, Text::from("b"));
+    }
+
+    #[test]
+    fn toggles_the_preview_context() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        app.handle_picker_event(PickerEvent::Results(vec![MockItem("a".into())]));
+        let (tx, _rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let toggle = KeyEvent::new(KeyCode::Char('t'), KeyModifiers::ALT);
+
+        app.handle_key_event(toggle, tx.clone()).unwrap();
+        let (preview, title) = app.preview();
+        assert_eq!(preview, Text::from("A"));
+        assert_eq!(title, "Mock Preview (match only)");
+
+        app.handle_key_event(toggle, tx).unwrap();
+        assert_eq!(app.preview(), (Text::from("a"), "Mock Preview".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn jumps_to_numbered_results() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('a')),
+            key(KeyCode::Char('b')),
+            key(KeyCode::Char('c')),
+            Ok(Event::Key(KeyEvent::new(
+                KeyCode::Char('j'),
+                KeyModifiers::ALT,
+            ))),
+            key(KeyCode::Char('3')),
+            key(KeyCode::Char('9')),
+            key(KeyCode::Esc),
+        ]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+
+        // Digits don't edit the query, and ones without a result are ignored.
+        assert_eq!(app.input.value(), "abc");
+        assert_eq!(app.state.selected(), Some(2));
+        assert!(!app.navigating);
+    }
+
+    #[tokio::test]
+    async fn accepts_the_filtered_result() {
+        let config = Config::default();
+        let picker = MockPicker::default();
+        let selected = Arc::clone(&picker.selected);
+        let mut app = App::new(picker, &config).unwrap();
+        let mut tui = Tui::with_backend(TestBackend::new(80, 24), &config).unwrap();
+
+        let events = tokio_stream::iter([
+            key(KeyCode::Char('a')),
+            key(KeyCode::Char('b')),
+            Ok(Event::Key(KeyEvent::new(
+                KeyCode::Char('f'),
+                KeyModifiers::CONTROL,
+            ))),
+            key(KeyCode::Char('b')),
+            key(KeyCode::Enter),
+        ]);
+        app.run_with_events(&mut tui, events).await.unwrap();
+
+        assert_eq!(*selected.lock().unwrap(), [MockItem("b".into())]);
+        assert!(!app.filtering);
+        assert_eq!(app.filter.value(), "b");
+        assert_eq!(app.input.value(), "ab");
+    }
+
+    #[tokio::test]
+    async fn searches_the_initial_query_without_ui() {
+        let config = Config::default();
+        let picker = MockPicker::default();
+        let selected = Arc::clone(&picker.selected);
+        let mut app = App::new(picker, &config).unwrap().query("z");
+
+        assert_eq!(app.search_initial().await, 1);
+        assert!(app.accept_selection().unwrap().is_none());
+        assert_eq!(*selected.lock().unwrap(), [MockItem("z".into())]);
+    }
+
+    #[tokio::test]
+    async fn warns_about_broad_queries() {
+        let config: Config = serde_json::from_str(r#"{ "warn_results": 2 }"#).unwrap();
+        let mut app = App::new(MockPicker::default(), &config)
+            .unwrap()
+            .query("ab");
+
+        assert_eq!(app.search_initial().await, 2);
+        assert!(app.status().is_none());
+
+        let mut app = App::new(MockPicker::default(), &config)
+            .unwrap()
+            .query("abc");
+        assert_eq!(app.search_initial().await, 3);
+        assert_eq!(
+            app.status(),
+            Some("Broad query: 3 results — consider narrowing it")
+        );
+        assert_eq!(group_digits(12_431), "12,431");
+        assert_eq!(group_digits(1_234_567), "1,234,567");
+        assert_eq!(group_digits(999), "999");
+    }
+
+    #[tokio::test]
+    async fn drops_the_results_of_previous_searches() {
+        let config = Config::default();
+        let mut app = App::new(MockPicker::default(), &config)
+            .unwrap()
+            .query("ab");
+
+        // Search again before the first search's results are handled, and keep typing.
+        app.search();
+        app.input = Input::new("abc".to_owned());
+        app.search();
+        app.input = Input::new("ab".to_owned());
+        while let Some(event) = app.next_search_event().await {
+            app.handle_picker_event(event);
+        }
+
+        assert_eq!(app.results.len(), 3);
+        // The results are for the query searched, not the one typed since.
+        assert_eq!(app.results_query.as_deref(), Some("abc"));
     }
 }
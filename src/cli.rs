@@ -0,0 +1,304 @@
+use binocular::CaseMatching;
+use clap::{Parser, ValueEnum};
+use std::{env, path::PathBuf};
+
+/// Binocular vision for your terminal.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Path to the configuration file, instead of the one in the OS configuration directory.
+    /// Defaults to the `BINOCULAR_CONFIG` environment variable, unless `--no-config` is
+    /// given.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Ignore any configuration file and use the defaults.
+    #[arg(long, conflicts_with = "config")]
+    pub no_config: bool,
+
+    /// The picker to start with.
+    #[arg(long, value_enum, default_value_t = PickerKind::Grep)]
+    pub picker: PickerKind,
+
+    /// Start with the command picker of the given name, defined in the configuration.
+    #[arg(long, value_name = "NAME", conflicts_with = "picker")]
+    pub command: Option<String>,
+
+    /// Start with the given search query.
+    #[arg(long, short, value_name = "QUERY")]
+    pub query: Option<String>,
+
+    /// Act on the only result of the initial query without showing the UI, if there's
+    /// exactly one.
+    #[arg(long = "select-1")]
+    pub select_1: bool,
+
+    /// Exit with code 1 without showing the UI when the initial query has no results,
+    /// printing the reason (like an invalid regex) if there's one.
+    #[arg(long = "exit-0")]
+    pub exit_0: bool,
+
+    /// Search the query again whenever files in the current directory change.
+    #[arg(long)]
+    pub watch: bool,
+
+    /// File the search starts from, which enables searching only that file. Can instead
+    /// be a URI like `grep://TODO?case=sensitive&scope=src`, naming the picker to start
+    /// with, its query and its options.
+    #[arg(value_name = "FILE", value_parser = Target::parse)]
+    pub file: Option<Target>,
+
+    /// Print the paths of the marked files (or else the selected file's) on exit, each
+    /// followed by a null byte, instead of opening them.
+    #[arg(long)]
+    pub print0: bool,
+
+    /// Print the final query on exit, before the selection (if printed), followed by a
+    /// newline (or a null byte with `--print0`).
+    #[arg(long)]
+    pub print_query: bool,
+
+    /// Export the files opened during the session to the file on exit (and with the
+    /// export key binding): a VS Code workspace for a `.code-workspace` file, otherwise
+    /// a plain list of paths.
+    #[arg(long, value_name = "PATH")]
+    pub export_workspace: Option<PathBuf>,
+
+    /// Check the configuration files and exit, reporting any problem.
+    #[arg(long)]
+    pub check_config: bool,
+
+    /// Print debugging information, and show the search flags in the input title.
+    #[arg(long)]
+    pub debug: bool,
+}
+
+impl Args {
+    /// Uses the configuration file from the environment if none was given, unless the
+    /// configuration is ignored.
+    pub fn apply_env(&mut self) {
+        if self.config.is_none() && !self.no_config {
+            self.config = env::var_os("BINOCULAR_CONFIG").map(PathBuf::from);
+        }
+    }
+
+    /// Applies the picker, query and flags of the launch URI, if one was given instead of a
+    /// file, returning it for its picker options.
+    pub fn apply_launch(&mut self) -> Option<Launch> {
+        let Some(Target::Launch(launch)) = &self.file else {
+            return None;
+        };
+        let launch = launch.clone();
+        self.file = None;
+        if let Some(picker) = launch.picker {
+            self.picker = picker;
+        }
+        if !launch.query.is_empty() {
+            self.query = Some(launch.query.clone());
+        }
+        self.watch |= launch.watch;
+        self.select_1 |= launch.select_1;
+        self.exit_0 |= launch.exit_0;
+        Some(launch)
+    }
+}
+
+/// The available pickers.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PickerKind {
+    /// Search the contents of files.
+    Grep,
+    /// Find files by name.
+    Files,
+    /// Switch between the buffers open in an editor.
+    Buffers,
+}
+
+/// The positional argument: either a file or a launch URI.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    File(PathBuf),
+    Launch(Launch),
+}
+
+impl Target {
+    /// Parses the argument as a launch URI if it has a scheme, and as a file otherwise.
+    fn parse(arg: &str) -> Result<Self, String> {
+        if arg.contains("://") {
+            Launch::parse(arg).map(Self::Launch)
+        } else {
+            Ok(Self::File(PathBuf::from(arg)))
+        }
+    }
+}
+
+/// A picker, query and options to start with, given as `<picker>://<query>?<options>`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Launch {
+    pub picker: Option<PickerKind>,
+    pub query: String,
+    /// How the grep picker matches the case of the query.
+    pub case: Option<CaseMatching>,
+    /// The directory the grep picker searches.
+    pub scope: Option<PathBuf>,
+    pub watch: bool,
+    pub select_1: bool,
+    pub exit_0: bool,
+}
+
+impl Launch {
+    /// Parses a launch URI, like `grep://TODO?case=sensitive&scope=src`.
+    fn parse(uri: &str) -> Result<Self, String> {
+        let (scheme, rest) = uri.split_once("://").unwrap_or((uri, ""));
+        let picker = PickerKind::from_str(scheme, true).map_err(|_| {
+            format!(
+                "Unknown picker `{}`, expected one of: {}",
+                scheme,
+                picker_names().join(", ")
+            )
+        })?;
+        let (query, options) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut launch = Self {
+            picker: Some(picker),
+            query: decode(query)?,
+            ..Self::default()
+        };
+
+        for option in options.split('&').filter(|option| !option.is_empty()) {
+            let (name, value) = option.split_once('=').unwrap_or((option, ""));
+            let value = decode(value)?;
+            match name {
+                "case" => {
+                    launch.case = Some(match value.as_str() {
+                        "smart" => CaseMatching::Smart,
+                        "sensitive" => CaseMatching::Sensitive,
+                        "insensitive" => CaseMatching::Insensitive,
+                        _ => {
+                            return Err(format!(
+                                "Invalid case `{}`, expected one of: smart, sensitive, insensitive",
+                                value
+                            ))
+                        }
+                    })
+                }
+                "scope" if !value.is_empty() => launch.scope = Some(PathBuf::from(value)),
+                "scope" => return Err("The `scope` option needs a directory".to_owned()),
+                "watch" => launch.watch = flag(name, &value)?,
+                "select-1" => launch.select_1 = flag(name, &value)?,
+                "exit-0" => launch.exit_0 = flag(name, &value)?,
+                _ => {
+                    return Err(format!(
+                    "Unknown option `{}`, expected one of: case, scope, watch, select-1, exit-0",
+                    name
+                ))
+                }
+            }
+        }
+
+        if !matches!(picker, PickerKind::Grep) && (launch.case.is_some() || launch.scope.is_some())
+        {
+            return Err(format!(
+                "The `case` and `scope` options are only supported by the grep picker, not `{}`",
+                scheme
+            ));
+        }
+        Ok(launch)
+    }
+}
+
+/// Returns the names of the pickers, as given on the command line.
+fn picker_names() -> Vec<String> {
+    PickerKind::value_variants()
+        .iter()
+        .filter_map(|kind| kind.to_possible_value())
+        .map(|value| value.get_name().to_owned())
+        .collect()
+}
+
+/// Parses the value of a boolean option, which is true when omitted.
+fn flag(name: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "" | "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!(
+            "Invalid value `{}` for `{}`, expected true or false",
+            value, name
+        )),
+    }
+}
+
+/// Decodes the percent-encoded characters of a URI component, and `+` as a space.
+fn decode(component: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(component.len());
+    let mut rest = component.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match byte {
+            b'%' => {
+                let hex = tail
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("Invalid percent-encoding in `{}`", component))?;
+                bytes.push(hex);
+                rest = &tail[2..];
+                continue;
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+        rest = tail;
+    }
+    String::from_utf8(bytes).map_err(|_| format!("Invalid UTF-8 in `{}`", component))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_launch_uris() {
+        assert_eq!(
+            Target::parse("grep://TODO%20fix?case=sensitive&scope=src&watch"),
+            Ok(Target::Launch(Launch {
+                picker: Some(PickerKind::Grep),
+                query: "TODO fix".to_owned(),
+                case: Some(CaseMatching::Sensitive),
+                scope: Some(PathBuf::from("src")),
+                watch: true,
+                ..Launch::default()
+            }))
+        );
+        assert_eq!(
+            Target::parse("files://main+rs?select-1=false"),
+            Ok(Target::Launch(Launch {
+                picker: Some(PickerKind::Files),
+                query: "main rs".to_owned(),
+                ..Launch::default()
+            }))
+        );
+        assert_eq!(
+            Target::parse("src/main.rs"),
+            Ok(Target::File(PathBuf::from("src/main.rs")))
+        );
+
+        assert!(Target::parse("ftp://x")
+            .unwrap_err()
+            .contains("Unknown picker `ftp`"));
+        assert!(Target::parse("grep://x?depth=2")
+            .unwrap_err()
+            .contains("Unknown option `depth`"));
+        assert!(Target::parse("grep://x?case=upper")
+            .unwrap_err()
+            .contains("Invalid case `upper`"));
+        assert!(Target::parse("files://x?case=smart")
+            .unwrap_err()
+            .contains("only supported by the grep picker"));
+        assert!(Target::parse("grep://%zz").is_err());
+    }
+
+    #[test]
+    fn rejects_conflicting_config_flags() {
+        assert!(Args::try_parse_from(["binocular", "--no-config", "--config", "x.json"]).is_err());
+        assert!(Args::try_parse_from(["binocular", "--no-config"]).is_ok());
+    }
+}
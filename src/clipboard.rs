@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Context, Result};
+use std::{
+    env,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Copies the text to the system clipboard, using the platform's clipboard command.
+pub(crate) fn copy(text: &str) -> Result<()> {
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("pbcopy", &[])
+    } else if cfg!(windows) {
+        ("clip", &[])
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wl-copy", &[])
+    } else {
+        ("xclip", &["-selection", "clipboard"])
+    };
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run `{}`", program))?;
+    child
+        .stdin
+        .take()
+        .context("Failed to write to the clipboard")?
+        .write_all(text.as_bytes())
+        .context("Failed to write to the clipboard")?;
+
+    let status = child.wait().context("Failed to write to the clipboard")?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("`{}` failed to copy to the clipboard", program))
+    }
+}
@@ -0,0 +1,173 @@
+use ratatui::style::Color;
+use std::{env, sync::OnceLock};
+
+/// The colors supported by the terminal.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum ColorSupport {
+    /// No colors, either because the terminal doesn't support them or because the
+    /// user disabled them with `NO_COLOR`.
+    None,
+    /// The 16 standard ANSI colors.
+    Ansi16,
+    /// The 256 indexed colors.
+    Ansi256,
+    /// 24-bit RGB colors.
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Detects the colors supported by the terminal from the environment.
+    fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+
+        // See https://no-color.org.
+        if !var("NO_COLOR").is_empty() || var("TERM") == "dumb" {
+            Self::None
+        } else if matches!(var("COLORTERM").as_str(), "truecolor" | "24bit") {
+            Self::TrueColor
+        } else if var("TERM").contains("256") {
+            Self::Ansi256
+        } else {
+            Self::Ansi16
+        }
+    }
+
+    /// Returns the (cached) colors supported by the terminal.
+    fn get() -> Self {
+        static SUPPORT: OnceLock<ColorSupport> = OnceLock::new();
+        *SUPPORT.get_or_init(Self::detect)
+    }
+}
+
+/// Returns whether colors are disabled.
+pub(crate) fn no_color() -> bool {
+    ColorSupport::get() == ColorSupport::None
+}
+
+/// Adapts the color to what the terminal supports, approximating it with the closest
+/// supported color. Returns [Color::Reset] when colors are disabled.
+pub(crate) fn terminal_color(color: Color) -> Color {
+    let support = ColorSupport::get();
+    match color {
+        _ if support == ColorSupport::None => Color::Reset,
+        Color::Rgb(r, g, b) if support == ColorSupport::Ansi256 => rgb_to_ansi256(r, g, b),
+        Color::Rgb(r, g, b) if support == ColorSupport::Ansi16 => rgb_to_ansi16(r, g, b),
+        Color::Indexed(i) if i >= 16 && support == ColorSupport::Ansi16 => {
+            let (r, g, b) = ansi256_to_rgb(i);
+            rgb_to_ansi16(r, g, b)
+        }
+        color => color,
+    }
+}
+
+/// The levels of each channel in the 6x6x6 color cube of the 256 colors.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The 16 standard ANSI colors with their (typical) RGB values.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (128, 0, 0)),
+    (Color::Green, (0, 128, 0)),
+    (Color::Yellow, (128, 128, 0)),
+    (Color::Blue, (0, 0, 128)),
+    (Color::Magenta, (128, 0, 128)),
+    (Color::Cyan, (0, 128, 128)),
+    (Color::Gray, (192, 192, 192)),
+    (Color::DarkGray, (128, 128, 128)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (0, 0, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Returns the squared distance between two RGB colors.
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// Approximates an RGB color with the closest of the 256 indexed colors, considering
+/// both the color cube and the grayscale ramp.
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> Color {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+
+    let average = ((r as u16 + g as u16 + b as u16) / 3) as u8;
+    let gray = 232 + (average.saturating_sub(8) / 10).min(23);
+
+    let closest = [cube, gray]
+        .into_iter()
+        .min_by_key(|&i| distance(ansi256_to_rgb(i), (r, g, b)))
+        .unwrap_or(cube);
+    Color::Indexed(closest)
+}
+
+/// Approximates an RGB color with the closest of the 16 standard colors.
+fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> Color {
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+/// Returns the RGB value of one of the 256 indexed colors.
+fn ansi256_to_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => ANSI16[i as usize].1,
+        16..=231 => {
+            let i = i - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + 10 * (i - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_indexed_colors_to_rgb() {
+        assert_eq!(ansi256_to_rgb(1), (128, 0, 0));
+        assert_eq!(ansi256_to_rgb(16), (0, 0, 0));
+        assert_eq!(ansi256_to_rgb(196), (255, 0, 0));
+        assert_eq!(ansi256_to_rgb(110), (135, 175, 215));
+        assert_eq!(ansi256_to_rgb(232), (8, 8, 8));
+        assert_eq!(ansi256_to_rgb(255), (238, 238, 238));
+    }
+
+    #[test]
+    fn approximates_rgb_with_indexed_colors() {
+        assert_eq!(rgb_to_ansi256(255, 0, 0), Color::Indexed(196));
+        assert_eq!(rgb_to_ansi256(135, 175, 215), Color::Indexed(110));
+        // Grays are closer to the grayscale ramp than to the cube.
+        assert_eq!(rgb_to_ansi256(128, 128, 128), Color::Indexed(244));
+        // Every indexed color of the cube and the ramp maps back to itself.
+        for i in 16..=255 {
+            let (r, g, b) = ansi256_to_rgb(i);
+            assert_eq!(rgb_to_ansi256(r, g, b), Color::Indexed(i));
+        }
+    }
+
+    #[test]
+    fn approximates_rgb_with_standard_colors() {
+        assert_eq!(rgb_to_ansi16(250, 10, 10), Color::LightRed);
+        assert_eq!(rgb_to_ansi16(100, 0, 0), Color::Red);
+        assert_eq!(rgb_to_ansi16(190, 190, 200), Color::Gray);
+        assert_eq!(rgb_to_ansi16(20, 20, 20), Color::Black);
+    }
+}
@@ -1,7 +1,20 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::event::KeyEvent;
 use ratatui::style::Color;
-use serde::Deserialize;
-use std::{fs, io};
+use serde::{de::IgnoredAny, Deserialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    env, fs, io, mem,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    color,
+    keys::{Action, KeyBinding},
+    open,
+};
 
 /// `binocular`'s configuration folder name.
 const CONFIG_DIR: &str = "binocular";
@@ -9,57 +22,1601 @@ const CONFIG_DIR: &str = "binocular";
 /// `binocular`'s configuration file name.
 const CONFIG_FILE: &str = "config.json";
 
-#[derive(Deserialize, PartialEq)]
+/// `binocular`'s search history file name, in the configuration folder.
+const HISTORY_FILE: &str = "history.json";
+
+/// `binocular`'s saved queries file name, in the configuration folder.
+const SCRATCHPAD_FILE: &str = "scratchpad.json";
+
+/// Project configuration file names, relative to the project's directory.
+const PROJECT_CONFIG_FILES: [&str; 2] = [".binocular.json", ".binocular/config.json"];
+
+/// Options of a configuration section that aren't known, like misspelled ones. They're
+/// ignored with a warning, and only reported as errors when checking the configuration.
+type UnknownOptions = BTreeMap<String, IgnoredAny>;
+
+/// Merges the unknown options of two configurations.
+fn merge_unknown(mut unknown: UnknownOptions, other: UnknownOptions) -> UnknownOptions {
+    unknown.extend(other);
+    unknown
+}
+
 #[derive(Default, Deserialize)]
 #[serde(default)]
 pub struct ConfigColors {
     base: Option<Color>,
     filepath: Option<Color>,
     selection: Option<Color>,
+    #[serde(rename = "match")]
+    matched: Option<Color>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigColors {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            base: other.base.or(self.base),
+            filepath: other.filepath.or(self.filepath),
+            selection: other.selection.or(self.selection),
+            matched: other.matched.or(self.matched),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigGrep {
+    multiline: Option<bool>,
+    timeout_ms: Option<u64>,
+    /// Only search the files modified within this long, like `30m`, `6h` or `2d`.
+    #[serde(deserialize_with = "deserialize_age")]
+    changed_within: Option<Duration>,
+    on_empty: Option<OnEmpty>,
+    case: Option<CaseMatching>,
+    max_context_bytes: Option<usize>,
+    max_matches_per_file: Option<usize>,
+    line_numbers: Option<bool>,
+    /// How strongly results in recently modified files are moved up, from `0` to `1`.
+    recency_weight: Option<f64>,
+    max_row_lines: Option<usize>,
+    ansi_colors: Option<bool>,
+    terms: Option<bool>,
+    /// Experimental: list the files once and search them by path until the listing
+    /// expires, instead of walking the directory on every search.
+    file_cache: Option<bool>,
+    file_cache_ttl_ms: Option<u64>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigGrep {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            multiline: other.multiline.or(self.multiline),
+            timeout_ms: other.timeout_ms.or(self.timeout_ms),
+            changed_within: other.changed_within.or(self.changed_within),
+            on_empty: other.on_empty.or(self.on_empty),
+            case: other.case.or(self.case),
+            max_context_bytes: other.max_context_bytes.or(self.max_context_bytes),
+            max_matches_per_file: other.max_matches_per_file.or(self.max_matches_per_file),
+            line_numbers: other.line_numbers.or(self.line_numbers),
+            recency_weight: other.recency_weight.or(self.recency_weight),
+            max_row_lines: other.max_row_lines.or(self.max_row_lines),
+            ansi_colors: other.ansi_colors.or(self.ansi_colors),
+            terms: other.terms.or(self.terms),
+            file_cache: other.file_cache.or(self.file_cache),
+            file_cache_ttl_ms: other.file_cache_ttl_ms.or(self.file_cache_ttl_ms),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+/// Units of the ages accepted in the configuration, with their length in seconds.
+const AGE_UNITS: [(char, u64); 4] = [('w', 604_800), ('d', 86_400), ('h', 3_600), ('m', 60)];
+
+/// Parses an age like `30m`, `6h`, `2d` or `1w`.
+fn parse_age(age: &str) -> Result<Duration> {
+    let unit = age.chars().last().unwrap_or_default();
+    let seconds = AGE_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, seconds)| *seconds)
+        .with_context(|| format!("Invalid age `{}`, expected a unit like `2d`", age))?;
+    let count = age
+        .strip_suffix(unit)
+        .unwrap_or_default()
+        .parse::<u64>()
+        .with_context(|| format!("Invalid age `{}`", age))?;
+    let seconds = count
+        .checked_mul(seconds)
+        .with_context(|| format!("Invalid age `{}`, it's too long", age))?;
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Formats an age in its largest whole unit, like `2d`.
+pub(crate) fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    AGE_UNITS
+        .iter()
+        .find(|(_, unit)| seconds.is_multiple_of(*unit))
+        .map_or_else(
+            || format!("{}s", seconds),
+            |(name, unit)| format!("{}{}", seconds / unit, name),
+        )
+}
+
+/// Deserializes an optional age, see [parse_age].
+fn deserialize_age<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|age| parse_age(&age))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Environment variables set for the spawned commands, on top of (and overriding) the
+/// inherited environment.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigEnv {
+    /// Variables for ripgrep (e.g. `RIPGREP_CONFIG_PATH`).
+    rg: HashMap<String, String>,
+    /// Variables for the command opening files. With a split, they're set for the
+    /// multiplexer command.
+    editor: HashMap<String, String>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigEnv {
+    fn merge(self, other: Self) -> Self {
+        let mut rg = self.rg;
+        rg.extend(other.rg);
+
+        let mut editor = self.editor;
+        editor.extend(other.editor);
+
+        Self {
+            rg,
+            editor,
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigDebounce {
+    min_ms: Option<u64>,
+    max_ms: Option<u64>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigDebounce {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            min_ms: other.min_ms.or(self.min_ms),
+            max_ms: other.max_ms.or(self.max_ms),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigPreview {
+    visible: Option<bool>,
+    diff: Option<bool>,
+    diff_base: Option<String>,
+    blame: Option<bool>,
+    /// Refresh the preview when the selected item's file changes on disk.
+    refresh: Option<bool>,
+    update: Option<PreviewUpdate>,
+    tab_width: Option<usize>,
+    render_whitespace: Option<bool>,
+    max_bytes: Option<u64>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigPreview {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            visible: other.visible.or(self.visible),
+            diff: other.diff.or(self.diff),
+            diff_base: other.diff_base.or(self.diff_base),
+            blame: other.blame.or(self.blame),
+            refresh: other.refresh.or(self.refresh),
+            update: other.update.or(self.update),
+            tab_width: other.tab_width.or(self.tab_width),
+            render_whitespace: other.render_whitespace.or(self.render_whitespace),
+            max_bytes: other.max_bytes.or(self.max_bytes),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+/// When the preview follows the selection as it moves.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewUpdate {
+    /// On every move.
+    #[default]
+    Live,
+    /// Once the selection stops moving for a moment, so that moving quickly doesn't
+    /// build the previews (like diffs) of all the results passed by.
+    OnIdle,
+}
+
+/// How duplicate results are collapsed.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupeBy {
+    /// Keep all results.
+    #[default]
+    None,
+    /// Collapse results with identical matched lines.
+    Line,
+}
+
+/// How the grep picker matches the case of the query.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMatching {
+    /// Ignore case unless the query has uppercase characters.
+    #[default]
+    Smart,
+    /// Match case.
+    Sensitive,
+    /// Ignore case.
+    Insensitive,
+}
+
+/// What the grep picker does when a search has no results.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnEmpty {
+    /// Nothing.
+    #[default]
+    None,
+    /// Show a hint about broadening the search.
+    Suggest,
+    /// Search again once, ignoring case and ignore files, labelling the results.
+    AutoBroaden,
+}
+
+/// How read-only files are opened.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadOnly {
+    /// Open them like the other files, warning that changes can't be saved.
+    #[default]
+    Warn,
+    /// Open them with the `viewer` template.
+    View,
+    /// Open a writable copy of them in a scratch directory.
+    Scratch,
+}
+
+/// Where the preview is placed relative to the results.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewLayout {
+    /// Above the results.
+    #[default]
+    Vertical,
+    /// Beside the results.
+    Horizontal,
+}
+
+/// How the help dialog is displayed.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HelpStyle {
+    /// A dialog centered over the results.
+    #[default]
+    Popup,
+    /// A page replacing the whole view.
+    Fullscreen,
+}
+
+/// How results that were already selected during the session are displayed.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VisitedStyle {
+    /// Display them like the other results.
+    #[default]
+    None,
+    /// Dim them.
+    Dim,
+    /// Strike them through.
+    Strikethrough,
+}
+
+/// How the file picker orders its results.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSort {
+    /// Sort by path.
+    Alphabetical,
+    /// Sort by how well the path matches the query.
+    #[default]
+    Score,
+    /// Sort the best matches by modification time, most recent first.
+    Recency,
+}
+
+impl FileSort {
+    /// Returns the next sort order, for cycling through them.
+    pub(crate) fn next(self) -> Self {
+        match self {
+            FileSort::Alphabetical => FileSort::Score,
+            FileSort::Score => FileSort::Recency,
+            FileSort::Recency => FileSort::Alphabetical,
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFiles {
+    sort: Option<FileSort>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigFiles {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            sort: other.sort.or(self.sort),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+/// Terminal multiplexers that can open files in a split pane.
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Multiplexer {
+    /// Detect the multiplexer from the environment.
+    Auto,
+    /// Split with `tmux split-window`.
+    Tmux,
+    /// Split with `wezterm cli split-pane`.
+    Wezterm,
+}
+
+impl Multiplexer {
+    /// Returns the default split command template for the multiplexer.
+    fn split_template(self) -> Option<&'static str> {
+        match self {
+            Multiplexer::Auto => None,
+            Multiplexer::Tmux => Some("tmux split-window -h -c #{pane_current_path} {command}"),
+            Multiplexer::Wezterm => Some("wezterm cli split-pane --right -- {command}"),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigSplit {
+    multiplexer: Option<Multiplexer>,
+    command: Option<String>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigSplit {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            multiplexer: other.multiplexer.or(self.multiplexer),
+            command: other.command.or(self.command),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+/// The editors whose open buffers can be listed.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BufferEditor {
+    /// Query a Neovim server with `nvim --server <address> --remote-expr`.
+    #[default]
+    Neovim,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigBuffers {
+    editor: Option<BufferEditor>,
+    /// Address of the editor's server (e.g. Neovim's `v:servername`).
+    server: Option<String>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigBuffers {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            editor: other.editor.or(self.editor),
+            server: other.server.or(self.server),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+}
+
+/// A picker built around a shell command, whose output lines are the results.
+#[derive(Clone, Deserialize)]
+pub struct ConfigCommand {
+    /// The shell command listing the results. If it contains a `{query}` placeholder, it's
+    /// run again whenever the query changes. Otherwise it's run once and its lines are
+    /// filtered by the query.
+    pub(crate) command: String,
+    /// Separator between the fields of each line. Defaults to whitespace.
+    pub(crate) separator: Option<String>,
+    /// The fields displayed in the results list (starting at 1). Defaults to the whole line.
+    pub(crate) display: Option<Vec<usize>>,
+    /// Shell command whose output previews a result, with `{}` replaced by the line and
+    /// `{1}`, `{2}`, … by its fields. Defaults to previewing the line itself.
+    pub(crate) preview: Option<String>,
+    /// The field holding the path of the file to open when selecting a result.
+    pub(crate) path: Option<usize>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    pub(crate) unknown: UnknownOptions,
+}
+
+/// A user-defined action running a shell command on the selected result.
+#[derive(Clone, Deserialize)]
+pub struct ConfigAction {
+    /// The key running the action.
+    pub(crate) key: KeyBinding,
+    /// The shell command run on the selected result, with `{file}`, `{line}` and `{text}`
+    /// replaced by its file, line and text (like a grep match's line).
+    pub(crate) command: String,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    pub(crate) unknown: UnknownOptions,
+}
+
+/// Options that can be overridden for each picker.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigPicker {
+    context_lines: Option<u16>,
+    context_before: Option<u16>,
+    context_after: Option<u16>,
+    max_results: Option<usize>,
+    warn_results: Option<usize>,
+    layout: Option<PreviewLayout>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
+}
+
+impl ConfigPicker {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            context_lines: other.context_lines.or(self.context_lines),
+            context_before: other.context_before.or(self.context_before),
+            context_after: other.context_after.or(self.context_after),
+            max_results: other.max_results.or(self.max_results),
+            warn_results: other.warn_results.or(self.warn_results),
+            layout: other.layout.or(self.layout),
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
 }
 
 #[derive(Default, Deserialize)]
 #[serde(default)]
 pub struct Config {
+    /// Number of context lines kept both before and after matches.
+    context_lines: Option<u16>,
+    context_before: Option<u16>,
+    context_after: Option<u16>,
+    max_results: Option<usize>,
+    /// Number of results above which the query is reported as suspiciously broad. Unlike
+    /// `max_results`, no results are left out.
+    warn_results: Option<usize>,
+    dedupe_by: Option<DedupeBy>,
+    animations: Option<bool>,
+    /// How long a search runs before the searching spinner shows, so that fast searches
+    /// don't flash it.
+    spinner_delay_ms: Option<u64>,
+    preserve_selection: Option<bool>,
+    select_best: Option<bool>,
+    watch: Option<bool>,
+    confirm_quit: Option<bool>,
+    visited_style: Option<VisitedStyle>,
+    help_style: Option<HelpStyle>,
+    layout: Option<PreviewLayout>,
+    /// How many searched queries are remembered across sessions, `0` disabling the history.
+    history_size: Option<usize>,
+    scrolloff: Option<usize>,
+    /// Number of blank lines between results, which makes them easier to tell apart.
+    row_padding: Option<u16>,
+    wrap_input: Option<bool>,
+    show_flags: Option<bool>,
+    group_by_file: Option<bool>,
+    /// Per-picker overrides of the global options, keyed by picker identifier.
+    pickers: HashMap<String, ConfigPicker>,
     colors: ConfigColors,
+    grep: ConfigGrep,
+    debounce: ConfigDebounce,
+    env: ConfigEnv,
+    files: ConfigFiles,
+    preview: ConfigPreview,
+    /// Opening files in a split pane of a terminal multiplexer.
+    split: ConfigSplit,
+    /// Listing the buffers open in an editor.
+    buffers: ConfigBuffers,
+    /// Command template used to open files, with `{file}`, `{line}` and `{column}`
+    /// placeholders (e.g. `nvim +{line} {file}` or `hx {file}:{line}:{column}`). The
+    /// template is split on whitespace, without any quoting. Defaults to `$VISUAL`, then
+    /// `$EDITOR`, and then the platform's default opener.
+    editor: Option<String>,
+    /// Whether opening a file focuses it, rather than keeping binocular focused. Only
+    /// applies to files opened in a split pane (binocular's pane is focused again once
+    /// it's open) and with macOS' default opener (which opens them in the background).
+    focus_editor: Option<bool>,
+    /// Command template used to open read-only files with `read_only` set to `view`,
+    /// with the same placeholders as `editor` (e.g. `nvim -R +{line} {file}`).
+    viewer: Option<String>,
+    read_only: Option<ReadOnly>,
+    /// Command templates used to open specific files, keyed by glob pattern or extension.
+    open: HashMap<String, String>,
+    /// Key bindings that override the default ones, keyed by action.
+    keys: HashMap<Action, KeyBinding>,
+    /// Pickers built around shell commands, keyed by name.
+    commands: HashMap<String, ConfigCommand>,
+    /// User-defined actions on the selected result, keyed by name.
+    actions: HashMap<String, ConfigAction>,
+    /// Options that aren't known, see [Config::unknown_options].
+    #[serde(flatten)]
+    unknown: UnknownOptions,
 }
 
 impl Config {
-    pub fn load() -> Result<Self> {
-        // Create the configuration directory if needed.
-        let config_dir = dirs::config_dir()
-            .ok_or_else(|| anyhow!("Failed to find a configuration directory"))?;
-        let config_dir = config_dir.join(CONFIG_DIR);
-        fs::create_dir_all(&config_dir).context("Failed to create configuration directory")?;
+    /// Loads the configuration, merging (from lowest to highest precedence):
+    /// 1. The built-in defaults.
+    /// 2. The global configuration file, either the given one or the one in the OS
+    ///    configuration directory (if it exists).
+    /// 3. The project configuration file, the closest `.binocular.json` or
+    ///    `.binocular/config.json` in the current directory or its ancestors (if any),
+    ///    without the options running commands or passed to them, like the diff base
+    ///    and the buffers server (see [Config::read_project]).
+    ///
+    /// See [Config::merge] for how the files are merged.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let config = Self::load_global(path)?;
+        match Self::project_path() {
+            Some(project_path) => Ok(config.merge(Self::read_project(&project_path)?)),
+            None => Ok(config),
+        }
+    }
 
-        // Load the user's configuration file (if it exists).
-        let config = match fs::read_to_string(config_dir.join(CONFIG_FILE)) {
-            Ok(user_config) => {
-                serde_json::from_str(&user_config).context("Failed to parse configuration file")?
+    /// Merges the `other` configuration over this one. Options are merged one by one,
+    /// so unset options in `other` (like a single color) keep their current value.
+    /// Maps (like `open` commands) are merged key by key.
+    pub fn merge(self, other: Self) -> Self {
+        let mut pickers = self.pickers;
+        for (id, picker) in other.pickers {
+            let merged = match pickers.remove(&id) {
+                Some(base) => base.merge(picker),
+                None => picker,
+            };
+            pickers.insert(id, merged);
+        }
+
+        let mut open = self.open;
+        open.extend(other.open);
+
+        let mut keys = self.keys;
+        keys.extend(other.keys);
+
+        let mut commands = self.commands;
+        commands.extend(other.commands);
+
+        let mut actions = self.actions;
+        actions.extend(other.actions);
+
+        Self {
+            context_lines: other.context_lines.or(self.context_lines),
+            context_before: other.context_before.or(self.context_before),
+            context_after: other.context_after.or(self.context_after),
+            max_results: other.max_results.or(self.max_results),
+            warn_results: other.warn_results.or(self.warn_results),
+            dedupe_by: other.dedupe_by.or(self.dedupe_by),
+            animations: other.animations.or(self.animations),
+            spinner_delay_ms: other.spinner_delay_ms.or(self.spinner_delay_ms),
+            preserve_selection: other.preserve_selection.or(self.preserve_selection),
+            select_best: other.select_best.or(self.select_best),
+            watch: other.watch.or(self.watch),
+            confirm_quit: other.confirm_quit.or(self.confirm_quit),
+            visited_style: other.visited_style.or(self.visited_style),
+            help_style: other.help_style.or(self.help_style),
+            layout: other.layout.or(self.layout),
+            history_size: other.history_size.or(self.history_size),
+            scrolloff: other.scrolloff.or(self.scrolloff),
+            row_padding: other.row_padding.or(self.row_padding),
+            wrap_input: other.wrap_input.or(self.wrap_input),
+            show_flags: other.show_flags.or(self.show_flags),
+            group_by_file: other.group_by_file.or(self.group_by_file),
+            pickers,
+            colors: self.colors.merge(other.colors),
+            grep: self.grep.merge(other.grep),
+            debounce: self.debounce.merge(other.debounce),
+            env: self.env.merge(other.env),
+            files: self.files.merge(other.files),
+            preview: self.preview.merge(other.preview),
+            split: self.split.merge(other.split),
+            buffers: self.buffers.merge(other.buffers),
+            editor: other.editor.or(self.editor),
+            focus_editor: other.focus_editor.or(self.focus_editor),
+            viewer: other.viewer.or(self.viewer),
+            read_only: other.read_only.or(self.read_only),
+            open,
+            keys,
+            commands,
+            actions,
+            unknown: merge_unknown(self.unknown, other.unknown),
+        }
+    }
+
+    /// Loads the global configuration file.
+    fn load_global(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            // An explicitly specified file must exist.
+            return Self::read(path);
+        }
+
+        match Self::default_path() {
+            Ok(config_path) => Self::load_default(&config_path),
+            Err(err) => {
+                eprintln!("Warning: {:#}, using the default configuration", err);
+                Ok(Self::default())
             }
-            // If the configuration file doesn't exist, just use the defaults.
-            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
-            Err(err) => return Err(err).context("Failed to read configuration file"),
-        };
+        }
+    }
 
+    /// Loads the configuration file in the OS configuration directory, creating the
+    /// directory if needed. Binocular works fine with the defaults, so they're used
+    /// (with a warning) when the directory or the file can't be accessed. Only invalid
+    /// files are errors.
+    fn load_default(config_path: &Path) -> Result<Self> {
+        if let Some(config_dir) = config_path.parent() {
+            if let Err(err) = fs::create_dir_all(config_dir) {
+                eprintln!(
+                    "Warning: Failed to create configuration directory {}: {}, using the \
+                     default configuration",
+                    config_dir.display(),
+                    err
+                );
+                return Ok(Self::default());
+            }
+        }
+
+        // Load the user's configuration file (if it exists).
+        match Self::read(config_path) {
+            Err(err) => match err.downcast_ref::<io::Error>() {
+                // If the configuration file doesn't exist, just use the defaults.
+                Some(io_err) if io_err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+                Some(_) => {
+                    eprintln!("Warning: {:#}, using the default configuration", err);
+                    Ok(Self::default())
+                }
+                None => Err(err),
+            },
+            res => res,
+        }
+    }
+
+    /// Returns the path of the configuration file in the OS configuration directory.
+    pub fn default_path() -> Result<PathBuf> {
+        dirs::config_dir()
+            .map(|config_dir| config_dir.join(CONFIG_DIR).join(CONFIG_FILE))
+            .ok_or_else(|| anyhow!("Failed to find a configuration directory"))
+    }
+
+    /// Returns the path of the search history file in the OS configuration directory,
+    /// or `None` if the history is disabled.
+    pub(crate) fn history_path(&self) -> Option<PathBuf> {
+        (self.history_size() > 0)
+            .then(dirs::config_dir)
+            .flatten()
+            .map(|config_dir| config_dir.join(CONFIG_DIR).join(HISTORY_FILE))
+    }
+
+    /// Returns the path of the saved queries file in the OS configuration directory, if
+    /// there's one.
+    pub(crate) fn scratchpad_path(&self) -> Option<PathBuf> {
+        dirs::config_dir().map(|config_dir| config_dir.join(CONFIG_DIR).join(SCRATCHPAD_FILE))
+    }
+
+    /// Returns the path of the project configuration file that applies to the current
+    /// directory, if any.
+    pub fn project_path() -> Option<PathBuf> {
+        let cwd = env::current_dir().ok()?;
+        cwd.ancestors()
+            .flat_map(|dir| PROJECT_CONFIG_FILES.map(|file| dir.join(file)))
+            .find(|path| path.is_file())
+    }
+
+    /// Reads a configuration file.
+    fn read(path: &Path) -> Result<Self> {
+        let user_config = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration file {}", path.display()))?;
+        serde_json::from_str(&user_config)
+            .with_context(|| format!("Failed to parse configuration file {}", path.display()))
+    }
+
+    /// Reads a project configuration file. Projects can come from anywhere (like a freshly
+    /// cloned repository), so the options running commands, setting their environment or
+    /// passed to them as arguments are ignored with a warning: they can only be set in the
+    /// global configuration.
+    fn read_project(path: &Path) -> Result<Self> {
+        let mut config = Self::read(path)?;
+        let ignored = config.take_command_options();
+        if !ignored.is_empty() {
+            eprintln!(
+                "Warning: Ignoring {} in project configuration file {}, only the global \
+                 configuration can set them",
+                ignored.join(", "),
+                path.display()
+            );
+        }
         Ok(config)
     }
 
+    /// Unsets the options that run commands, set their environment or are passed to
+    /// them, returning the names of the ones that were set.
+    fn take_command_options(&mut self) -> Vec<&'static str> {
+        [
+            ("editor", self.editor.take().is_some()),
+            ("viewer", self.viewer.take().is_some()),
+            ("split.command", self.split.command.take().is_some()),
+            ("open", !mem::take(&mut self.open).is_empty()),
+            ("commands", !mem::take(&mut self.commands).is_empty()),
+            ("actions", !mem::take(&mut self.actions).is_empty()),
+            ("env.rg", !mem::take(&mut self.env.rg).is_empty()),
+            ("env.editor", !mem::take(&mut self.env.editor).is_empty()),
+            ("preview.diff_base", self.preview.diff_base.take().is_some()),
+            ("buffers.server", self.buffers.server.take().is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect()
+    }
+
+    /// Returns the options overridden for the given picker, if any.
+    fn picker(&self, picker: &str) -> Option<&ConfigPicker> {
+        self.pickers.get(picker)
+    }
+
+    /// Returns the number of context lines kept around matches for the given picker.
+    /// Defaults to `4`.
+    pub(crate) fn context_lines(&self, picker: &str) -> u16 {
+        self.picker(picker)
+            .and_then(|picker| picker.context_lines)
+            .or(self.context_lines)
+            .unwrap_or(4)
+    }
+
+    /// Returns the number of context lines kept before matches for the given picker.
+    /// Defaults to [Config::context_lines].
+    pub(crate) fn context_before(&self, picker: &str) -> u16 {
+        self.picker(picker)
+            .and_then(|picker| picker.context_before)
+            .or(self.context_before)
+            .unwrap_or_else(|| self.context_lines(picker))
+    }
+
+    /// Returns the number of context lines kept after matches for the given picker.
+    /// Defaults to [Config::context_lines].
+    pub(crate) fn context_after(&self, picker: &str) -> u16 {
+        self.picker(picker)
+            .and_then(|picker| picker.context_after)
+            .or(self.context_after)
+            .unwrap_or_else(|| self.context_lines(picker))
+    }
+
+    /// Returns the maximum number of results displayed for the given picker.
+    /// Defaults to no limit.
+    pub(crate) fn max_results(&self, picker: &str) -> Option<usize> {
+        self.picker(picker)
+            .and_then(|picker| picker.max_results)
+            .or(self.max_results)
+    }
+
+    /// Returns the number of results above which the given picker warns that the query
+    /// is too broad. Defaults to never warning.
+    pub(crate) fn warn_results(&self, picker: &str) -> Option<usize> {
+        self.picker(picker)
+            .and_then(|picker| picker.warn_results)
+            .or(self.warn_results)
+    }
+
+    /// Returns the layout configured for the given picker, if any. Unlike other options,
+    /// the picker's preferred layout applies when none is configured.
+    pub(crate) fn layout(&self, picker: &str) -> Option<PreviewLayout> {
+        self.picker(picker)
+            .and_then(|picker| picker.layout)
+            .or(self.layout)
+    }
+
+    /// Returns how duplicate results are collapsed.
+    /// Defaults to [DedupeBy::None].
+    pub(crate) fn dedupe_by(&self) -> DedupeBy {
+        self.dedupe_by.unwrap_or_default()
+    }
+
+    /// Returns whether UI animations (like highlighting the selection as it moves) are enabled.
+    /// Defaults to `false`.
+    pub(crate) fn animations(&self) -> bool {
+        self.animations.unwrap_or(false)
+    }
+
+    /// Returns whether the query is searched again when the files in the current
+    /// directory change. Defaults to `false`.
+    pub(crate) fn watch(&self) -> bool {
+        self.watch.unwrap_or(false)
+    }
+
+    /// Returns whether new results select the best matching one, for pickers that score
+    /// their results, instead of the first one. Defaults to `false`.
+    pub(crate) fn select_best(&self) -> bool {
+        self.select_best.unwrap_or(false)
+    }
+
+    /// Returns whether the selected item is kept when the results of the current
+    /// query are refreshed. Defaults to `true`.
+    pub(crate) fn preserve_selection(&self) -> bool {
+        self.preserve_selection.unwrap_or(true)
+    }
+
+    /// Returns whether quitting asks for confirmation when results are marked.
+    /// Defaults to `false`.
+    pub(crate) fn confirm_quit(&self) -> bool {
+        self.confirm_quit.unwrap_or(false)
+    }
+
+    /// Returns how results that were already selected are displayed.
+    /// Defaults to [VisitedStyle::None].
+    pub(crate) fn visited_style(&self) -> VisitedStyle {
+        self.visited_style.unwrap_or_default()
+    }
+
+    /// Returns how the help dialog is displayed. Defaults to [HelpStyle::Popup].
+    pub(crate) fn help_style(&self) -> HelpStyle {
+        self.help_style.unwrap_or_default()
+    }
+
+    /// Returns how many searched queries are remembered across sessions. Defaults to `100`.
+    pub(crate) fn history_size(&self) -> usize {
+        self.history_size.unwrap_or(100)
+    }
+
+    /// Returns whether long queries wrap over several lines of the input field, instead
+    /// of scrolling horizontally. Defaults to `false`.
+    pub(crate) fn wrap_input(&self) -> bool {
+        self.wrap_input.unwrap_or(false)
+    }
+
+    /// Returns whether the input title shows the command-line flags derived from the
+    /// picker's modes. Defaults to `false`.
+    pub(crate) fn show_flags(&self) -> bool {
+        self.show_flags.unwrap_or(false)
+    }
+
+    /// Returns the minimum number of results kept visible above and below the selection.
+    /// Defaults to `0`.
+    pub(crate) fn scrolloff(&self) -> usize {
+        self.scrolloff.unwrap_or(0)
+    }
+
+    /// Returns the number of blank lines between results. Defaults to `0`.
+    pub(crate) fn row_padding(&self) -> usize {
+        self.row_padding.unwrap_or(0) as usize
+    }
+
+    /// Returns whether results are grouped under a header for each file.
+    /// Defaults to `false`.
+    pub(crate) fn group_by_file(&self) -> bool {
+        self.group_by_file.unwrap_or(false)
+    }
+
     /// Returns the base UI color. Used for borders, titles, and other general UI elements.
     /// Defaults to [Color::LightCyan].
     pub(crate) fn base_color(&self) -> Color {
-        self.colors.base.unwrap_or(Color::LightCyan)
+        color::terminal_color(self.colors.base.unwrap_or(Color::LightCyan))
     }
 
     /// Returns the filepath color. Used for the filepath in the results list.
     /// Defaults to [Color::LightBlue].
     pub(crate) fn filepath_color(&self) -> Color {
-        self.colors.filepath.unwrap_or(Color::LightBlue)
+        color::terminal_color(self.colors.filepath.unwrap_or(Color::LightBlue))
     }
 
     /// Returns the selection color. Used for the currently selected item in the results list.
     /// Defaults to [Color::Yellow].
     pub(crate) fn selection_color(&self) -> Color {
-        self.colors.selection.unwrap_or(Color::Yellow)
+        color::terminal_color(self.colors.selection.unwrap_or(Color::Yellow))
+    }
+
+    /// Returns the match color. Used for the matched part of the lines in the results
+    /// list. Defaults to [Color::LightGreen].
+    pub(crate) fn match_color(&self) -> Color {
+        color::terminal_color(self.colors.matched.unwrap_or(Color::LightGreen))
+    }
+
+    /// Returns whether the grep picker starts in multiline (PCRE2) mode.
+    /// Defaults to `false`.
+    pub(crate) fn grep_multiline(&self) -> bool {
+        self.grep.multiline.unwrap_or(false)
+    }
+
+    /// Returns how long a search runs before the searching spinner shows.
+    /// Defaults to 150ms.
+    pub(crate) fn spinner_delay(&self) -> Duration {
+        Duration::from_millis(self.spinner_delay_ms.unwrap_or(150))
+    }
+
+    /// Returns the minimum delay between typing and searching.
+    /// Defaults to no delay.
+    pub(crate) fn debounce_min(&self) -> Duration {
+        Duration::from_millis(self.debounce.min_ms.unwrap_or(0))
+    }
+
+    /// Returns the maximum delay between typing and searching. When set, the delay
+    /// grows with the number of files in the project, up to this value.
+    /// Defaults to a fixed delay (see [Config::debounce_min]).
+    pub(crate) fn debounce_max(&self) -> Option<Duration> {
+        self.debounce.max_ms.map(Duration::from_millis)
+    }
+
+    /// Returns how the file picker orders its results.
+    /// Defaults to [FileSort::Score].
+    pub(crate) fn files_sort(&self) -> FileSort {
+        self.files.sort.unwrap_or_default()
+    }
+
+    /// Returns whether the preview window is shown on startup.
+    /// Defaults to `true`.
+    pub(crate) fn preview_visible(&self) -> bool {
+        self.preview.visible.unwrap_or(true)
+    }
+
+    /// Returns whether the preview starts in diff mode.
+    /// Defaults to `false`.
+    pub(crate) fn preview_diff(&self) -> bool {
+        self.preview.diff.unwrap_or(false)
+    }
+
+    /// Returns whether the preview starts annotated with `git blame`.
+    /// Defaults to `false`.
+    pub(crate) fn preview_blame(&self) -> bool {
+        self.preview.blame.unwrap_or(false)
+    }
+
+    /// Returns when the preview follows the selection as it moves.
+    /// Defaults to [PreviewUpdate::Live].
+    pub(crate) fn preview_update(&self) -> PreviewUpdate {
+        self.preview.update.unwrap_or_default()
+    }
+
+    /// Returns whether the preview is refreshed when the selected item's file changes.
+    /// Defaults to `false`.
+    pub(crate) fn preview_refresh(&self) -> bool {
+        self.preview.refresh.unwrap_or(false)
+    }
+
+    /// Returns the git ref that diff previews compare against.
+    /// Defaults to `HEAD`.
+    pub(crate) fn preview_diff_base(&self) -> &str {
+        self.preview.diff_base.as_deref().unwrap_or("HEAD")
+    }
+
+    /// Returns the number of columns that tabs are aligned to in the preview.
+    /// Defaults to `4`.
+    pub(crate) fn preview_tab_width(&self) -> usize {
+        self.preview.tab_width.unwrap_or(4)
+    }
+
+    /// Returns whether tabs and trailing spaces are shown as visible glyphs in the preview.
+    /// Defaults to `false`.
+    pub(crate) fn preview_render_whitespace(&self) -> bool {
+        self.preview.render_whitespace.unwrap_or(false)
+    }
+
+    /// Returns the size of the largest file that can be previewed, in bytes.
+    /// Defaults to 10 MB.
+    pub(crate) fn preview_max_bytes(&self) -> u64 {
+        self.preview.max_bytes.unwrap_or(10_000_000)
+    }
+
+    /// Returns how long a grep search can run before being cancelled.
+    /// Defaults to no timeout.
+    pub(crate) fn grep_timeout(&self) -> Option<Duration> {
+        self.grep.timeout_ms.map(Duration::from_millis)
+    }
+
+    /// Returns the total size of the context kept around the matches of a grep search,
+    /// in bytes. Matches past it only keep their matched line. Defaults to 50 MB.
+    pub(crate) fn grep_max_context_bytes(&self) -> usize {
+        self.grep.max_context_bytes.unwrap_or(50_000_000)
+    }
+
+    /// Returns the maximum number of matches kept per file in a grep search, so that
+    /// files with many matches don't bury the other files. Defaults to no limit.
+    pub(crate) fn grep_max_matches_per_file(&self) -> Option<usize> {
+        self.grep.max_matches_per_file
+    }
+
+    /// Returns whether the grep picker's results show their line number.
+    /// Defaults to `true`.
+    pub(crate) fn grep_line_numbers(&self) -> bool {
+        self.grep.line_numbers.unwrap_or(true)
+    }
+
+    /// Returns whether the grep picker runs ripgrep with colored output to find the exact
+    /// extent of the matches (including regex and multiline ones) for highlighting them.
+    /// Otherwise they're only highlighted for literal queries. Defaults to `false`.
+    pub(crate) fn grep_ansi_colors(&self) -> bool {
+        self.grep.ansi_colors.unwrap_or(false)
+    }
+
+    /// Returns whether the grep picker's queries are split into terms: whitespace
+    /// separated terms must all match a line, and a term starting with `|` is an
+    /// alternative to the previous one. Defaults to `false`.
+    pub(crate) fn grep_terms(&self) -> bool {
+        self.grep.terms.unwrap_or(false)
+    }
+
+    /// Returns how long the files listed by the grep picker are reused, or `None` if
+    /// every search walks the directory. Defaults to `None`, and to 10 seconds when the
+    /// file cache is enabled.
+    pub(crate) fn grep_file_cache_ttl(&self) -> Option<Duration> {
+        self.grep
+            .file_cache
+            .unwrap_or(false)
+            .then(|| Duration::from_millis(self.grep.file_cache_ttl_ms.unwrap_or(10_000)))
+    }
+
+    /// Returns how many lines of a multiline match are displayed in its row of the
+    /// results list, the rest being in the preview. Defaults to `1`.
+    pub(crate) fn grep_max_row_lines(&self) -> usize {
+        self.grep.max_row_lines.unwrap_or(1).max(1)
+    }
+
+    /// Returns how strongly the grep picker's results are ranked by the modification
+    /// time of their file, between `0` (ripgrep's order) and `1` (most recent first).
+    /// Defaults to `0`.
+    pub(crate) fn grep_recency_weight(&self) -> f64 {
+        self.grep.recency_weight.unwrap_or(0.0).clamp(0.0, 1.0)
+    }
+
+    /// Returns how recently files must have been modified to be searched by the grep
+    /// picker. Defaults to searching all files.
+    pub(crate) fn grep_changed_within(&self) -> Option<Duration> {
+        self.grep.changed_within
+    }
+
+    /// Returns what the grep picker does when a search has no results.
+    /// Defaults to [OnEmpty::None].
+    pub(crate) fn grep_on_empty(&self) -> OnEmpty {
+        self.grep.on_empty.unwrap_or_default()
+    }
+
+    /// Returns how the grep picker matches the case of the query.
+    /// Defaults to [CaseMatching::Smart].
+    pub(crate) fn grep_case(&self) -> CaseMatching {
+        self.grep.case.unwrap_or_default()
+    }
+
+    /// Returns the environment variables set for ripgrep.
+    pub(crate) fn rg_env(&self) -> &HashMap<String, String> {
+        &self.env.rg
+    }
+
+    /// Returns the environment variables set for the command opening files.
+    pub(crate) fn editor_env(&self) -> &HashMap<String, String> {
+        &self.env.editor
+    }
+
+    /// Returns the command template used to open files in a split pane, in which
+    /// `{command}` is replaced by the open command. Defaults to opening files directly,
+    /// without a split.
+    pub(crate) fn split_template(&self) -> Option<&str> {
+        let multiplexer = self.split_multiplexer()?;
+        self.split
+            .command
+            .as_deref()
+            .or(multiplexer.split_template())
+    }
+
+    /// Returns the multiplexer used to open files in a split pane, if any.
+    pub(crate) fn split_multiplexer(&self) -> Option<Multiplexer> {
+        match self.split.multiplexer? {
+            Multiplexer::Auto => open::detect_multiplexer(),
+            multiplexer => Some(multiplexer),
+        }
+    }
+
+    /// Returns the editor whose buffers are listed by the buffer picker.
+    /// Defaults to [BufferEditor::Neovim].
+    pub(crate) fn buffers_editor(&self) -> BufferEditor {
+        self.buffers.editor.unwrap_or_default()
+    }
+
+    /// Returns the address of the editor's server. Defaults to the server of the Neovim
+    /// instance binocular runs in (from the `NVIM` environment variable), if any.
+    pub(crate) fn buffers_server(&self) -> Option<String> {
+        self.buffers.server.clone().or_else(|| {
+            env::var("NVIM")
+                .ok()
+                .filter(|_| self.buffers_editor() == BufferEditor::Neovim)
+        })
+    }
+
+    /// Returns whether opening a file focuses the editor, rather than keeping binocular
+    /// focused (see [Config::focus_editor]'s field). Defaults to `true`.
+    pub(crate) fn focus_editor(&self) -> bool {
+        self.focus_editor.unwrap_or(true)
+    }
+
+    /// Returns the key bound to the given action.
+    /// Defaults to the action's default key binding.
+    pub(crate) fn key_binding(&self, action: Action) -> KeyBinding {
+        self.keys
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+
+    /// Returns the action bound to the given key event, if any.
+    pub(crate) fn action(&self, key: &KeyEvent) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| self.key_binding(*action).matches(key))
+    }
+
+    /// Returns the action with the given key binding, if any.
+    fn action_bound_to(&self, binding: KeyBinding) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| self.key_binding(*action) == binding)
+    }
+
+    /// Returns the name of the user-defined action bound to the given key event and
+    /// the action, if any.
+    pub(crate) fn custom_action(&self, key: &KeyEvent) -> Option<(&str, &ConfigAction)> {
+        self.actions
+            .iter()
+            .find(|(_, action)| action.key.matches(key))
+            .map(|(name, action)| (name.as_str(), action))
+    }
+
+    /// Returns the names of the options that aren't known, like `grep.multline`.
+    pub fn unknown_options(&self) -> Vec<String> {
+        let sections = [
+            ("", &self.unknown),
+            ("colors.", &self.colors.unknown),
+            ("grep.", &self.grep.unknown),
+            ("debounce.", &self.debounce.unknown),
+            ("env.", &self.env.unknown),
+            ("files.", &self.files.unknown),
+            ("preview.", &self.preview.unknown),
+            ("split.", &self.split.unknown),
+            ("buffers.", &self.buffers.unknown),
+        ];
+        let pickers = self
+            .pickers
+            .iter()
+            .map(|(id, picker)| (format!("pickers.{}.", id), &picker.unknown));
+        let commands = self
+            .commands
+            .iter()
+            .map(|(name, command)| (format!("commands.{}.", name), &command.unknown));
+        let actions = self
+            .actions
+            .iter()
+            .map(|(name, action)| (format!("actions.{}.", name), &action.unknown));
+        let mut unknown = sections
+            .into_iter()
+            .map(|(prefix, unknown)| (prefix.to_owned(), unknown))
+            .chain(pickers)
+            .chain(commands)
+            .chain(actions)
+            .flat_map(|(prefix, unknown)| {
+                unknown
+                    .keys()
+                    .map(move |name| format!("{}{}", prefix, name))
+            })
+            .collect::<Vec<_>>();
+        unknown.sort();
+        unknown
+    }
+
+    /// Checks the configuration for problems that parsing it doesn't catch, like actions
+    /// bound to the same key or unknown options.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = self
+            .unknown_options()
+            .into_iter()
+            .map(|name| format!("Unknown option `{}`", name))
+            .collect::<Vec<_>>();
+        for (i, action) in Action::ALL.iter().enumerate() {
+            let binding = self.key_binding(*action);
+            if let Some(other) = Action::ALL[..i]
+                .iter()
+                .find(|other| self.key_binding(**other) == binding)
+            {
+                problems.push(format!(
+                    "`{}` is bound to both {:?} and {:?}",
+                    binding, other, action
+                ));
+            }
+        }
+        if let Some(weight) = self
+            .grep
+            .recency_weight
+            .filter(|w| !(0.0..=1.0).contains(w))
+        {
+            problems.push(format!(
+                "`grep.recency_weight` must be between 0 and 1, got {}",
+                weight
+            ));
+        }
+        for (name, command) in &self.commands {
+            if command.command.trim().is_empty() {
+                problems.push(format!(
+                    "The command picker `{}` has an empty command",
+                    name
+                ));
+            }
+        }
+        for (name, custom) in &self.actions {
+            if let Some(action) = self.action_bound_to(custom.key) {
+                problems.push(format!(
+                    "`{}` is bound to both {:?} and the action `{}`",
+                    custom.key, action, name
+                ));
+            }
+            if let Some((other, _)) = self
+                .actions
+                .iter()
+                .find(|(other, action)| action.key == custom.key && *other < name)
+            {
+                problems.push(format!(
+                    "`{}` is bound to both the actions `{}` and `{}`",
+                    custom.key, other, name
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            bail!("{}", problems.join("\n"));
+        }
+        Ok(())
+    }
+
+    /// Returns the command picker with the given name.
+    pub(crate) fn command(&self, name: &str) -> Result<&ConfigCommand> {
+        self.commands
+            .get(name)
+            .with_context(|| format!("No command picker named `{}` is configured", name))
+    }
+
+    /// Returns the command template used to open the given file. The `open` pattern
+    /// that matches the file is used, preferring the longest one if several do.
+    /// Defaults to the `editor` template, and then to [default_editor_template].
+    pub(crate) fn open_template(&self, file: &str) -> Result<Cow<'_, str>> {
+        match self
+            .open
+            .iter()
+            .filter(|(pattern, _)| open::pattern_matches(pattern, file))
+            .max_by_key(|(pattern, _)| (pattern.len(), *pattern))
+            .map(|(_, template)| template.as_str())
+            .or(self.editor.as_deref())
+        {
+            Some(template) => Ok(Cow::Borrowed(template)),
+            None => default_editor_template(
+                env::var("VISUAL").ok().as_deref(),
+                env::var("EDITOR").ok().as_deref(),
+                env::consts::OS,
+                self.focus_editor(),
+            )
+            .map(Cow::Owned),
+        }
+    }
+
+    /// Returns the command template used to view the given read-only file. Defaults to
+    /// the template used to open it.
+    pub(crate) fn viewer_template(&self, file: &str) -> Result<Cow<'_, str>> {
+        match &self.viewer {
+            Some(template) => Ok(Cow::Borrowed(template)),
+            None => self.open_template(file),
+        }
+    }
+
+    /// Returns how read-only files are opened. Defaults to [ReadOnly::Warn].
+    pub(crate) fn read_only(&self) -> ReadOnly {
+        self.read_only.unwrap_or_default()
+    }
+}
+
+/// Returns the command template used to open files when no editor is configured: the
+/// `$VISUAL` editor, then the `$EDITOR` one (opened at the line with `+{line}`, which
+/// most terminal editors understand), and then the platform's default opener. Without
+/// `focus`, the opener keeps the file in the background where it can.
+fn default_editor_template(
+    visual: Option<&str>,
+    editor: Option<&str>,
+    os: &str,
+    focus: bool,
+) -> Result<String> {
+    let set = |editor: &&str| !editor.trim().is_empty();
+    if let Some(editor) = visual.filter(set).or(editor.filter(set)) {
+        return Ok(format!("{} +{{line}} {{file}}", editor.trim()));
+    }
+
+    match os {
+        "macos" if focus => Ok("open {file}".to_owned()),
+        "macos" => Ok("open -g {file}".to_owned()),
+        // `start` is a `cmd` builtin, which treats a quoted first argument as the window
+        // title, so open files with Explorer like it would.
+        "windows" => Ok("explorer {file}".to_owned()),
+        "linux" | "freebsd" | "dragonfly" | "netbsd" | "openbsd" => {
+            Ok("xdg-open {file}".to_owned())
+        }
+        _ => bail!(
+            "No editor to open files with, set `editor` in the binocular configuration or the \
+             VISUAL or EDITOR environment variable"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    fn parse(config: &str) -> Config {
+        serde_json::from_str(config).unwrap()
+    }
+
+    #[test]
+    fn merges_colors_field_by_field() {
+        let global = parse(r#"{ "colors": { "base": "Red", "selection": "Blue" } }"#);
+        let project = parse(r#"{ "colors": { "selection": "Green" } }"#);
+        let config = global.merge(project);
+
+        assert_eq!(config.colors.base, Some(Color::Red));
+        assert_eq!(config.colors.selection, Some(Color::Green));
+        assert_eq!(config.colors.filepath, None);
+    }
+
+    #[test]
+    fn merges_picker_overrides() {
+        let global =
+            parse(r#"{ "pickers": { "grep": { "context_lines": 2, "max_results": 10 } } }"#);
+        let project =
+            parse(r#"{ "context_lines": 6, "pickers": { "grep": { "max_results": 5 } } }"#);
+        let config = global.merge(project);
+
+        assert_eq!(config.context_lines("grep"), 2);
+        assert_eq!(config.context_lines("other"), 6);
+        assert_eq!(config.max_results("grep"), Some(5));
+    }
+
+    #[test]
+    fn overrides_layouts_per_picker() {
+        let config =
+            parse(r#"{ "layout": "vertical", "pickers": { "grep": { "layout": "horizontal" } } }"#);
+
+        assert_eq!(config.layout("grep"), Some(PreviewLayout::Horizontal));
+        assert_eq!(config.layout("files"), Some(PreviewLayout::Vertical));
+        assert_eq!(Config::default().layout("files"), None);
+    }
+
+    #[test]
+    fn ignores_command_options_in_project_files() {
+        let mut project = parse(
+            r#"{
+                "editor": "sh -c evil",
+                "split": { "multiplexer": "tmux", "command": "evil {command}" },
+                "env": { "rg": { "RIPGREP_CONFIG_PATH": "evil" } },
+                "actions": { "evil": { "key": "alt-e", "command": "evil {file}" } },
+                "preview": { "diff_base": "--output=evil", "diff": true },
+                "buffers": { "server": "--evil" },
+                "colors": { "selection": "Green" }
+            }"#,
+        );
+
+        assert_eq!(
+            project.take_command_options(),
+            [
+                "editor",
+                "split.command",
+                "actions",
+                "env.rg",
+                "preview.diff_base",
+                "buffers.server"
+            ]
+        );
+        let config = parse(r#"{ "editor": "vim {file}" }"#).merge(project);
+        assert_eq!(config.open_template("main.rs").unwrap(), "vim {file}");
+        assert!(config.split.multiplexer.is_some() && config.split.command.is_none());
+        assert!(config.env.rg.is_empty() && config.actions.is_empty());
+        assert_eq!(config.preview_diff_base(), "HEAD");
+        assert!(config.preview_diff() && config.buffers.server.is_none());
+        assert_eq!(config.colors.selection, Some(Color::Green));
+    }
+
+    #[test]
+    fn merging_defaults_keeps_options() {
+        let global = parse(r#"{ "editor": "vim {file}", "grep": { "multiline": true } }"#);
+        let config = global.merge(Config::default());
+
+        assert_eq!(config.open_template("main.rs").unwrap(), "vim {file}");
+        assert!(config.grep_multiline());
+    }
+
+    #[test]
+    fn falls_back_to_the_environment_editor() {
+        assert_eq!(
+            default_editor_template(Some("nvim"), Some("vi"), "linux", true).unwrap(),
+            "nvim +{line} {file}"
+        );
+        assert_eq!(
+            default_editor_template(Some(" "), Some("vi"), "linux", true).unwrap(),
+            "vi +{line} {file}"
+        );
+        assert_eq!(
+            default_editor_template(None, None, "linux", true).unwrap(),
+            "xdg-open {file}"
+        );
+        assert_eq!(
+            default_editor_template(None, None, "macos", true).unwrap(),
+            "open {file}"
+        );
+        assert_eq!(
+            default_editor_template(None, None, "macos", false).unwrap(),
+            "open -g {file}"
+        );
+        assert!(default_editor_template(None, None, "haiku", true).is_err());
+    }
+
+    #[test]
+    fn enables_the_file_cache() {
+        assert_eq!(Config::default().grep_file_cache_ttl(), None);
+        assert_eq!(
+            parse(r#"{ "grep": { "file_cache": true } }"#).grep_file_cache_ttl(),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            parse(r#"{ "grep": { "file_cache": false, "file_cache_ttl_ms": 500 } }"#)
+                .grep_file_cache_ttl(),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_ages() {
+        let config = parse(r#"{ "grep": { "changed_within": "2d" } }"#);
+
+        assert_eq!(
+            config.grep_changed_within(),
+            Some(Duration::from_secs(2 * 86_400))
+        );
+        assert_eq!(format_age(Duration::from_secs(90 * 60)), "90m");
+        assert!(
+            serde_json::from_str::<Config>(r#"{ "grep": { "changed_within": "2x" } }"#).is_err()
+        );
+        assert!(parse_age("99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn validates_custom_action_keys() {
+        let config = parse(
+            r#"{ "actions": {
+                "lint": { "key": "alt-z", "command": "lint {file}" },
+                "browse": { "key": "alt-h", "command": "open {file}" }
+            } }"#,
+        );
+        let key = KeyEvent::new(KeyCode::Char('z'), KeyModifiers::ALT);
+
+        assert_eq!(config.custom_action(&key).unwrap().0, "lint");
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "`<alt-h>` is bound to both RecentSearches and the action `browse`"
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_options() {
+        let config = parse(
+            r#"{
+                "colour": true,
+                "grep": { "multline": true, "multiline": true },
+                "pickers": { "files": { "layuot": "horizontal" } },
+                "actions": { "lint": { "key": "alt-z", "command": "lint", "kye": "x" } }
+            }"#,
+        );
+
+        assert!(config.grep_multiline());
+        assert_eq!(
+            config.unknown_options(),
+            [
+                "actions.lint.kye",
+                "colour",
+                "grep.multline",
+                "pickers.files.layuot"
+            ]
+        );
+        // Merging keeps them.
+        let config = Config::default().merge(config);
+        assert_eq!(config.unknown_options().len(), 4);
+    }
+
+    #[test]
+    fn validates_options() {
+        assert!(Config::default().validate().is_ok());
+
+        let config = parse(
+            r#"{
+                "grep": { "recency_weight": 2, "multline": true },
+                "commands": { "todo": { "command": " " } }
+            }"#,
+        );
+        let err = config.validate().unwrap_err().to_string();
+        assert_eq!(
+            err.lines().collect::<Vec<_>>(),
+            [
+                "Unknown option `grep.multline`",
+                "`grep.recency_weight` must be between 0 and 1, got 2",
+                "The command picker `todo` has an empty command",
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_defaults_without_a_config_directory() {
+        let dir = env::temp_dir().join(format!("binocular-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // A missing directory is created.
+        let missing = dir.join("missing").join(CONFIG_FILE);
+        let created = Config::load_default(&missing).is_ok();
+        let created_dir = dir.join("missing").is_dir();
+
+        // A directory that can't be created (here under a file) falls back to the defaults.
+        fs::write(dir.join("file"), "").unwrap();
+        let unwritable = dir.join("file").join(CONFIG_DIR).join(CONFIG_FILE);
+        let fallback = Config::load_default(&unwritable).is_ok();
+
+        // Invalid files are still errors.
+        fs::write(&missing, "{ \"context_lines\": \"many\" }").unwrap();
+        let invalid = Config::load_default(&missing).is_err();
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(created && created_dir);
+        assert!(fallback);
+        assert!(invalid);
     }
 }
@@ -0,0 +1,385 @@
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span, Text},
+};
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::color;
+
+/// Returns the diff of the file against the given base ref, with the given number of
+/// context lines around each change. Returns `None` if the file isn't in a git repository,
+/// if the base looks like an option rather than a ref, or if `git` fails for any other
+/// reason.
+pub(crate) fn diff(file: &str, base: &str, context: u16) -> Option<String> {
+    // The base can come from a project's configuration, so don't let it pass options
+    // (like `--output`) to git.
+    if base.starts_with('-') {
+        return None;
+    }
+
+    let output = Command::new("git")
+        .arg("diff")
+        .arg("--no-color")
+        .arg("--no-ext-diff")
+        .arg(format!("--unified={}", context))
+        .arg(base)
+        .arg("--")
+        .arg(file)
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The last change of a line, from `git blame`.
+pub(crate) struct BlameLine {
+    /// The abbreviated hash of the commit.
+    commit: String,
+    author: String,
+    /// The line's contents.
+    content: String,
+}
+
+/// Returns the last change of each line of the file. Returns `None` if the file isn't
+/// in a git repository or if `git` fails for any other reason.
+pub(crate) fn blame(file: &str) -> Option<Vec<BlameLine>> {
+    let output = Command::new("git")
+        .arg("blame")
+        .arg("--line-porcelain")
+        .arg("--")
+        .arg(file)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // Each line is described by a header starting with its commit's hash, followed by
+    // the commit's details and the line's contents (prefixed by a tab).
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut blame = Vec::new();
+    let (mut commit, mut author) = (None, "");
+    for line in stdout.lines() {
+        if let Some(content) = line.strip_prefix('\t') {
+            blame.push(BlameLine {
+                commit: commit.take().unwrap_or_default(),
+                author: author.to_owned(),
+                content: content.to_owned(),
+            });
+        } else if commit.is_none() {
+            commit = Some(line.chars().take(8).collect());
+        } else if let Some(name) = line.strip_prefix("author ") {
+            author = name;
+        }
+    }
+
+    Some(blame)
+}
+
+/// Returns the root directory of the git repository containing the current directory.
+/// Returns `None` if the current directory isn't in a git repository.
+pub(crate) fn root() -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--show-toplevel")
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim_end()))
+}
+
+/// Returns a web link to the lines of the file at the current commit, on the repository's
+/// hosting service (GitHub, GitLab or Bitbucket). Returns `None` if the file isn't in a
+/// git repository or if the `origin` remote isn't hosted on a known service.
+pub(crate) fn permalink(file: &str, lines: &RangeInclusive<u32>) -> Option<String> {
+    let remote = git_output(&["remote", "get-url", "origin"])?;
+    let commit = git_output(&["rev-parse", "HEAD"])?;
+    let root = root()?.canonicalize().ok()?;
+    let path = Path::new(file).canonicalize().ok()?;
+    let path = path.strip_prefix(root).ok()?.to_str()?.replace('\\', "/");
+
+    blob_url(&remote, &commit, &path, lines)
+}
+
+/// Services hosting git repositories, whose web URLs are known.
+#[derive(Debug, PartialEq)]
+enum Forge {
+    GitHub,
+    GitLab,
+    Bitbucket,
+}
+
+impl Forge {
+    /// Detects the service from the host name of a repository, which is either the
+    /// service's own (like `github.com`) or a self-hosted one named after it (like
+    /// `gitlab.example.com`).
+    fn detect(host: &str) -> Option<Self> {
+        host.split('.').find_map(|label| match label {
+            "github" => Some(Forge::GitHub),
+            "gitlab" => Some(Forge::GitLab),
+            "bitbucket" => Some(Forge::Bitbucket),
+            _ => None,
+        })
+    }
+}
+
+/// Returns the web link to the lines of the file (relative to the repository's root) at
+/// the commit, for a repository with the given remote.
+fn blob_url(remote: &str, commit: &str, path: &str, lines: &RangeInclusive<u32>) -> Option<String> {
+    let (host, url) = web_url(remote)?;
+    let (start, end) = (lines.start(), lines.end());
+    match Forge::detect(&host)? {
+        Forge::GitLab => {
+            let range = if start == end {
+                format!("L{}", start)
+            } else {
+                format!("L{}-{}", start, end)
+            };
+            Some(format!("{}/-/blob/{}/{}#{}", url, commit, path, range))
+        }
+        Forge::Bitbucket => {
+            let range = if start == end {
+                format!("lines-{}", start)
+            } else {
+                format!("lines-{}:{}", start, end)
+            };
+            Some(format!("{}/src/{}/{}#{}", url, commit, path, range))
+        }
+        Forge::GitHub => {
+            let range = if start == end {
+                format!("L{}", start)
+            } else {
+                format!("L{}-L{}", start, end)
+            };
+            Some(format!("{}/blob/{}/{}#{}", url, commit, path, range))
+        }
+    }
+}
+
+/// Converts a git remote URL (HTTPS or SSH) into the repository's host name and web URL.
+fn web_url(remote: &str) -> Option<(String, String)> {
+    let remote = remote.trim_end_matches('/').trim_end_matches(".git");
+    let (host, path) = if let Some(rest) = remote
+        .strip_prefix("https://")
+        .or_else(|| remote.strip_prefix("http://"))
+        .or_else(|| remote.strip_prefix("ssh://"))
+    {
+        rest.split_once('/')?
+    } else {
+        // SCP-like syntax: `git@github.com:owner/repo`.
+        remote.split_once(':')?
+    };
+
+    // Drop any user name and port.
+    let host = host.rsplit('@').next()?;
+    let host = host.split(':').next()?.to_lowercase();
+    let url = format!("https://{}/{}", host, path);
+    Some((host, url))
+}
+
+/// Runs a git command, returning its trimmed output if it succeeds.
+fn git_output(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Builds a preview of the diff hunks that overlap with the given lines (in the
+/// current version of the file), coloring added and removed lines.
+/// Returns `None` if the lines weren't changed.
+pub(crate) fn diff_preview(diff: &str, lines: &RangeInclusive<u32>) -> Option<Text<'static>> {
+    let mut preview = Vec::new();
+    let mut in_hunk = false;
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("@@ ") {
+            in_hunk = hunk_new_lines(header)
+                .is_some_and(|hunk| hunk.start() <= lines.end() && lines.start() <= hunk.end());
+            if in_hunk {
+                preview.push(Line::styled(
+                    line.to_owned(),
+                    Style::default().fg(color::terminal_color(Color::DarkGray)),
+                ));
+            }
+        } else if in_hunk {
+            let style = match line.chars().next() {
+                Some('+') => Style::default().fg(color::terminal_color(Color::Green)),
+                Some('-') => Style::default().fg(color::terminal_color(Color::Red)),
+                _ => Style::default(),
+            };
+            preview.push(Line::styled(line.to_owned(), style));
+        }
+    }
+
+    (!preview.is_empty()).then(|| Text::from(preview))
+}
+
+/// Builds a preview of the lines of a blamed file around the given lines, annotated with
+/// the commit and author of their last change in a dim gutter.
+pub(crate) fn blame_preview(
+    blame: &[BlameLine],
+    lines: &RangeInclusive<u32>,
+    context_before: u16,
+    context_after: u16,
+) -> Option<Text<'static>> {
+    let start = lines.start().saturating_sub(context_before.into()).max(1) as usize;
+    let end = (lines.end().saturating_add(context_after.into()) as usize).min(blame.len());
+    let gutter = Style::default().fg(color::terminal_color(Color::DarkGray));
+    let preview = blame
+        .get(start - 1..end)?
+        .iter()
+        .map(|line| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<8} {:<12.12} ", line.commit, line.author),
+                    gutter,
+                ),
+                Span::raw(line.content.clone()),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    (!preview.is_empty()).then(|| Text::from(preview))
+}
+
+/// Parses the range of lines covered in the new version of the file from a hunk header
+/// like `-1,5 +1,6 @@`.
+fn hunk_new_lines(header: &str) -> Option<RangeInclusive<u32>> {
+    let new = header.split_whitespace().find(|s| s.starts_with('+'))?;
+    let (start, len) = new[1..].split_once(',').unwrap_or((&new[1..], "1"));
+    let start = start.parse::<u32>().ok()?;
+    let len = len.parse::<u32>().ok()?;
+
+    Some(start..=start.saturating_add(len.saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn converts_remotes_to_web_urls() {
+        let url = |remote| web_url(remote).map(|(_, url)| url);
+        assert_eq!(
+            url("https://github.com/owner/repo.git").as_deref(),
+            Some("https://github.com/owner/repo")
+        );
+        assert_eq!(
+            url("git@gitlab.com:group/sub/repo.git").as_deref(),
+            Some("https://gitlab.com/group/sub/repo")
+        );
+        assert_eq!(
+            url("ssh://git@bitbucket.org:7999/team/repo/").as_deref(),
+            Some("https://bitbucket.org/team/repo")
+        );
+        assert_eq!(url("/local/path"), None);
+    }
+
+    #[test]
+    fn detects_forges_from_the_host() {
+        assert_eq!(Forge::detect("github.com"), Some(Forge::GitHub));
+        assert_eq!(Forge::detect("gitlab.example.com"), Some(Forge::GitLab));
+        assert_eq!(Forge::detect("bitbucket.org"), Some(Forge::Bitbucket));
+        assert_eq!(Forge::detect("mygithub.io"), None);
+        assert_eq!(Forge::detect("example.com"), None);
+    }
+
+    #[test]
+    fn rejects_diff_bases_that_look_like_options() {
+        let output = env::temp_dir().join(format!("binocular-diff-test-{}", process::id()));
+        let base = format!("--output={}", output.display());
+        assert_eq!(diff("Cargo.toml", &base, 3), None);
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn links_to_the_lines_of_files() {
+        let link = |remote, lines| blob_url(remote, "abc123", "src/main.rs", &lines);
+        assert_eq!(
+            link("git@github.com:owner/repo.git", 3..=3).as_deref(),
+            Some("https://github.com/owner/repo/blob/abc123/src/main.rs#L3")
+        );
+        assert_eq!(
+            link("https://gitlab.com/owner/repo", 3..=5).as_deref(),
+            Some("https://gitlab.com/owner/repo/-/blob/abc123/src/main.rs#L3-5")
+        );
+        assert_eq!(
+            link("https://bitbucket.org/owner/repo", 3..=5).as_deref(),
+            Some("https://bitbucket.org/owner/repo/src/abc123/src/main.rs#lines-3:5")
+        );
+        // Only the host tells the service, not the path.
+        assert_eq!(link("https://example.com/github/repo", 3..=3), None);
+    }
+
+    #[test]
+    fn parses_hunk_headers() {
+        assert_eq!(hunk_new_lines("-1,5 +1,6 @@"), Some(1..=6));
+        assert_eq!(hunk_new_lines("-10,2 +12,3 @@ fn main() {"), Some(12..=14));
+        // The length is omitted for single lines.
+        assert_eq!(hunk_new_lines("-3 +4 @@"), Some(4..=4));
+        // Deleted lines cover the line they were deleted before.
+        assert_eq!(hunk_new_lines("-7,2 +6,0 @@"), Some(6..=6));
+        assert_eq!(hunk_new_lines("-1,5 @@"), None);
+        assert_eq!(hunk_new_lines("-1,5 +x,2 @@"), None);
+    }
+
+    #[test]
+    fn previews_the_hunks_around_the_lines() {
+        let diff = "\
+diff --git a/file.rs b/file.rs
+--- a/file.rs
++++ b/file.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    old();
++    new();
+@@ -20,2 +20,3 @@
+ let x = 1;
++let y = 2;
+";
+        let lines = |preview: Text<'static>| {
+            preview
+                .lines
+                .iter()
+                .map(|line| line.spans.iter().map(|span| &*span.content).collect())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            lines(diff_preview(diff, &(2..=2)).unwrap()),
+            [
+                "@@ -1,3 +1,3 @@",
+                " fn main() {",
+                "-    old();",
+                "+    new();"
+            ]
+        );
+        assert_eq!(
+            lines(diff_preview(diff, &(3..=21)).unwrap()),
+            [
+                "@@ -1,3 +1,3 @@",
+                " fn main() {",
+                "-    old();",
+                "+    new();",
+                "@@ -20,2 +20,3 @@",
+                " let x = 1;",
+                "+let y = 2;",
+            ]
+        );
+        // Unchanged lines have no preview.
+        assert!(diff_preview(diff, &(10..=12)).is_none());
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::Config;
+
+/// A query searched with a picker.
+#[derive(Clone, Deserialize, Serialize)]
+struct Entry {
+    /// The identifier of the picker the query was searched with.
+    picker: String,
+    query: String,
+    /// How many times a result of the query was selected.
+    count: u32,
+    /// When a result of the query was last selected, in seconds since the Unix epoch.
+    last_used: u64,
+}
+
+impl Entry {
+    /// Ranks the entry by how often and how recently it was used, like `zoxide` does.
+    fn frecency(&self, now: u64) -> f64 {
+        let weight = match now.saturating_sub(self.last_used) {
+            age if age < 3_600 => 4.0,
+            age if age < 86_400 => 2.0,
+            age if age < 604_800 => 1.0,
+            _ => 0.5,
+        };
+        self.count as f64 * weight
+    }
+}
+
+/// The searched queries, persisted in the configuration directory across sessions.
+/// Queries are recorded when one of their results is selected.
+#[derive(Default)]
+pub struct History {
+    /// The file the history is persisted in, if enabled.
+    path: Option<PathBuf>,
+    /// The maximum number of queries remembered.
+    max_entries: usize,
+    entries: Vec<Entry>,
+}
+
+impl History {
+    /// Loads the history, which is empty if it's disabled or can't be read.
+    pub fn load(config: &Config) -> Self {
+        let path = config.history_path();
+        let entries = path.as_deref().map(read_entries).unwrap_or_default();
+        Self {
+            path,
+            max_entries: config.history_size(),
+            entries,
+        }
+    }
+
+    /// Returns the picker's queries, the most frequently and recently used first.
+    pub(crate) fn ranked(&self, picker: &str) -> Vec<String> {
+        let now = now();
+        let mut entries = self
+            .entries
+            .iter()
+            .filter(|entry| entry.picker == picker)
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+        entries
+            .into_iter()
+            .map(|entry| entry.query.clone())
+            .collect()
+    }
+
+    /// Records the use of a query and saves the history, forgetting the lowest ranked
+    /// queries beyond the limit. Does nothing if the history is disabled.
+    pub(crate) fn record(&mut self, picker: &str, query: &str) -> Result<()> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+
+        // Other sessions may have recorded queries since the history was loaded.
+        self.entries = read_entries(&path);
+        self.add(picker, query, now());
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to save the search history")?;
+        }
+        let json =
+            serde_json::to_string(&self.entries).context("Failed to save the search history")?;
+        fs::write(path, json).context("Failed to save the search history")
+    }
+
+    /// Adds a use of the query at the given time.
+    fn add(&mut self, picker: &str, query: &str, now: u64) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.picker == picker && entry.query == query)
+        {
+            Some(entry) => {
+                entry.count += 1;
+                entry.last_used = now;
+            }
+            None => self.entries.push(Entry {
+                picker: picker.to_owned(),
+                query: query.to_owned(),
+                count: 1,
+                last_used: now,
+            }),
+        }
+
+        if self.entries.len() > self.max_entries {
+            self.entries
+                .sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+            self.entries.truncate(self.max_entries);
+        }
+    }
+}
+
+/// Reads the history file, ignoring missing and corrupted ones.
+fn read_entries(path: &Path) -> Vec<Entry> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_queries_by_frecency() {
+        let mut history = History {
+            max_entries: 2,
+            ..History::default()
+        };
+        let now = now();
+        history.add("grep", "old", now - 30 * 86_400);
+        history.add("grep", "old", now - 30 * 86_400);
+        history.add("grep", "old", now - 30 * 86_400);
+        history.add("grep", "recent", now);
+        history.add("files", "recent", now);
+
+        // The limit forgets the lowest ranked query.
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.ranked("grep"), ["recent"]);
+        assert_eq!(history.ranked("files"), ["recent"]);
+    }
+}
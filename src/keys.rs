@@ -0,0 +1,395 @@
+use anyhow::{anyhow, bail, Error, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+
+/// An action that can be bound to a key.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Quit the application.
+    Quit,
+    /// Select the previous result.
+    SelectPrevious,
+    /// Select the next result.
+    SelectNext,
+    /// Act on the selected result (e.g. open it).
+    Select,
+    /// Mark or unmark the selected result. Selecting acts on all the marked results.
+    ToggleMark,
+    /// Act on the last selected result again, even if it's no longer listed.
+    Reselect,
+    /// Search again with a higher result limit, when the results were truncated.
+    LoadMore,
+    /// Toggle the help dialog.
+    ToggleHelp,
+    /// Toggle the preview window.
+    TogglePreview,
+    /// Scroll the preview up by a line.
+    ScrollPreviewUp,
+    /// Scroll the preview down by a line.
+    ScrollPreviewDown,
+    /// Toggle the diff preview.
+    ToggleDiff,
+    /// Toggle annotating the preview with the commit and author of each line's last
+    /// change, from `git blame`.
+    ToggleBlame,
+    /// Toggle previewing results with their context, or only the matched lines.
+    ToggleContext,
+    /// Reveal the selected result's file in the file manager.
+    RevealFile,
+    /// Copy a web link to the selected result (or its `path:line`) to the clipboard.
+    CopyLink,
+    /// Copy the selected result's preview (like a match with its context) to the
+    /// clipboard, under a `path:line` header.
+    CopySnippet,
+    /// Open the menu of the recent searches, the most frequently and recently used
+    /// first. Picking one searches it again.
+    RecentSearches,
+    /// Open the menu of the queries saved for the current project. Picking one searches
+    /// it.
+    SavedQueries,
+    /// Save the current query for the current project, under a name typed in the input
+    /// field.
+    SaveQuery,
+    /// Export the files opened during the session to the workspace file (see
+    /// `--export-workspace`).
+    ExportWorkspace,
+    /// Clear the search input (or the filter, while editing it).
+    ClearQuery,
+    /// Reset the session to its state at launch: clear the query, results, marks and
+    /// filter, and restore the picker's default modes (like its scope).
+    ResetSession,
+    /// Switch between editing the search input and a filter over the results.
+    ToggleFilter,
+    /// Toggle the navigation mode, where keys don't edit the query and the first
+    /// results in view are labeled with the digit jumping to them.
+    ToggleNavigation,
+    /// Toggle multiline search (grep picker).
+    ToggleMultiline,
+    /// Toggle matching whole lines equal to the literal query (grep picker).
+    ToggleExact,
+    /// Toggle listing the lines that don't match the query instead (grep picker).
+    ToggleInvert,
+    /// Toggle searching file names instead of contents (grep picker).
+    ToggleFilenames,
+    /// Toggle only searching the recently changed files, when configured (grep picker).
+    ToggleChanged,
+    /// Toggle the line numbers of the results (grep picker).
+    ToggleLineNumbers,
+    /// Cycle between the current file, the current directory and the repository as
+    /// the search scope (grep picker).
+    CycleScope,
+    /// Narrow the search scope to the directory of the selected result (grep picker).
+    ScopeToDirectory,
+    /// Restore the search scope from before narrowing it to a result's directory
+    /// (grep picker).
+    ResetScope,
+    /// Cycle between the orders of the results (file picker).
+    CycleSort,
+}
+
+impl Action {
+    /// All actions, in the order they're displayed in the help dialog.
+    pub(crate) const ALL: [Action; 35] = [
+        Action::Quit,
+        Action::SelectPrevious,
+        Action::SelectNext,
+        Action::Select,
+        Action::ToggleMark,
+        Action::Reselect,
+        Action::LoadMore,
+        Action::ClearQuery,
+        Action::ResetSession,
+        Action::RecentSearches,
+        Action::SavedQueries,
+        Action::SaveQuery,
+        Action::ExportWorkspace,
+        Action::ToggleFilter,
+        Action::ToggleNavigation,
+        Action::ToggleMultiline,
+        Action::ToggleExact,
+        Action::ToggleInvert,
+        Action::ToggleFilenames,
+        Action::ToggleChanged,
+        Action::ToggleLineNumbers,
+        Action::CycleScope,
+        Action::ScopeToDirectory,
+        Action::ResetScope,
+        Action::CycleSort,
+        Action::TogglePreview,
+        Action::ScrollPreviewUp,
+        Action::ScrollPreviewDown,
+        Action::ToggleDiff,
+        Action::ToggleBlame,
+        Action::ToggleContext,
+        Action::RevealFile,
+        Action::CopyLink,
+        Action::CopySnippet,
+        Action::ToggleHelp,
+    ];
+
+    /// Returns a short description of the action.
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::SelectPrevious => "Previous result",
+            Action::SelectNext => "Next result",
+            Action::Select => "Select result",
+            Action::ToggleMark => "Mark result",
+            Action::Reselect => "Select last result again",
+            Action::LoadMore => "Load more results",
+            Action::ToggleHelp => "Toggle help",
+            Action::TogglePreview => "Toggle preview",
+            Action::ScrollPreviewUp => "Scroll preview up",
+            Action::ScrollPreviewDown => "Scroll preview down",
+            Action::ToggleDiff => "Toggle diff preview",
+            Action::ToggleBlame => "Toggle blame preview",
+            Action::ToggleContext => "Toggle preview context",
+            Action::RevealFile => "Reveal in file manager",
+            Action::CopyLink => "Copy link to result",
+            Action::CopySnippet => "Copy result snippet",
+            Action::ClearQuery => "Clear search",
+            Action::ResetSession => "Reset session",
+            Action::RecentSearches => "Recent searches",
+            Action::SavedQueries => "Saved queries",
+            Action::SaveQuery => "Save query",
+            Action::ExportWorkspace => "Export opened files",
+            Action::ToggleFilter => "Filter results",
+            Action::ToggleNavigation => "Toggle navigation mode",
+            Action::ToggleMultiline => "Toggle multiline search",
+            Action::ToggleExact => "Toggle exact line search",
+            Action::ToggleInvert => "Toggle inverted search",
+            Action::ToggleFilenames => "Toggle file name search",
+            Action::ToggleChanged => "Toggle recently changed files",
+            Action::ToggleLineNumbers => "Toggle line numbers",
+            Action::CycleScope => "Cycle search scope",
+            Action::ScopeToDirectory => "Search result's directory",
+            Action::ResetScope => "Restore search scope",
+            Action::CycleSort => "Cycle sort order",
+        }
+    }
+
+    /// Returns the key bound to the action by default.
+    pub(crate) fn default_binding(self) -> KeyBinding {
+        let (code, modifiers) = match self {
+            Action::Quit => (KeyCode::Esc, KeyModifiers::NONE),
+            Action::SelectPrevious => (KeyCode::Up, KeyModifiers::NONE),
+            Action::SelectNext => (KeyCode::Down, KeyModifiers::NONE),
+            Action::Select => (KeyCode::Enter, KeyModifiers::NONE),
+            Action::ToggleMark => (KeyCode::Tab, KeyModifiers::NONE),
+            Action::Reselect => (KeyCode::Char('r'), KeyModifiers::ALT),
+            Action::LoadMore => (KeyCode::Char('l'), KeyModifiers::ALT),
+            Action::ToggleHelp => (KeyCode::Char('?'), KeyModifiers::NONE),
+            Action::TogglePreview => (KeyCode::Char('/'), KeyModifiers::CONTROL),
+            Action::ScrollPreviewUp => (KeyCode::Up, KeyModifiers::SHIFT),
+            Action::ScrollPreviewDown => (KeyCode::Down, KeyModifiers::SHIFT),
+            Action::ToggleDiff => (KeyCode::Char('d'), KeyModifiers::ALT),
+            Action::ToggleBlame => (KeyCode::Char('b'), KeyModifiers::ALT),
+            Action::ToggleContext => (KeyCode::Char('t'), KeyModifiers::ALT),
+            Action::RevealFile => (KeyCode::Char('e'), KeyModifiers::ALT),
+            Action::CopyLink => (KeyCode::Char('y'), KeyModifiers::ALT),
+            Action::CopySnippet => (KeyCode::Char('p'), KeyModifiers::ALT),
+            Action::ClearQuery => (KeyCode::Char('l'), KeyModifiers::CONTROL),
+            Action::ResetSession => (KeyCode::Char('k'), KeyModifiers::ALT),
+            Action::RecentSearches => (KeyCode::Char('h'), KeyModifiers::ALT),
+            Action::SavedQueries => (KeyCode::Char('q'), KeyModifiers::ALT),
+            Action::SaveQuery => (KeyCode::Char('w'), KeyModifiers::ALT),
+            Action::ExportWorkspace => (KeyCode::Char('a'), KeyModifiers::ALT),
+            Action::ToggleFilter => (KeyCode::Char('f'), KeyModifiers::CONTROL),
+            Action::ToggleNavigation => (KeyCode::Char('j'), KeyModifiers::ALT),
+            Action::ToggleMultiline => (KeyCode::Char('m'), KeyModifiers::ALT),
+            Action::ToggleExact => (KeyCode::Char('x'), KeyModifiers::ALT),
+            Action::ToggleInvert => (KeyCode::Char('v'), KeyModifiers::ALT),
+            Action::ToggleFilenames => (KeyCode::Char('f'), KeyModifiers::ALT),
+            Action::ToggleChanged => (KeyCode::Char('c'), KeyModifiers::ALT),
+            Action::ToggleLineNumbers => (KeyCode::Char('n'), KeyModifiers::ALT),
+            Action::CycleScope => (KeyCode::Char('s'), KeyModifiers::ALT),
+            Action::ScopeToDirectory => (KeyCode::Char('g'), KeyModifiers::ALT),
+            Action::ResetScope => (KeyCode::Char('u'), KeyModifiers::ALT),
+            Action::CycleSort => (KeyCode::Char('o'), KeyModifiers::ALT),
+        };
+
+        KeyBinding { code, modifiers }
+    }
+}
+
+/// A key combination, written like `ctrl-u`, `alt-enter` or `?`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Returns whether the key event triggers this binding.
+    pub(crate) fn matches(&self, key: &KeyEvent) -> bool {
+        match (self.code, key.code) {
+            // Terminals usually send `ctrl-/` as `ctrl-7`.
+            (KeyCode::Char('/'), KeyCode::Char('7')) if self.modifiers == KeyModifiers::CONTROL => {
+                key.modifiers == KeyModifiers::CONTROL
+            }
+            // Shift is implied by the character itself (e.g. `?` or `A`).
+            (KeyCode::Char(c), KeyCode::Char(key_c)) => {
+                c == key_c
+                    && self.modifiers - KeyModifiers::SHIFT == key.modifiers - KeyModifiers::SHIFT
+            }
+            (code, key_code) => code == key_code && self.modifiers == key.modifiers,
+        }
+    }
+}
+
+/// Names of the non-character keys.
+const KEY_NAMES: [(KeyCode, &str); 15] = [
+    (KeyCode::Esc, "esc"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::BackTab, "backtab"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Delete, "delete"),
+    (KeyCode::Insert, "insert"),
+    (KeyCode::Up, "up"),
+    (KeyCode::Down, "down"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Right, "right"),
+    (KeyCode::Home, "home"),
+    (KeyCode::End, "end"),
+    (KeyCode::PageUp, "pageup"),
+    (KeyCode::PageDown, "pagedown"),
+];
+
+/// Modifier prefixes.
+const MODIFIER_NAMES: [(KeyModifiers, &str); 3] = [
+    (KeyModifiers::CONTROL, "ctrl-"),
+    (KeyModifiers::ALT, "alt-"),
+    (KeyModifiers::SHIFT, "shift-"),
+];
+
+impl FromStr for KeyBinding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut key = s;
+        let mut modifiers = KeyModifiers::NONE;
+        while let Some((modifier, rest)) = MODIFIER_NAMES
+            .iter()
+            .find_map(|(modifier, name)| Some((*modifier, key.strip_prefix(name)?)))
+            .filter(|(_, rest)| !rest.is_empty())
+        {
+            modifiers |= modifier;
+            key = rest;
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            (Some('f'), Some(_)) if key[1..].parse::<u8>().is_ok() => {
+                KeyCode::F(key[1..].parse().unwrap_or_default())
+            }
+            (Some(_), Some(_)) => KEY_NAMES
+                .iter()
+                .find(|(_, name)| *name == key)
+                .map(|(code, _)| *code)
+                .ok_or_else(|| anyhow!("Unknown key `{}` in key binding `{}`", key, s))?,
+            (None, _) => bail!("Invalid empty key binding"),
+        };
+
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBinding {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    /// Formats the binding as shown in the help dialog: `?` or `<ctrl-u>`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let (KeyCode::Char(c), true) =
+            (self.code, (self.modifiers - KeyModifiers::SHIFT).is_empty())
+        {
+            return write!(f, "{}", c);
+        }
+
+        write!(f, "<")?;
+        for (modifier, name) in MODIFIER_NAMES {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(c) => write!(f, "{}", c)?,
+            KeyCode::F(n) => write!(f, "f{}", n)?,
+            code => write!(
+                f,
+                "{}",
+                KEY_NAMES
+                    .iter()
+                    .find(|(key_code, _)| *key_code == code)
+                    .map_or("?", |(_, name)| name)
+            )?,
+        }
+        write!(f, ">")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> (KeyCode, KeyModifiers) {
+        let binding = s.parse::<KeyBinding>().unwrap();
+        (binding.code, binding.modifiers)
+    }
+
+    #[test]
+    fn parses_key_bindings() {
+        assert_eq!(parse("?"), (KeyCode::Char('?'), KeyModifiers::NONE));
+        assert_eq!(parse("ctrl-u"), (KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(
+            parse("ctrl-alt-enter"),
+            (KeyCode::Enter, KeyModifiers::CONTROL | KeyModifiers::ALT)
+        );
+        assert_eq!(parse("shift-f5"), (KeyCode::F(5), KeyModifiers::SHIFT));
+        assert_eq!(parse("f"), (KeyCode::Char('f'), KeyModifiers::NONE));
+        // A trailing `-` is the key itself.
+        assert_eq!(parse("alt--"), (KeyCode::Char('-'), KeyModifiers::ALT));
+        assert!("ctrl-nope".parse::<KeyBinding>().is_err());
+        assert!("".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn displays_key_bindings() {
+        let display = |s: &str| s.parse::<KeyBinding>().unwrap().to_string();
+        assert_eq!(display("?"), "?");
+        assert_eq!(display("shift-A"), "A");
+        assert_eq!(display("alt-ctrl-u"), "<ctrl-alt-u>");
+        assert_eq!(display("pagedown"), "<pagedown>");
+        assert_eq!(display("f12"), "<f12>");
+    }
+
+    #[test]
+    fn round_trips_key_bindings() {
+        for s in [
+            "ctrl-u",
+            "alt-enter",
+            "shift-tab",
+            "ctrl-shift-f3",
+            "esc",
+            "alt-x",
+        ] {
+            let binding = s.parse::<KeyBinding>().unwrap();
+            let displayed = binding.to_string();
+            let reparsed = displayed
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .parse::<KeyBinding>()
+                .unwrap();
+            assert!(reparsed == binding, "{} displayed as {}", s, displayed);
+        }
+    }
+}
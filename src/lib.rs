@@ -2,9 +2,33 @@
 mod app;
 pub use app::App;
 
+/// System clipboard access.
+mod clipboard;
+
+/// Terminal color support.
+mod color;
+
 /// User configuration.
 mod config;
-pub use config::Config;
+pub use config::{CaseMatching, Config};
+
+/// Git integration.
+mod git;
+
+/// Key bindings.
+mod keys;
+pub use keys::Action;
+
+/// Persisted search history.
+mod history;
+pub use history::History;
+
+/// Named queries saved per project.
+mod scratchpad;
+pub use scratchpad::Scratchpad;
+
+/// Opening files in external programs.
+mod open;
 
 /// `Binocular` pickers.
 pub mod pickers;
@@ -12,3 +36,9 @@ pub mod pickers;
 /// Terminal user interface.
 mod tui;
 pub use tui::Tui;
+
+/// Watching files for changes.
+mod watch;
+
+/// Exporting the files opened during a session.
+mod workspace;
@@ -1,10 +1,25 @@
 use anyhow::{Context, Result};
-use std::panic;
+use clap::Parser;
+use std::{
+    io::{self, Write},
+    panic, process,
+};
 
-use binocular::{pickers::GrepPicker, App, Config, Tui};
+use binocular::{
+    pickers::{BufferPicker, CommandPicker, FilePicker, GrepPicker, Picker, PickerItem},
+    App, Config, History, Scratchpad, Tui,
+};
+use cli::{Args, PickerKind, Target};
+
+/// Command line interface.
+mod cli;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let mut args = Args::parse();
+    args.apply_env();
+    let launch = args.apply_launch().unwrap_or_default();
+
     // Make sure we cleanup when panicking.
     let original_panic = panic::take_hook();
     panic::set_hook(Box::new(move |panic_info| {
@@ -13,18 +28,150 @@ async fn main() -> Result<()> {
     }));
 
     // Initialize the application.
-    let config = Config::load().context("Failed to load binocular configuration")?;
-    let mut tui = Tui::setup(&config).context("Failed to setup terminal")?;
-    let mut app = App::new(GrepPicker);
+    let config = if args.no_config {
+        Config::default()
+    } else {
+        Config::load(args.config.as_deref()).context("Failed to load binocular configuration")?
+    };
+    // Unknown options are only errors when checking the configuration.
+    let unknown = config.unknown_options();
+    if !unknown.is_empty() && !args.check_config {
+        eprintln!(
+            "Warning: Ignoring unknown configuration options `{}`",
+            unknown.join("`, `")
+        );
+    }
+    if args.debug {
+        match (args.no_config, &args.config) {
+            (true, _) => eprintln!("Using the default configuration"),
+            (false, Some(path)) => eprintln!("Using configuration file {}", path.display()),
+            (false, None) => match Config::default_path() {
+                Ok(path) if path.exists() => {
+                    eprintln!("Using configuration file {}", path.display())
+                }
+                _ => eprintln!("No global configuration file found"),
+            },
+        }
+        if let Some(path) = Config::project_path().filter(|_| !args.no_config) {
+            eprintln!("Using project configuration file {}", path.display());
+        }
+    }
+    if args.check_config {
+        config
+            .validate()
+            .context("Invalid binocular configuration")?;
+        eprintln!("The configuration is valid");
+        return Ok(());
+    }
+
+    let command = args
+        .command
+        .as_deref()
+        .map(|name| CommandPicker::new(&config, name))
+        .transpose()?;
+    let res = match (command, args.picker) {
+        (Some(picker), _) => run(App::new(picker, &config)?, &config, &args).await,
+        (None, PickerKind::Grep) => {
+            let mut picker = GrepPicker::new(&config);
+            if let Some(Target::File(file)) = args.file.clone() {
+                picker = picker.with_file(file);
+            }
+            if let Some(case) = launch.case {
+                picker = picker.with_case(case);
+            }
+            if let Some(dir) = launch.scope {
+                picker = picker.with_directory(dir);
+            }
+            run(App::new(picker, &config)?, &config, &args).await
+        }
+        (None, PickerKind::Files) => {
+            run(App::new(FilePicker::new(&config), &config)?, &config, &args).await
+        }
+        (None, PickerKind::Buffers) => {
+            run(
+                App::new(BufferPicker::new(&config), &config)?,
+                &config,
+                &args,
+            )
+            .await
+        }
+    };
+
+    // Print the query and the selection for the calling script.
+    let (query, paths) = res?;
+    let mut stdout = io::stdout();
+    if args.print_query {
+        let terminator = if args.print0 { '\0' } else { '\n' };
+        write!(stdout, "{}{}", query, terminator).context("Failed to print the query")?;
+    }
+    for path in paths {
+        write!(stdout, "{}\0", path).context("Failed to print the selection")?;
+    }
+    stdout.flush().context("Failed to print the selection")?;
+
+    Ok(())
+}
+
+/// Exit code used by `--exit-0` when the initial query has no results.
+const NO_RESULTS_EXIT_CODE: i32 = 1;
+
+/// Runs the application loop, returning the final query. With `--print0`, also returns
+/// the paths of the marked items (or else of the selected one) instead of letting the
+/// picker open them. With `--select-1`
+/// or `--exit-0`, the initial query is searched first, and the UI is only shown if its
+/// results are ambiguous.
+async fn run<I: PickerItem, P: Picker<I>>(
+    app: App<'_, I, P>,
+    config: &Config,
+    args: &Args,
+) -> Result<(String, Vec<String>)> {
+    let mut app = app
+        .return_selection(args.print0)
+        .show_flags(args.debug)
+        .watch(args.watch)
+        .history(History::load(config))
+        .scratchpad(Scratchpad::load(config))
+        .workspace(args.export_workspace.clone());
+    if let Some(query) = &args.query {
+        app = app.query(query);
+    }
+    let path = |item: I| item.location().map(|location| location.path.to_owned());
 
-    // Application loop.
-    let res = app
-        .run(&mut tui)
-        .await
-        .context("Failed to run the application");
+    if args.select_1 || args.exit_0 {
+        match app.search_initial().await {
+            0 if args.exit_0 => {
+                // Explain why there are no results, like an invalid query.
+                if let Some(status) = app.status() {
+                    eprintln!("{}", status);
+                }
+                process::exit(NO_RESULTS_EXIT_CODE);
+            }
+            1 if args.select_1 => {
+                let selection = app
+                    .accept_selection()?
+                    .into_iter()
+                    .filter_map(path)
+                    .collect();
+                if args.export_workspace.is_some() {
+                    app.export_workspace()?;
+                }
+                return Ok((app.search_query().to_owned(), selection));
+            }
+            _ => {}
+        }
+    }
 
-    // Cleanup.
+    let mut tui = Tui::setup(config).context("Failed to setup terminal")?;
+    let selection = app.run(&mut tui).await;
     Tui::shutdown();
 
-    res
+    let selection = selection
+        .context("Failed to run the application")?
+        .into_iter()
+        .filter_map(path)
+        .collect();
+    if args.export_workspace.is_some() {
+        app.export_workspace()?;
+    }
+    Ok((app.search_query().to_owned(), selection))
 }
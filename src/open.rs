@@ -0,0 +1,478 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    iter,
+    path::{Component, Path, PathBuf},
+};
+use tokio::process::Command;
+
+use crate::{
+    config::{Multiplexer, ReadOnly},
+    Config,
+};
+
+/// Opens the file at the given line and (character) column using the command configured
+/// for it.
+/// When a split is configured, the command runs in a new multiplexer pane instead.
+/// Read-only files are opened according to the `read_only` option.
+pub(crate) fn open(config: &Config, file: &str, line: u32, column: usize) -> Result<()> {
+    let scratch;
+    let (template, file) = match (config.read_only(), is_read_only(file)) {
+        (ReadOnly::View, true) => (config.viewer_template(file)?, file),
+        (ReadOnly::Scratch, true) => {
+            scratch = scratch_copy(file, &scratch_dir()?)?;
+            (config.open_template(file)?, scratch.as_str())
+        }
+        _ => (config.open_template(file)?, file),
+    };
+    let mut command = command_from_template(&template, file, line, column)?;
+    if let Some(split_template) = config.split_template() {
+        command = split_command(split_template, &command)?;
+    }
+    command.envs(config.editor_env());
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run `{}`", template))?;
+
+    // Split commands focus the new pane, so focus binocular's pane again once it's open.
+    let refocus = config
+        .split_template()
+        .and(config.split_multiplexer())
+        .filter(|_| !config.focus_editor())
+        .and_then(refocus_command);
+    if let Some(mut refocus) = refocus {
+        tokio::spawn(async move {
+            if child.wait().await.is_ok() {
+                let _ = refocus.status().await;
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Returns the command focusing binocular's pane in the multiplexer, if it runs in one.
+fn refocus_command(multiplexer: Multiplexer) -> Option<Command> {
+    let (program, args, var): (_, &[&str], _) = match multiplexer {
+        Multiplexer::Auto => return None,
+        Multiplexer::Tmux => ("tmux", &["select-pane", "-t"], "TMUX_PANE"),
+        Multiplexer::Wezterm => (
+            "wezterm",
+            &["cli", "activate-pane", "--pane-id"],
+            "WEZTERM_PANE",
+        ),
+    };
+    let pane = env::var_os(var)?;
+    let mut command = Command::new(program);
+    command.args(args).arg(pane);
+    Some(command)
+}
+
+/// Returns whether the file exists but the user can't write to it. Opening the file for
+/// writing (without truncating it) tells, whoever owns it.
+fn is_read_only(file: &str) -> bool {
+    fs::metadata(file).is_ok_and(|metadata| metadata.is_file())
+        && OpenOptions::new()
+            .write(true)
+            .open(file)
+            .is_err_and(|err| err.kind() == io::ErrorKind::PermissionDenied)
+}
+
+/// Returns the directory of the scratch copies, in the user's cache directory rather
+/// than in the temporary directory shared with the other users.
+fn scratch_dir() -> Result<PathBuf> {
+    dirs::cache_dir()
+        .map(|dir| dir.join("binocular").join("scratch"))
+        .context("Failed to find a cache directory for scratch copies")
+}
+
+/// Returns the path of the file's copy in the scratch directory, mirroring its path so
+/// that files with the same name don't overwrite each other's copy.
+fn scratch_path(file: &str, scratch_dir: &Path) -> PathBuf {
+    Path::new(file)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .fold(scratch_dir.to_path_buf(), |path, name| path.join(name))
+}
+
+/// Copies the file to the scratch directory, returning the path of the writable copy.
+fn scratch_copy(file: &str, scratch_dir: &Path) -> Result<String> {
+    let scratch = scratch_path(file, scratch_dir);
+    if let Some(dir) = scratch.parent() {
+        // Only the user can access the copies.
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder
+            .create(dir)
+            .with_context(|| format!("Failed to create scratch directory {}", dir.display()))?;
+    }
+
+    // Write the contents to a new file instead of copying the file, which would keep it
+    // read-only. A previous copy is replaced rather than written through, in case it's
+    // now a link to another file.
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    fs::remove_file(&scratch)
+        .or_else(|err| match err.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(err),
+        })
+        .and_then(|()| fs::read(file))
+        .and_then(|contents| options.open(&scratch)?.write_all(&contents))
+        .with_context(|| format!("Failed to copy {} to {}", file, scratch.display()))?;
+
+    Ok(scratch.to_string_lossy().into_owned())
+}
+
+/// Returns a notice for the user when the opened file is read-only, describing how it
+/// was opened.
+pub(crate) fn read_only_notice(config: &Config, file: &str) -> Option<String> {
+    if !is_read_only(file) {
+        return None;
+    }
+
+    Some(match config.read_only() {
+        ReadOnly::Warn => format!("{} is read-only, changes can't be saved", file),
+        ReadOnly::View => format!("{} is read-only, opened it in the viewer", file),
+        ReadOnly::Scratch => match scratch_dir() {
+            Ok(dir) => format!(
+                "{} is read-only, opened a scratch copy at {}",
+                file,
+                scratch_path(file, &dir).display()
+            ),
+            Err(_) => format!("{} is read-only", file),
+        },
+    })
+}
+
+/// Returns the terminal multiplexer binocular is running in, if any.
+pub(crate) fn detect_multiplexer() -> Option<Multiplexer> {
+    if env::var_os("TMUX").is_some() {
+        Some(Multiplexer::Tmux)
+    } else if env::var_os("WEZTERM_PANE").is_some() {
+        Some(Multiplexer::Wezterm)
+    } else {
+        None
+    }
+}
+
+/// Wraps a command in a split command template, replacing the `{command}` placeholder
+/// with the command's program and arguments.
+fn split_command(template: &str, command: &Command) -> Result<Command> {
+    let command = command.as_std();
+    let mut args = template.split_whitespace().flat_map(|arg| {
+        if arg == "{command}" {
+            iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(OsStr::to_owned)
+                .collect()
+        } else {
+            vec![OsString::from(arg)]
+        }
+    });
+    let program = args
+        .next()
+        .with_context(|| format!("Invalid split command template `{}`", template))?;
+
+    let mut split = Command::new(program);
+    split.args(args);
+    Ok(split)
+}
+
+/// Reveals the file in the system's file manager.
+pub(crate) fn reveal(file: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(file);
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("explorer");
+        command.arg(format!("/select,{}", file));
+        command
+    } else {
+        // Most Linux file managers can't select a file, so just open its directory.
+        let dir = Path::new(file)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let mut command = Command::new("xdg-open");
+        command.arg(dir);
+        command
+    };
+
+    command
+        .spawn()
+        .with_context(|| format!("Failed to reveal {} in the file manager", file))
+        .map(|_| ())
+}
+
+/// Builds a command from a template, replacing the `{file}`, `{line}` and `{column}`
+/// placeholders. Placeholders are replaced after splitting the template into arguments,
+/// so filenames containing whitespace are passed as a single argument.
+pub(crate) fn command_from_template(
+    template: &str,
+    file: &str,
+    line: u32,
+    column: usize,
+) -> Result<Command> {
+    let (line, column) = (line.to_string(), column.to_string());
+    let mut args = template.split_whitespace().map(|arg| {
+        arg.replace("{file}", file)
+            .replace("{line}", &line)
+            .replace("{column}", &column)
+    });
+    let program = args
+        .next()
+        .with_context(|| format!("Invalid empty command template for {}", file))?;
+
+    let mut command = Command::new(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// Builds a shell command from a template, replacing each `{name}` placeholder with its
+/// value. On Unix, values are passed to `sh` as positional parameters instead of being
+/// spliced into the script, so they're never interpreted by the shell.
+pub(crate) fn shell_command(
+    template: &str,
+    placeholders: &[(&str, &str)],
+) -> Result<std::process::Command> {
+    if cfg!(windows) {
+        return cmd_command(template, placeholders);
+    }
+
+    let script = substitute(template, placeholders, |i, _| format!("\"${{{}}}\"", i + 1));
+    let mut command = std::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(script)
+        .arg("sh")
+        .args(placeholders.iter().map(|(_, value)| value));
+    Ok(command)
+}
+
+/// Builds a `cmd` command from a template, replacing each `{name}` placeholder with its
+/// value in double quotes. `cmd` can't escape characters within quotes, so values with
+/// characters it would still interpret are refused.
+fn cmd_command(template: &str, placeholders: &[(&str, &str)]) -> Result<std::process::Command> {
+    if let Some((name, _)) = placeholders
+        .iter()
+        .find(|(_, value)| value.contains(['"', '%', '!', '\r', '\n']))
+    {
+        bail!("The value of {{{}}} can't be passed to cmd safely", name);
+    }
+
+    let script = substitute(template, placeholders, |_, value| format!("\"{}\"", value));
+    let mut command = std::process::Command::new("cmd");
+    command.arg("/C");
+    // `cmd` doesn't parse its command line like other programs, so the script is
+    // passed as is.
+    #[cfg(windows)]
+    std::os::windows::process::CommandExt::raw_arg(&mut command, script);
+    #[cfg(not(windows))]
+    command.arg(script);
+    Ok(command)
+}
+
+/// Replaces the `{name}` placeholders of the template in a single pass, with the
+/// replacement of the placeholder at the given position and with the given value. Values
+/// are never searched for placeholders themselves, and unknown placeholders are kept.
+fn substitute(
+    template: &str,
+    placeholders: &[(&str, &str)],
+    replacement: impl Fn(usize, &str) -> String,
+) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let placeholder = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            let i = placeholders.iter().position(|(other, _)| *other == name)?;
+            Some((i, end))
+        });
+        match placeholder {
+            Some((i, end)) => {
+                result.push_str(&replacement(i, placeholders[i].1));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Returns whether the file matches an `open` pattern.
+/// Patterns are either a bare extension (`png` or `.png`) or a glob supporting `*` and `?`.
+/// Globs without a `/` are matched against the file name only.
+pub(crate) fn pattern_matches(pattern: &str, file: &str) -> bool {
+    let path = Path::new(file);
+    if !pattern.contains(['*', '?', '/']) {
+        return path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(pattern.trim_start_matches('.')));
+    }
+
+    let target = if pattern.contains('/') {
+        file
+    } else {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(file)
+    };
+    glob_matches(pattern.as_bytes(), target.as_bytes())
+}
+
+/// Matches `text` against a glob `pattern` where `*` matches any sequence of characters
+/// and `?` matches a single one.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_matches(&pattern[1..], text)
+                || (!text.is_empty() && glob_matches(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_matches(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p == t => glob_matches(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn matches_open_patterns() {
+        // Bare extensions, with or without a dot, ignoring case.
+        assert!(pattern_matches("png", "assets/logo.PNG"));
+        assert!(pattern_matches(".png", "logo.png"));
+        assert!(!pattern_matches("png", "png"));
+        assert!(!pattern_matches("png", "logo.png.bak"));
+
+        // Globs without a `/` only match the file name.
+        assert!(pattern_matches("*.test.ts", "src/app.test.ts"));
+        assert!(pattern_matches("Makefile?", "build/Makefile2"));
+        assert!(!pattern_matches("src*", "src/main.rs"));
+
+        // Globs with a `/` match the whole path.
+        assert!(pattern_matches("docs/*.md", "docs/guide.md"));
+        assert!(!pattern_matches("docs/*.md", "src/docs/guide.md"));
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_matches(b"", b""));
+        assert!(glob_matches(b"*", b""));
+        assert!(glob_matches(b"a*c", b"abbbc"));
+        assert!(glob_matches(b"a?c", b"abc"));
+        assert!(!glob_matches(b"a?c", b"ac"));
+        assert!(!glob_matches(b"a*c", b"abcd"));
+        assert!(glob_matches(b"**a", b"bba"));
+    }
+
+    #[test]
+    fn mirrors_paths_in_the_scratch_directory() {
+        let dir = Path::new("/scratch");
+        assert_eq!(
+            scratch_path("/etc/hosts", dir),
+            Path::new("/scratch/etc/hosts")
+        );
+        assert_eq!(
+            scratch_path("./src/../main.rs", dir),
+            Path::new("/scratch/src/main.rs")
+        );
+        assert_eq!(scratch_path("main.rs", dir), Path::new("/scratch/main.rs"));
+    }
+
+    #[test]
+    fn copies_files_to_the_scratch_directory() {
+        let dir = env::temp_dir().join(format!("binocular-scratch-{}", process::id()));
+        let scratch_dir = dir.join("scratch");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file.txt");
+        fs::write(&file, "original").unwrap();
+        let file = file.to_str().unwrap();
+
+        let copy = scratch_copy(file, &scratch_dir);
+        let again = scratch_copy(file, &scratch_dir);
+        let contents = copy.as_ref().ok().map(fs::read_to_string);
+        let writable = copy.as_ref().is_ok_and(|copy| !is_read_only(copy));
+        fs::remove_dir_all(&dir).unwrap();
+        let (copy, again) = (copy.unwrap(), again.unwrap());
+        assert!(Path::new(&copy).starts_with(&scratch_dir));
+        assert_eq!(copy, again);
+        assert_eq!(contents.unwrap().unwrap(), "original");
+        assert!(writable);
+    }
+
+    #[test]
+    fn builds_commands_from_templates() {
+        let command =
+            command_from_template("code --goto {file}:{line}:{column}", "my file.rs", 12, 5)
+                .unwrap();
+        let command = command.as_std();
+        assert_eq!(command.get_program(), "code");
+        // The file name stays a single argument.
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["--goto", "my file.rs:12:5"]
+        );
+
+        assert!(command_from_template("  ", "file.rs", 1, 1).is_err());
+    }
+
+    #[test]
+    fn substitutes_placeholders_once() {
+        let placeholders = [("file", "{line}"), ("line", "12")];
+        assert_eq!(
+            substitute("{file}:{line} {} {other", &placeholders, |_, value| {
+                value.to_owned()
+            }),
+            "{line}:12 {} {other"
+        );
+    }
+
+    #[test]
+    fn passes_values_to_the_shell_as_parameters() {
+        let command = shell_command("echo {file} {}", &[("file", "$(rm -rf ~)"), ("", "a b")]);
+        if cfg!(windows) {
+            assert!(command.is_err());
+            return;
+        }
+        let command = command.unwrap();
+        assert_eq!(command.get_program(), "sh");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["-c", "echo \"${1}\" \"${2}\"", "sh", "$(rm -rf ~)", "a b"]
+        );
+    }
+
+    #[test]
+    fn quotes_values_for_cmd() {
+        let command = cmd_command("type {file}", &[("file", "my & file.txt")]).unwrap();
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["/C", "type \"my & file.txt\""]
+        );
+        assert!(cmd_command("type {file}", &[("file", "%PATH%")]).is_err());
+        assert!(cmd_command("type {file}", &[("file", "a\" & calc \"")]).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ratatui::{style::Style, text::Line, text::Span, widgets::ListItem};
+use std::{cmp::Reverse, io::ErrorKind, path::Path, process::Stdio, sync::Arc};
+use tokio::{process::Command, sync::mpsc::Sender, sync::OnceCell, task::JoinHandle};
+
+use crate::{
+    config::BufferEditor,
+    pickers::{
+        files, results_events, ListStyle, Location, Picker, PickerEvent, PickerItem, PreviewLoader,
+        SearchContext,
+    },
+    Config,
+};
+
+/// The picker's identifier.
+const ID: &str = "buffers";
+
+/// Neovim expression listing the listed buffers with a name, most recently used first,
+/// as `number<TAB>line<TAB>path` lines.
+const NEOVIM_LIST_EXPR: &str = r#"join(map(sort(filter(getbufinfo({'buflisted': 1}), {_, b -> b.name != ''}), {a, b -> b.lastused - a.lastused}), {_, b -> b.bufnr .. "\t" .. b.lnum .. "\t" .. b.name}), "\n")"#;
+
+/// A buffer open in the editor.
+#[derive(Clone)]
+pub struct BufferItem {
+    /// The editor's identifier for the buffer.
+    number: u32,
+    /// The line of the cursor in the buffer.
+    line_number: u32,
+    path: String,
+    /// The path displayed in the results list, relative to the current directory.
+    display: String,
+    /// How well the path matches the query.
+    score: i64,
+}
+
+impl PickerItem for BufferItem {
+    fn as_list_item(&self, config: &Config, _style: ListStyle) -> ListItem<'static> {
+        let file_style = Style::default().fg(config.filepath_color());
+        ListItem::new(Line::from(vec![
+            Span::styled(self.display.clone(), file_style),
+            Span::raw(format!(" [{}]", self.number)),
+        ]))
+    }
+
+    fn preview(&self, config: &Config) -> String {
+        files::preview_file(&self.path, config)
+    }
+
+    fn preview_loader(&self, config: &Config) -> Option<PreviewLoader> {
+        Some(files::preview_file_loader(&self.path, config))
+    }
+
+    fn text(&self) -> String {
+        self.path.clone()
+    }
+
+    fn key(&self) -> String {
+        self.path.clone()
+    }
+
+    fn score(&self) -> Option<i64> {
+        Some(self.score)
+    }
+
+    fn location(&self) -> Option<Location<'_>> {
+        Some(Location {
+            path: &self.path,
+            lines: self.line_number..=self.line_number,
+        })
+    }
+}
+
+/// Parses a line of [NEOVIM_LIST_EXPR]'s output.
+fn parse_neovim_buffer(line: &str, cwd: &Path) -> Option<BufferItem> {
+    let mut fields = line.splitn(3, '\t');
+    let number = fields.next()?.parse().ok()?;
+    let line_number = fields.next()?.parse().ok()?;
+    let path = fields.next()?.to_owned();
+    let display = Path::new(&path)
+        .strip_prefix(cwd)
+        .map_or(path.clone(), |relative| relative.display().to_string());
+
+    Some(BufferItem {
+        number,
+        line_number,
+        path,
+        display,
+        score: 0,
+    })
+}
+
+/// Runs an `nvim` command, reporting missing installations.
+async fn run_nvim(command: &mut Command) -> Result<String> {
+    let output = match command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await
+    {
+        Err(err) if err.kind() == ErrorKind::NotFound => bail!("Neovim is not installed"),
+        res => res.context("Failed to reach the Neovim server")?,
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "Failed to reach the Neovim server: {}",
+            stderr.lines().next().unwrap_or("unknown error")
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Lists the buffers open in the editor, most recently used first.
+async fn list_buffers(editor: BufferEditor, server: &str, cwd: &Path) -> Result<Vec<BufferItem>> {
+    match editor {
+        BufferEditor::Neovim => {
+            let output = run_nvim(
+                Command::new("nvim")
+                    .arg("--server")
+                    .arg(server)
+                    .arg("--remote-expr")
+                    .arg(NEOVIM_LIST_EXPR),
+            )
+            .await?;
+            Ok(output
+                .lines()
+                .filter_map(|line| parse_neovim_buffer(line, cwd))
+                .collect())
+        }
+    }
+}
+
+/// Returns the buffers matching the query, the best matches first. Buffers that match
+/// as well keep their recency order.
+fn filter_buffers(query: &str, buffers: &[BufferItem]) -> Vec<BufferItem> {
+    let mut matches = buffers
+        .iter()
+        .filter_map(|buffer| Some((files::fuzzy_score(query, &buffer.display)?, buffer)))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|(score, _)| Reverse(*score));
+    matches
+        .into_iter()
+        .map(|(score, buffer)| BufferItem {
+            score,
+            ..buffer.clone()
+        })
+        .collect()
+}
+
+/// A picker for switching between the buffers open in an editor, queried through the
+/// editor's server.
+pub struct BufferPicker {
+    editor: BufferEditor,
+    /// Address of the editor's server, if known.
+    server: Option<Arc<str>>,
+    /// The open buffers, listed on the first search.
+    buffers: Arc<OnceCell<Arc<Vec<BufferItem>>>>,
+    /// The search in progress, if any.
+    search: Option<JoinHandle<()>>,
+}
+
+impl BufferPicker {
+    /// Creates a new buffer picker.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            editor: config.buffers_editor(),
+            server: config.buffers_server().map(Arc::from),
+            buffers: Arc::default(),
+            search: None,
+        }
+    }
+}
+
+impl Picker<BufferItem> for BufferPicker {
+    fn id(&self) -> &'static str {
+        ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Buffers"
+    }
+
+    fn preview_title(&self) -> &'static str {
+        "Buffer Preview"
+    }
+
+    fn handle_input_change(
+        &mut self,
+        context: SearchContext,
+        sender: Sender<PickerEvent<BufferItem>>,
+    ) {
+        // Cancel the previous search, its results are outdated.
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+
+        let (editor, server) = (self.editor, self.server.clone());
+        let buffers = Arc::clone(&self.buffers);
+        self.search = Some(tokio::spawn(async move {
+            let results = match server {
+                Some(server) => buffers
+                    .get_or_try_init(|| async {
+                        list_buffers(editor, &server, &context.cwd)
+                            .await
+                            .map(Arc::new)
+                    })
+                    .await
+                    .map(|buffers| filter_buffers(&context.query, buffers)),
+                None => Err(anyhow!(
+                    "No editor server found, set `buffers.server` or run binocular in Neovim"
+                )),
+            };
+            let events = match results {
+                Ok(results) => results_events(results, context.options.max_results),
+                Err(err) => vec![
+                    PickerEvent::Results(Vec::new()),
+                    PickerEvent::Status(format!("{:#}", err)),
+                ],
+            };
+
+            // Send the results to the application. If this fails the application is
+            // shutting down, so there's no one to report the error to.
+            for event in events {
+                let _ = sender.send(event).await;
+            }
+        }));
+    }
+
+    fn try_handle_input_sync(
+        &mut self,
+        context: &SearchContext,
+    ) -> Option<Vec<PickerEvent<BufferItem>>> {
+        // Filtering the buffers is instant once they're listed.
+        let buffers = self.buffers.get()?;
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+        Some(results_events(
+            filter_buffers(&context.query, buffers),
+            context.options.max_results,
+        ))
+    }
+
+    fn handle_selection(&self, item: &BufferItem, _config: &Config) -> Result<()> {
+        // Switch to the buffer in the editor.
+        let server = self.server.as_deref().context("No editor server found")?;
+        match self.editor {
+            BufferEditor::Neovim => Command::new("nvim")
+                .arg("--server")
+                .arg(server)
+                .arg("--remote-send")
+                .arg(format!("<C-\\><C-N>:buffer {}<CR>", item.number))
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map(|_| ())
+                .context("Failed to switch buffers in Neovim"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_neovim_buffers() {
+        let buffer =
+            parse_neovim_buffer("3\t12\t/project/src/main.rs", Path::new("/project")).unwrap();
+
+        assert_eq!(buffer.number, 3);
+        assert_eq!(buffer.line_number, 12);
+        assert_eq!(buffer.path, "/project/src/main.rs");
+        assert_eq!(buffer.display, "src/main.rs");
+        assert!(parse_neovim_buffer("not a buffer", Path::new("/project")).is_none());
+    }
+}
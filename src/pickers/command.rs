@@ -0,0 +1,280 @@
+use anyhow::{bail, Context, Result};
+use ratatui::widgets::ListItem;
+use std::{iter, process::Stdio, sync::Arc};
+use tokio::{process::Command, sync::mpsc::Sender, sync::OnceCell, task::JoinHandle};
+
+use crate::{
+    config::ConfigCommand,
+    open,
+    pickers::{
+        results_events, ListStyle, Location, Picker, PickerEvent, PickerItem, PreviewLoader,
+        SearchContext,
+    },
+    Config,
+};
+
+/// The picker's identifier.
+const ID: &str = "command";
+
+/// A line of a command's output.
+#[derive(Clone)]
+pub struct CommandItem {
+    line: String,
+    fields: Vec<String>,
+    /// The text displayed in the results list.
+    display: String,
+    command: Arc<ConfigCommand>,
+}
+
+impl CommandItem {
+    /// Splits a line of the command's output into its fields.
+    fn new(line: &str, command: &Arc<ConfigCommand>) -> Self {
+        let fields = match &command.separator {
+            Some(separator) => line.split(separator.as_str()).map(str::to_owned).collect(),
+            None => line
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>(),
+        };
+        let display = match &command.display {
+            Some(display) => display
+                .iter()
+                .filter_map(|field| fields.get(field.checked_sub(1)?))
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("  "),
+            None => line.to_owned(),
+        };
+
+        Self {
+            line: line.to_owned(),
+            fields,
+            display,
+            command: Arc::clone(command),
+        }
+    }
+
+    /// Returns the field at the given position (starting at 1), if there's one.
+    fn field(&self, field: usize) -> Option<&str> {
+        self.fields.get(field.checked_sub(1)?).map(String::as_str)
+    }
+
+    /// Runs the preview command, returning its output.
+    fn run_preview(&self, template: &str) -> String {
+        // `{}` is the whole line, and `{1}`, `{2}`, … its fields.
+        let names = (1..=self.fields.len())
+            .map(|field| field.to_string())
+            .collect::<Vec<_>>();
+        let placeholders = iter::once(("", self.line.as_str()))
+            .chain(
+                names
+                    .iter()
+                    .map(String::as_str)
+                    .zip(self.fields.iter().map(String::as_str)),
+            )
+            .collect::<Vec<_>>();
+        let output = open::shell_command(template, &placeholders)
+            .and_then(|mut command| Ok(command.stderr(Stdio::null()).output()?));
+        match output {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
+            Err(err) => format!("Failed to run `{}`: {:#}", template, err),
+        }
+    }
+}
+
+impl PickerItem for CommandItem {
+    fn as_list_item(&self, _config: &Config, _style: ListStyle) -> ListItem<'static> {
+        ListItem::new(self.display.clone())
+    }
+
+    fn preview(&self, _config: &Config) -> String {
+        match &self.command.preview {
+            Some(template) => self.run_preview(template),
+            None => self.line.clone(),
+        }
+    }
+
+    fn preview_loader(&self, _config: &Config) -> Option<PreviewLoader> {
+        // The preview command runs in the background, it could be slow.
+        let template = self.command.preview.clone()?;
+        let item = self.clone();
+        Some(Box::new(move || item.run_preview(&template)))
+    }
+
+    fn text(&self) -> String {
+        self.line.clone()
+    }
+
+    fn key(&self) -> String {
+        self.line.clone()
+    }
+
+    fn location(&self) -> Option<Location<'_>> {
+        Some(Location {
+            path: self
+                .field(self.command.path?)
+                .filter(|path| !path.is_empty())?,
+            lines: 1..=1,
+        })
+    }
+}
+
+/// Runs the command and returns its output lines as items.
+async fn run(command: Arc<ConfigCommand>, query: &str) -> Result<Vec<CommandItem>> {
+    let output = Command::from(open::shell_command(&command.command, &[("query", query)])?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run `{}`", command.command))?;
+    if !output.status.success() && output.stdout.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}", stderr.lines().next().unwrap_or("Command failed"));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| CommandItem::new(line, &command))
+        .collect())
+}
+
+/// Returns the items containing the query (ignoring case).
+fn filter_items(items: &[CommandItem], query: &str) -> Vec<CommandItem> {
+    let query = query.to_lowercase();
+    items
+        .iter()
+        .filter(|item| item.line.to_lowercase().contains(&query))
+        .cloned()
+        .collect()
+}
+
+/// A picker listing the output lines of a configured shell command.
+pub struct CommandPicker {
+    command: Arc<ConfigCommand>,
+    /// The command's output, when it doesn't depend on the query.
+    items: Arc<OnceCell<Arc<Vec<CommandItem>>>>,
+    /// The search in progress, if any.
+    search: Option<JoinHandle<()>>,
+}
+
+impl CommandPicker {
+    /// Creates a picker for the configured command with the given name.
+    pub fn new(config: &Config, name: &str) -> Result<Self> {
+        Ok(Self {
+            command: Arc::new(config.command(name)?.clone()),
+            items: Arc::default(),
+            search: None,
+        })
+    }
+}
+
+impl Picker<CommandItem> for CommandPicker {
+    fn id(&self) -> &'static str {
+        ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Command"
+    }
+
+    fn preview_title(&self) -> &'static str {
+        "Command Preview"
+    }
+
+    fn handle_input_change(
+        &mut self,
+        context: SearchContext,
+        sender: Sender<PickerEvent<CommandItem>>,
+    ) {
+        // Cancel the previous search (killing its command), its results are outdated.
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+
+        let command = Arc::clone(&self.command);
+        let items = Arc::clone(&self.items);
+        self.search = Some(tokio::spawn(async move {
+            let results = if command.command.contains("{query}") {
+                run(command, &context.query).await
+            } else {
+                // Run the command once, then filter its lines by the query.
+                items
+                    .get_or_try_init(|| async { run(command, "").await.map(Arc::new) })
+                    .await
+                    .map(|items| filter_items(items, &context.query))
+            };
+
+            let events = match results {
+                Ok(results) => results_events(results, context.options.max_results),
+                Err(err) => vec![
+                    PickerEvent::Results(Vec::new()),
+                    PickerEvent::Status(format!("{:#}", err)),
+                ],
+            };
+
+            // Send the results to the application. If this fails the application is
+            // shutting down, so there's no one to report the error to.
+            for event in events {
+                let _ = sender.send(event).await;
+            }
+        }));
+    }
+
+    fn try_handle_input_sync(
+        &mut self,
+        context: &SearchContext,
+    ) -> Option<Vec<PickerEvent<CommandItem>>> {
+        // Filtering the command's output is instant once it ran.
+        let items = self.items.get()?;
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+        Some(results_events(
+            filter_items(items, &context.query),
+            context.options.max_results,
+        ))
+    }
+
+    fn handle_selection(&self, item: &CommandItem, config: &Config) -> Result<()> {
+        // Open the file in the configured field, if any.
+        match item.location() {
+            Some(location) => open::open(config, location.path, 1, 1),
+            None => bail!("No file to open, configure the `path` field of the command"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(separator: Option<&str>, display: Option<Vec<usize>>) -> Arc<ConfigCommand> {
+        Arc::new(ConfigCommand {
+            command: "true".to_owned(),
+            separator: separator.map(str::to_owned),
+            display,
+            preview: None,
+            path: Some(2),
+            unknown: Default::default(),
+        })
+    }
+
+    #[test]
+    fn splits_lines_into_fields() {
+        let item = CommandItem::new("  a  b\tc ", &command(None, None));
+        assert_eq!(item.fields, ["a", "b", "c"]);
+        assert_eq!(item.display, "  a  b\tc ");
+        assert_eq!(item.field(2), Some("b"));
+        assert_eq!(item.field(0), None);
+        assert_eq!(item.field(4), None);
+
+        // Empty fields are kept with a separator.
+        let item = CommandItem::new("a::src/main.rs", &command(Some(":"), Some(vec![3, 1, 9])));
+        assert_eq!(item.fields, ["a", "", "src/main.rs"]);
+        assert_eq!(item.display, "src/main.rs  a");
+        // Empty fields aren't files to open.
+        assert!(item.location().is_none());
+    }
+}
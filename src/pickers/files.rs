@@ -0,0 +1,465 @@
+use anyhow::{bail, Context, Result};
+use ratatui::{
+    style::{Color, Style},
+    text::Span,
+    widgets::ListItem,
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader, ErrorKind},
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{process::Command, sync::mpsc::Sender, sync::OnceCell, task::JoinHandle};
+
+use crate::{
+    config::{FileSort, PreviewLayout},
+    open,
+    pickers::{
+        results_events, ListStyle, Location, ModeFlag, Picker, PickerEvent, PickerItem,
+        PreviewLoader, SearchContext,
+    },
+    Action, Config,
+};
+
+/// The picker's identifier.
+const ID: &str = "files";
+
+/// Number of lines displayed in a file's preview.
+const PREVIEW_LINES: usize = 50;
+
+/// Maximum number of files that are sorted by modification time. The remaining
+/// matches keep their fuzzy score order, so that huge trees aren't stat'ed on every
+/// key press.
+const MAX_RECENCY_SORTED: usize = 5000;
+
+/// How long the modification times of the files sorted by recency are cached, so that
+/// typing a query doesn't stat the same files on every key press while files edited
+/// since then still move up.
+const MODIFIED_TTL: Duration = Duration::from_secs(5);
+
+/// Maximum number of files that are filtered synchronously, before the next render.
+const MAX_SYNC_FILES: usize = 2000;
+
+/// A file in the searched directory.
+#[derive(Clone)]
+pub struct FileItem {
+    path: String,
+    /// How well the path matches the query.
+    score: i64,
+}
+
+impl PickerItem for FileItem {
+    fn as_list_item(&self, config: &Config, _style: ListStyle) -> ListItem<'static> {
+        ListItem::new(Span::styled(
+            self.path.clone(),
+            Style::default().fg(config.filepath_color()),
+        ))
+    }
+
+    fn preview(&self, config: &Config) -> String {
+        preview_file(&self.path, config)
+    }
+
+    fn preview_loader(&self, config: &Config) -> Option<PreviewLoader> {
+        Some(preview_file_loader(&self.path, config))
+    }
+
+    fn text(&self) -> String {
+        self.path.clone()
+    }
+
+    fn key(&self) -> String {
+        self.path.clone()
+    }
+
+    fn score(&self) -> Option<i64> {
+        Some(self.score)
+    }
+
+    fn location(&self) -> Option<Location<'_>> {
+        Some(Location {
+            path: &self.path,
+            lines: 1..=1,
+        })
+    }
+}
+
+/// Previews the first lines of the file, unless it's too large.
+pub(super) fn preview_file(path: &str, config: &Config) -> String {
+    read_preview(path, config.preview_max_bytes())
+}
+
+/// Returns a function previewing the file in the background, see [preview_file].
+pub(super) fn preview_file_loader(path: &str, config: &Config) -> PreviewLoader {
+    let (path, max_bytes) = (path.to_owned(), config.preview_max_bytes());
+    Box::new(move || read_preview(&path, max_bytes))
+}
+
+/// Reads the first lines of the file, unless it has more than `max_bytes`.
+fn read_preview(path: &str, max_bytes: u64) -> String {
+    // Don't read huge files (which might not even have line breaks).
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.len() > max_bytes {
+            return format!(
+                "File too large to preview ({:.1} MB)",
+                metadata.len() as f64 / 1_000_000.0
+            );
+        }
+    }
+
+    match File::open(path) {
+        Ok(file) => BufReader::new(file)
+            .lines()
+            .take(PREVIEW_LINES)
+            .map_while(Result::ok)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Err(err) => format!("Failed to read {}: {}", path, err),
+    }
+}
+
+/// Shared state reused across searches.
+#[derive(Clone, Default)]
+struct FileCache {
+    /// The files in the current directory, listed on the first search.
+    files: Arc<OnceCell<Arc<Vec<String>>>>,
+    /// Modification times of the files sorted by recency.
+    modified: Arc<Mutex<ModifiedTimes>>,
+}
+
+/// Modification times of files, cached for [MODIFIED_TTL].
+#[derive(Default)]
+struct ModifiedTimes {
+    /// When the cached times were first read.
+    since: Option<Instant>,
+    times: HashMap<String, Option<SystemTime>>,
+}
+
+impl ModifiedTimes {
+    /// Returns when the file was last modified, reading it again once the cached times
+    /// expire.
+    fn get(&mut self, path: &str) -> Option<SystemTime> {
+        let now = Instant::now();
+        if self
+            .since
+            .is_none_or(|since| now.duration_since(since) > MODIFIED_TTL)
+        {
+            self.since = Some(now);
+            self.times.clear();
+        }
+        *self
+            .times
+            .entry(path.to_owned())
+            .or_insert_with(|| fs::metadata(path).and_then(|m| m.modified()).ok())
+    }
+}
+
+/// Lists the files in the directory, respecting ignore files.
+async fn list_files(cwd: &Path, env: &HashMap<String, String>) -> Result<Arc<Vec<String>>> {
+    let output = match Command::new(if cfg!(windows) { "rg.exe" } else { "rg" })
+        .current_dir(cwd)
+        .envs(env)
+        .arg("--files")
+        .arg("--color=never")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+    {
+        Err(err) if err.kind() == ErrorKind::NotFound => bail!("ripgrep is not installed"),
+        res => res.context("Failed to list files")?,
+    };
+
+    Ok(Arc::new(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.to_owned())
+            .collect(),
+    ))
+}
+
+/// Returns how well the path matches the query, or `None` if it doesn't contain all of
+/// the query's characters in order (ignoring case). Consecutive matches and matches at
+/// the start of a path component score higher, and shorter paths win ties.
+pub(super) fn fuzzy_score(query: &str, path: &str) -> Option<i64> {
+    let mut query_chars = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut last_match = None;
+    let mut prev_char = None;
+    for (i, c) in path.chars().enumerate() {
+        let Some(&query_char) = query_chars.peek() else {
+            break;
+        };
+        if c.to_lowercase().next() == Some(query_char) {
+            score += 1;
+            if last_match.is_some_and(|last| last + 1 == i) {
+                score += 4;
+            }
+            if prev_char.is_none_or(|prev| matches!(prev, '/' | '\\' | '_' | '-' | '.')) {
+                score += 2;
+            }
+            last_match = Some(i);
+            query_chars.next();
+        }
+        prev_char = Some(c);
+    }
+
+    query_chars
+        .peek()
+        .is_none()
+        .then(|| score * 100 - path.len() as i64)
+}
+
+/// Filters and sorts the files matching the query.
+fn filter_files(
+    query: &str,
+    files: &[String],
+    sort: FileSort,
+    modified: &Mutex<ModifiedTimes>,
+) -> Vec<FileItem> {
+    let mut matches = files
+        .iter()
+        .filter_map(|path| Some((fuzzy_score(query, path)?, path)))
+        .collect::<Vec<_>>();
+    match sort {
+        FileSort::Alphabetical => matches.sort_by_key(|(_, path)| *path),
+        FileSort::Score | FileSort::Recency => {
+            matches.sort_by_key(|(score, path)| (Reverse(*score), *path))
+        }
+    }
+
+    if sort == FileSort::Recency {
+        // Only sort the best matches, caching the modification times across searches.
+        let sorted = matches.len().min(MAX_RECENCY_SORTED);
+        let mut modified = modified.lock().unwrap_or_else(|err| err.into_inner());
+        matches[..sorted].sort_by_cached_key(|(_, path)| Reverse(modified.get(path)));
+    }
+
+    matches
+        .into_iter()
+        .map(|(score, path)| FileItem {
+            path: path.clone(),
+            score,
+        })
+        .collect()
+}
+
+/// A picker for finding files by name.
+pub struct FilePicker {
+    /// How matching files are ordered.
+    sort: FileSort,
+    /// The configured order, restored when the picker is reset.
+    default_sort: FileSort,
+    cache: FileCache,
+    /// Environment variables set for ripgrep.
+    env: Arc<HashMap<String, String>>,
+    /// The search in progress, if any.
+    search: Option<JoinHandle<()>>,
+}
+
+impl FilePicker {
+    /// Creates a new file picker.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            sort: config.files_sort(),
+            default_sort: config.files_sort(),
+            cache: FileCache::default(),
+            env: Arc::new(config.rg_env().clone()),
+            search: None,
+        }
+    }
+}
+
+impl Picker<FileItem> for FilePicker {
+    fn id(&self) -> &'static str {
+        ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Find Files"
+    }
+
+    fn icon(&self) -> Option<&'static str> {
+        Some("▤")
+    }
+
+    fn prompt_color(&self, config: &Config) -> Color {
+        config.filepath_color()
+    }
+
+    fn modes(&self) -> Vec<ModeFlag> {
+        match self.sort {
+            FileSort::Alphabetical => vec![ModeFlag("a-z".to_owned())],
+            FileSort::Score => Vec::new(),
+            FileSort::Recency => vec![ModeFlag("recent".to_owned())],
+        }
+    }
+
+    fn preview_title(&self) -> &'static str {
+        "File Preview"
+    }
+
+    fn layout(&self) -> PreviewLayout {
+        // File names are short, leaving room for a preview beside them.
+        PreviewLayout::Horizontal
+    }
+
+    fn handle_input_change(
+        &mut self,
+        context: SearchContext,
+        sender: Sender<PickerEvent<FileItem>>,
+    ) {
+        // Cancel the previous search, its results are outdated.
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+
+        let cache = self.cache.clone();
+        let (sort, env) = (self.sort, Arc::clone(&self.env));
+        self.search = Some(tokio::spawn(async move {
+            let files = cache
+                .files
+                .get_or_try_init(|| list_files(&context.cwd, &env))
+                .await;
+            let events = match files {
+                Ok(files) => {
+                    let files = Arc::clone(files);
+                    let results = tokio::task::spawn_blocking(move || {
+                        filter_files(&context.query, &files, sort, &cache.modified)
+                    })
+                    .await
+                    .unwrap_or_default();
+                    results_events(results, context.options.max_results)
+                }
+                Err(err) => vec![
+                    PickerEvent::Results(Vec::new()),
+                    PickerEvent::Status(format!("{:#}", err)),
+                ],
+            };
+
+            // Send the results to the application. If this fails the application is
+            // shutting down, so there's no one to report the error to.
+            for event in events {
+                let _ = sender.send(event).await;
+            }
+        }));
+    }
+
+    fn try_handle_input_sync(
+        &mut self,
+        context: &SearchContext,
+    ) -> Option<Vec<PickerEvent<FileItem>>> {
+        // Filtering a small listing is instant once it's cached. Sorting by recency
+        // might stat the files, so it stays in the background.
+        let files = self
+            .cache
+            .files
+            .get()
+            .filter(|files| files.len() <= MAX_SYNC_FILES && self.sort != FileSort::Recency)?;
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+        let results = filter_files(&context.query, files, self.sort, &self.cache.modified);
+        Some(results_events(results, context.options.max_results))
+    }
+
+    fn handle_selection(&self, item: &FileItem, config: &Config) -> Result<()> {
+        // Open the file with the configured command.
+        open::open(config, &item.path, 1, 1)
+    }
+
+    fn handle_action(&mut self, action: Action, _selected: Option<&FileItem>) -> bool {
+        match action {
+            Action::CycleSort => {
+                self.sort = self.sort.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sort = self.default_sort;
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn scores_fuzzy_matches() {
+        assert!(fuzzy_score("xyz", "src/main.rs").is_none());
+        // The characters must be in order.
+        assert!(fuzzy_score("sm", "src/main.rs").is_some());
+        assert!(fuzzy_score("ms", "src/main.rs").is_some());
+        assert!(fuzzy_score("nm", "src/main.rs").is_none());
+        // Case is ignored.
+        assert_eq!(
+            fuzzy_score("MAIN", "src/main.rs"),
+            fuzzy_score("main", "src/main.rs")
+        );
+        // Consecutive matches and matches at the start of a component score higher.
+        assert!(fuzzy_score("main", "src/main.rs") > fuzzy_score("main", "src/my_app_in.rs"));
+        assert!(fuzzy_score("app", "src/app.rs") > fuzzy_score("app", "src/map_pp.rs"));
+        // Shorter paths win ties.
+        assert!(fuzzy_score("app", "app.rs") > fuzzy_score("app", "src/app.rs"));
+        assert_eq!(fuzzy_score("", "src/app.rs"), Some(-10));
+    }
+
+    #[test]
+    fn filters_and_sorts_files() {
+        let files = ["src/tui.rs", "src/app.rs", "README.md", "benches/app.rs"].map(String::from);
+        let modified = Mutex::default();
+        let paths = |sort| {
+            filter_files("app", &files, sort, &modified)
+                .into_iter()
+                .map(|item| item.path)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(paths(FileSort::Score), ["src/app.rs", "benches/app.rs"]);
+        assert_eq!(
+            paths(FileSort::Alphabetical),
+            ["benches/app.rs", "src/app.rs"]
+        );
+        assert_eq!(
+            filter_files("", &files, FileSort::Alphabetical, &modified).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn sorts_files_by_recency() {
+        let dir = env::temp_dir().join(format!("binocular-recency-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (old, new) = (dir.join("old.rs"), dir.join("new.rs"));
+        fs::write(&old, "").unwrap();
+        File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        fs::write(&new, "").unwrap();
+        let files = [&old, &new].map(|path| path.display().to_string());
+
+        let modified = Mutex::default();
+        let results = filter_files("rs", &files, FileSort::Recency, &modified);
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(results[0].path, files[1]);
+        assert_eq!(results[1].path, files[0]);
+        // The times are cached for the next searches.
+        assert_eq!(modified.lock().unwrap().times.len(), 2);
+    }
+}
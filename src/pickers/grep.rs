@@ -1,73 +1,288 @@
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use ratatui::{
-    style::Style,
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::ListItem,
 };
-use std::{collections::HashMap, io::ErrorKind, iter};
-use tokio::{process::Command, sync::mpsc::Sender};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fs,
+    future::Future,
+    io::ErrorKind,
+    iter, mem,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    process::{Child, Command},
+    sync::mpsc::Sender,
+    task::JoinHandle,
+    time,
+};
 
 use crate::{
-    pickers::{Picker, PickerItem},
-    Config,
+    config::{self, CaseMatching, DedupeBy, OnEmpty},
+    git, open,
+    pickers::{
+        files, results_events, smart_case_contains, sort_results, ListStyle, Location, ModeFlag,
+        Picker, PickerEvent, PickerItem, PreviewLoader, SearchContext,
+    },
+    Action, Config,
 };
 
-/// Number of context lines kept before and after a matched line.
-const CTX_LINES: u16 = 4;
+/// The picker's identifier.
+const ID: &str = "grep";
 
 /// A `grep` match.
+#[derive(Clone)]
 pub struct GrepItem {
     filename: String,
-    line_number: u16,
+    line_number: u32,
+    /// Column where the match starts, as ripgrep reports it: a 1-based byte offset into
+    /// the line. It's 1 when unknown.
+    column: usize,
+    /// Length of the match on its first line in bytes, when known.
+    match_len: Option<usize>,
+    /// Last line of the match. Only differs from `line_number` for multiline matches.
+    end_line_number: u32,
     matched_line: String,
     context: String,
+    /// Number of collapsed results with the same matched line.
+    duplicates: usize,
+    /// Number of matches left out of the results after this one in its file, by the
+    /// limit of matches per file.
+    more_in_file: usize,
+    /// What the item represents.
+    kind: GrepItemKind,
+}
+
+/// The kinds of [GrepItem]s.
+#[derive(Clone, Copy, PartialEq)]
+enum GrepItemKind {
+    /// A match within a file.
+    Match,
+    /// A file whose name matched.
+    File,
+    /// A binary file with matches, which ripgrep only reports with a notice.
+    BinaryFile,
 }
 
 impl GrepItem {
     /// Creates a new `grep` item builder.
     fn builder(
         filename: impl Into<String>,
-        line_number: u16,
+        line_number: u32,
+        column: usize,
         matched_line: impl Into<String>,
     ) -> GrepItemBuilder {
         GrepItemBuilder {
             filename: filename.into(),
             line_number,
+            column,
+            end_line_number: line_number,
             matched_line: matched_line.into(),
-            pre_context: Vec::with_capacity(CTX_LINES.into()),
-            post_context: Vec::with_capacity(CTX_LINES.into()),
+            pre_context: Vec::new(),
+            post_context: Vec::new(),
+        }
+    }
+
+    /// Creates an item for a file whose name matched.
+    fn whole_file(filename: impl Into<String>) -> Self {
+        Self {
+            filename: filename.into(),
+            line_number: 1,
+            column: 1,
+            match_len: None,
+            end_line_number: 1,
+            matched_line: String::new(),
+            context: String::new(),
+            duplicates: 0,
+            more_in_file: 0,
+            kind: GrepItemKind::File,
+        }
+    }
+
+    /// Creates an item for a binary file with matches, from ripgrep's notice.
+    fn binary_file(filename: impl Into<String>, notice: impl Into<String>) -> Self {
+        Self {
+            kind: GrepItemKind::BinaryFile,
+            matched_line: notice.into(),
+            ..Self::whole_file(filename)
+        }
+    }
+
+    /// Returns the column where the match starts in characters, as editors count them.
+    fn char_column(&self) -> usize {
+        match self.matched_line.get(..self.column.saturating_sub(1)) {
+            Some(before) => before.chars().count() + 1,
+            None => self.column,
         }
     }
 }
 
 impl PickerItem for GrepItem {
-    fn as_list_item(&self, config: &Config) -> ListItem {
+    fn as_list_item(&self, config: &Config, style: ListStyle) -> ListItem<'static> {
+        // Matches only display their file name when they aren't grouped by file.
+        let show_filename = !style.grouped;
         let file_style = Style::default().fg(config.filepath_color());
-        ListItem::new(vec![Line::from(vec![
-            Span::styled(&self.filename, file_style),
-            Span::styled(format!(" [{}] ", self.line_number), file_style),
-            Span::raw(&self.matched_line),
-        ])])
+        match self.kind {
+            GrepItemKind::Match => {}
+            GrepItemKind::File => {
+                return ListItem::new(Span::styled(self.filename.clone(), file_style))
+            }
+            GrepItemKind::BinaryFile => {
+                return ListItem::new(Line::from(vec![
+                    Span::styled(self.filename.clone(), file_style),
+                    Span::styled(" [binary]", file_style),
+                ]))
+            }
+        }
+
+        let line_range = match (
+            show_filename,
+            style.line_numbers,
+            self.end_line_number > self.line_number,
+        ) {
+            (true, true, true) => format!(" [{}-{}] ", self.line_number, self.end_line_number),
+            (true, true, false) => format!(" [{}] ", self.line_number),
+            // Grouped under a file header, like `  12: text`.
+            (false, true, true) => format!("  {}-{}: ", self.line_number, self.end_line_number),
+            (false, true, false) => format!("  {}: ", self.line_number),
+            // Without line numbers, like `file.rs  text`.
+            (_, false, _) => "  ".to_owned(),
+        };
+
+        // Multiline matches display up to the configured number of lines, the rest is
+        // in the preview.
+        let mut lines = self.matched_line.lines();
+        let first_line = lines.next().unwrap_or_default();
+        let block = lines
+            .by_ref()
+            .take(config.grep_max_row_lines().saturating_sub(1))
+            .collect::<Vec<_>>();
+        let ellipsis = if lines.next().is_some() { " …" } else { "" };
+        let filename = if show_filename {
+            self.filename.as_str()
+        } else {
+            ""
+        };
+        // The block's lines are indented under the first one.
+        let indent = " ".repeat(filename.chars().count() + line_range.chars().count());
+
+        // Highlight the match when its extent is known.
+        let start = self.column.saturating_sub(1);
+        let (before, matched, after) = match self.match_len.and_then(|len| {
+            Some((
+                first_line.get(..start)?,
+                first_line.get(start..start + len)?,
+            ))
+        }) {
+            Some((before, matched)) => {
+                (before, matched, &first_line[before.len() + matched.len()..])
+            }
+            None => (first_line, "", ""),
+        };
+
+        let first = Line::from(vec![
+            Span::styled(filename.to_owned(), file_style),
+            Span::styled(line_range, file_style),
+            Span::raw(before.to_owned()),
+            Span::styled(
+                matched.to_owned(),
+                Style::default().fg(config.match_color()),
+            ),
+            Span::raw(after.to_owned()),
+            Span::raw(if block.is_empty() { ellipsis } else { "" }),
+            Span::styled(
+                if self.duplicates > 0 {
+                    format!(" (+{})", self.duplicates)
+                } else {
+                    String::new()
+                },
+                file_style,
+            ),
+            Span::styled(
+                if self.more_in_file > 0 {
+                    format!(" … ({} more in this file)", self.more_in_file)
+                } else {
+                    String::new()
+                },
+                file_style.add_modifier(Modifier::DIM),
+            ),
+        ]);
+        let block_len = block.len();
+        ListItem::new(
+            iter::once(first)
+                .chain(block.into_iter().enumerate().map(|(i, line)| {
+                    Line::from(vec![
+                        Span::raw(indent.clone()),
+                        Span::raw(line.to_owned()),
+                        Span::raw(if i + 1 == block_len { ellipsis } else { "" }),
+                    ])
+                }))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn preview(&self, config: &Config) -> String {
+        match self.kind {
+            GrepItemKind::Match => self.context.to_owned(),
+            GrepItemKind::File => files::preview_file(&self.filename, config),
+            GrepItemKind::BinaryFile => format!("Binary file — {}", self.matched_line),
+        }
+    }
+
+    fn preview_loader(&self, config: &Config) -> Option<PreviewLoader> {
+        (self.kind == GrepItemKind::File)
+            .then(|| files::preview_file_loader(&self.filename, config))
+    }
+
+    fn compact_preview(&self) -> Option<String> {
+        (self.kind == GrepItemKind::Match).then(|| self.matched_line.clone())
+    }
+
+    fn matches_filter(&self, needle: &str, case: CaseMatching) -> bool {
+        smart_case_contains(&self.filename, needle, case)
+            || smart_case_contains(&self.matched_line, needle, case)
     }
 
-    fn preview(&self) -> String {
-        self.context.to_owned()
+    fn key(&self) -> String {
+        format!("{}:{}", self.filename, self.line_number)
+    }
+
+    fn text(&self) -> String {
+        match self.kind {
+            GrepItemKind::Match => self.matched_line.clone(),
+            GrepItemKind::File | GrepItemKind::BinaryFile => self.filename.clone(),
+        }
+    }
+
+    fn location(&self) -> Option<Location<'_>> {
+        Some(Location {
+            path: &self.filename,
+            lines: self.line_number..=self.end_line_number,
+        })
     }
 }
 
 /// A builder for [GrepItem]s.
 struct GrepItemBuilder {
     filename: String,
-    line_number: u16,
+    line_number: u32,
+    column: usize,
+    end_line_number: u32,
     matched_line: String,
     pre_context: Vec<String>,
     post_context: Vec<String>,
 }
 
 impl GrepItemBuilder {
-    /// Adds context before the matched line to the [GrepItem].
-    fn add_pre_context(mut self, ctx: &HashMap<u16, &str>) -> Self {
-        for line in self.line_number.saturating_sub(CTX_LINES)..self.line_number {
+    /// Adds up to `before` lines of context before the matched line to the [GrepItem].
+    fn add_pre_context(mut self, ctx: &HashMap<u32, &str>, before: u16) -> Self {
+        for line in self.line_number.saturating_sub(before.into())..self.line_number {
             if let Some(ctx_line) = ctx.get(&line) {
                 self.pre_context.push(ctx_line.to_string());
             }
@@ -76,9 +291,19 @@ impl GrepItemBuilder {
         self
     }
 
-    /// Adds context after the matched line to the [GrepItem].
-    fn add_post_context(mut self, ctx: &HashMap<u16, &str>) -> Self {
-        for line in self.line_number + 1..=self.line_number + CTX_LINES {
+    /// Extends a multiline match with the next matched line.
+    fn add_matched_line(mut self, line: &str) -> Self {
+        self.end_line_number = self.end_line_number.saturating_add(1);
+        self.matched_line.push('\n');
+        self.matched_line.push_str(line);
+
+        self
+    }
+
+    /// Adds up to `after` lines of context after the matched line to the [GrepItem].
+    fn add_post_context(mut self, ctx: &HashMap<u32, &str>, after: u16) -> Self {
+        let first = self.end_line_number.saturating_add(1);
+        for line in first..=self.end_line_number.saturating_add(after.into()) {
             if let Some(ctx_line) = ctx.get(&line) {
                 self.post_context.push(ctx_line.to_string());
             }
@@ -87,150 +312,1802 @@ impl GrepItemBuilder {
         self
     }
 
-    /// Builds the [GrepItem].
-    fn build(self) -> GrepItem {
-        let context = self
+    /// Builds the [GrepItem], taking the size of its context from the `budget` of bytes.
+    /// Past the budget, the context is only the matched line.
+    fn build(self, budget: &mut usize) -> GrepItem {
+        let len = self
             .pre_context
-            .into_iter()
-            .chain(iter::once(self.matched_line.clone()))
-            .chain(self.post_context.into_iter())
-            .collect::<Vec<_>>()
-            .join("\n");
+            .iter()
+            .chain(&self.post_context)
+            .map(|line| line.len() + 1)
+            .sum::<usize>()
+            + self.matched_line.len();
+        let context = if len <= *budget {
+            *budget -= len;
+            self.pre_context
+                .into_iter()
+                .chain(iter::once(self.matched_line.clone()))
+                .chain(self.post_context)
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            *budget = 0;
+            self.matched_line.clone()
+        };
 
         GrepItem {
             filename: self.filename,
             line_number: self.line_number,
+            column: self.column,
+            match_len: None,
+            end_line_number: self.end_line_number,
             matched_line: self.matched_line,
             context,
+            duplicates: 0,
+            more_in_file: 0,
+            kind: GrepItemKind::Match,
+        }
+    }
+}
+
+/// Parses ripgrep's `--heading` output into [GrepItem]s, keeping up to `before` and
+/// `after` lines of context around each match. In `multiline` mode, a matched line
+/// continues the match of the previous line when it starts at the first column, which is
+/// how ripgrep prints the lines a match spans after its first one. Matches on adjacent
+/// lines that start further in are kept apart.
+///
+/// Matching lines can start with the column of the match (ripgrep's `--column`), like
+/// `12:5:text`. Without it, matches are assumed to start at the first column.
+///
+/// Once the context of the results adds up to `max_context_bytes`, the next results only
+/// keep their matched line, so that huge result sets stay within a bounded memory
+/// footprint.
+///
+/// File headings are tracked explicitly: the first line of the output and every line
+/// following an empty line is a file name, no matter what it looks like. This way paths
+/// like `123:weird/file.rs` are never mistaken for numbered lines.
+pub fn parse_rg_output(
+    output: &str,
+    before: u16,
+    after: u16,
+    multiline: bool,
+    max_context_bytes: usize,
+) -> Result<Vec<GrepItem>> {
+    // Parse each item, keeping track of the context lines around each match.
+    let mut budget = max_context_bytes;
+    let mut file = None;
+    let mut ctx = HashMap::with_capacity(before as usize + after as usize);
+    let mut builder: Option<GrepItemBuilder> = None;
+    let mut results = Vec::new();
+    for output_line in output.split('\n') {
+        if output_line.is_empty() {
+            // Changing files, so the current context is the post-context of the file's
+            // last item. Then clear the context and expect a new heading.
+            if let Some(builder) = builder.take() {
+                results.push(builder.add_post_context(&ctx, after).build(&mut budget));
+            }
+            file = None;
+            ctx.clear();
+            continue;
+        }
+
+        let Some(file) = file else {
+            // Must be a line with the file name.
+            file = Some(output_line);
+            continue;
+        };
+
+        // Ripgrep doesn't print the matches of binary files, only a notice.
+        if output_line.starts_with("binary file matches") {
+            if let Some(builder) = builder.take() {
+                results.push(builder.add_post_context(&ctx, after).build(&mut budget));
+            }
+            results.push(GrepItem::binary_file(file, output_line));
+            continue;
+        }
+        if output_line.starts_with("WARNING: stopped searching binary file") {
+            continue;
+        }
+
+        // Every other line is a numbered context or matching line.
+        let separator_index = output_line
+            .find(|c: char| !c.is_ascii_digit())
+            .filter(|&i| i > 0);
+        let (line_number, c, line) = match separator_index {
+            Some(i) if matches!(output_line.as_bytes()[i], b'-' | b':') => (
+                &output_line[..i],
+                output_line.as_bytes()[i],
+                &output_line[i + 1..],
+            ),
+            // This is technically impossible because we're matching ripgrep's
+            // format, but we'll handle it anyway.
+            _ => bail!(
+                "expected a context or a matching line but found: {}",
+                output_line
+            ),
+        };
+        let line_number = line_number
+            .parse::<u32>()
+            .context("output line should start with a line number")?;
+
+        // Matching lines start with the column of the match.
+        let (column, line) = if c == b':' {
+            split_column(line)
+        } else {
+            (1, line)
+        };
+
+        // Add the line to the context.
+        ctx.insert(line_number, line);
+
+        if c == b':' {
+            // In multiline mode, the lines a match spans after its first one start at the
+            // first column.
+            if let Some(current) = builder.take_if(|builder| {
+                multiline
+                    && column == 1
+                    && builder.filename == file
+                    && builder.end_line_number.checked_add(1) == Some(line_number)
+            }) {
+                builder = Some(current.add_matched_line(line));
+                continue;
+            }
+
+            // We have a match.
+            if let Some(builder) = builder {
+                // The current context is the post-context for the previous item (if any).
+                results.push(builder.add_post_context(&ctx, after).build(&mut budget));
+            }
+
+            // The current context is the pre-context for this item.
+            builder = Some(
+                GrepItem::builder(file, line_number, column, line).add_pre_context(&ctx, before),
+            );
+        }
+    }
+
+    // Add the last item.
+    if let Some(builder) = builder {
+        results.push(builder.add_post_context(&ctx, after).build(&mut budget));
+    }
+
+    Ok(results)
+}
+
+/// Splits the column off a matching line printed with `--column`, defaulting to 1.
+fn split_column(line: &str) -> (usize, &str) {
+    line.split_once(':')
+        .filter(|(column, _)| !column.is_empty() && column.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|(column, rest)| Some((column.parse().ok()?, rest)))
+        .unwrap_or((1, line))
+}
+
+/// Strips the SGR escape sequences from ripgrep's `--color=always` output, in which
+/// only matches are styled. Returns the plain output along with the length of the first
+/// match of each matching line, keyed by file and line number.
+fn strip_match_colors(output: &str) -> (String, HashMap<(String, u32), usize>) {
+    let mut plain = String::with_capacity(output.len());
+    let mut match_lens = HashMap::new();
+    let mut in_match = false;
+    let mut file = None;
+    for line in output.split('\n') {
+        let line_start = plain.len();
+        // Multiline matches continue from the previous line.
+        let mut first_match: Option<(usize, Option<usize>)> = in_match.then_some((0, None));
+        let mut rest = line;
+        while let Some(escape) = rest.find("\x1b[") {
+            plain.push_str(&rest[..escape]);
+            let Some(end) = rest[escape..].find('m') else {
+                rest = &rest[escape..];
+                break;
+            };
+            // Any style starts a match, and a reset ends it.
+            let params = &rest[escape + 2..escape + end];
+            in_match = !matches!(params, "" | "0");
+            let offset = plain.len() - line_start;
+            match (&mut first_match, in_match) {
+                (None, true) => first_match = Some((offset, None)),
+                (Some((_, end @ None)), false) => *end = Some(offset),
+                _ => {}
+            }
+            rest = &rest[escape + end + 1..];
+        }
+        plain.push_str(rest);
+        let plain_line = &plain[line_start..];
+
+        if plain_line.is_empty() {
+            file = None;
+        } else if file.is_none() {
+            file = Some(plain_line.to_owned());
+        } else if let (Some(file), Some((start, end))) = (&file, first_match) {
+            let line_number = plain_line
+                .split_once(':')
+                .and_then(|(number, _)| number.parse().ok());
+            if let Some(line_number) = line_number {
+                let len = end.unwrap_or(plain_line.len()) - start;
+                match_lens.insert((file.clone(), line_number), len);
+            }
+        }
+        plain.push('\n');
+    }
+    plain.pop();
+
+    (plain, match_lens)
+}
+
+/// Collapses the results with identical matched lines, keeping the first occurrence
+/// and counting the rest.
+fn dedupe_by_line(results: Vec<GrepItem>) -> Vec<GrepItem> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut deduped: Vec<GrepItem> = Vec::with_capacity(results.len());
+    for item in results {
+        // Only matches have lines to compare.
+        if item.kind != GrepItemKind::Match {
+            deduped.push(item);
+            continue;
+        }
+
+        match seen.get(&item.matched_line) {
+            Some(&i) => deduped[i].duplicates += 1,
+            None => {
+                seen.insert(item.matched_line.clone(), deduped.len());
+                deduped.push(item);
+            }
+        }
+    }
+
+    deduped
+}
+
+/// The paths searched by ripgrep.
+#[derive(Clone)]
+enum Scope {
+    /// The file binocular was launched from.
+    File(PathBuf),
+    /// The current directory.
+    Directory,
+    /// The root of the git repository containing the current directory.
+    Repository(PathBuf),
+    /// A directory drilled down into from a result.
+    Subdirectory(PathBuf),
+}
+
+/// Options used to run a `grep` search.
+#[derive(Clone)]
+struct GrepOptions {
+    /// How duplicate results are collapsed.
+    dedupe_by: DedupeBy,
+    /// Whether matches can span multiple lines (using ripgrep's PCRE2 engine).
+    multiline: bool,
+    /// How long ripgrep can run before being killed, if limited.
+    timeout: Option<Duration>,
+    /// The paths to search.
+    scope: Scope,
+    /// Whether to match file names instead of file contents.
+    filenames: bool,
+    /// Whether matched lines must be exactly equal to the query, taken literally.
+    exact: bool,
+    /// Whether to list the lines (or file names) that don't match the query instead.
+    inverted: bool,
+    /// How the case of the query is matched, unless the search is broadened.
+    case: CaseMatching,
+    /// Environment variables set for ripgrep.
+    env: HashMap<String, String>,
+    /// How recently files must have been modified to be searched, if limited.
+    changed_within: Option<Duration>,
+    /// What to do when a search has no results.
+    on_empty: OnEmpty,
+    /// Whether to also search ignored and hidden files, ignoring case.
+    broadened: bool,
+    /// The total size of the results' context, in bytes.
+    max_context_bytes: usize,
+    /// The maximum number of results per file, if limited.
+    max_matches_per_file: Option<usize>,
+    /// Whether to find the extent of the matches from ripgrep's colored output.
+    ansi_colors: bool,
+    /// How strongly results in recently modified files are moved up.
+    recency_weight: f64,
+    /// Whether the query is split into terms, see [parse_terms].
+    terms: bool,
+    /// How long listed files are searched by path before listing them again, if cached.
+    file_cache_ttl: Option<Duration>,
+    /// The files listed for content searches, shared across searches.
+    file_list: FileListCache,
+    /// The files recently changed, shared across searches.
+    changed_list: FileListCache,
+    /// The paths (or globs) the query restricts the search to, see [parse_path_filters].
+    path_filters: Vec<String>,
+}
+
+/// Maximum total size of the paths passed to ripgrep, in bytes. Command lines are limited
+/// to 32K characters on Windows, and elsewhere by `ARG_MAX` (256K on macOS), which also
+/// counts the environment. Larger listings of cached files have their directories walked
+/// by ripgrep instead.
+const MAX_ARGS_BYTES: usize = if cfg!(windows) { 30_000 } else { 200_000 };
+
+/// How long the recently changed files are searched before listing them again.
+const CHANGED_FILES_TTL: Duration = Duration::from_secs(10);
+
+/// The last listing of the files in scope, reused by content searches until it expires.
+#[derive(Clone, Default)]
+struct FileListCache(Arc<Mutex<Option<FileList>>>);
+
+/// The listed paths, the path filters they were listed with, and whether ignored and
+/// hidden files were included.
+type FileListKey = (Vec<PathBuf>, Vec<String>, bool);
+
+/// The files listed in some paths.
+struct FileList {
+    key: FileListKey,
+    listed_at: Instant,
+    files: Arc<Vec<PathBuf>>,
+}
+
+impl FileListCache {
+    /// Returns the files listed with the given key, unless they were listed more than
+    /// `ttl` ago.
+    fn get(&self, key: &FileListKey, ttl: Duration) -> Option<Arc<Vec<PathBuf>>> {
+        self.0
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|list| list.key == *key && list.listed_at.elapsed() < ttl)
+            .map(|list| Arc::clone(&list.files))
+    }
+
+    /// Remembers the files listed with the given key.
+    fn set(&self, key: FileListKey, files: Arc<Vec<PathBuf>>) {
+        *self.0.lock().unwrap() = Some(FileList {
+            key,
+            listed_at: Instant::now(),
+            files,
+        });
+    }
+
+    /// Forgets the listed files.
+    fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
+/// Returns whether the paths fit on ripgrep's command line, see [MAX_ARGS_BYTES].
+fn fit_in_args(paths: &[PathBuf]) -> bool {
+    paths
+        .iter()
+        .map(|path| path.as_os_str().len() + 1)
+        .sum::<usize>()
+        <= MAX_ARGS_BYTES
+}
+
+/// Reorders the results by blending ripgrep's order with the recency of their file:
+/// each result is scored by its position among the results and its file's position
+/// among the files sorted by modification time (both normalized to `0..=1`), weighted
+/// by `weight`. Files are only stat'ed once per search.
+fn rank_by_recency(results: Vec<GrepItem>, cwd: &Path, weight: f64) -> Vec<GrepItem> {
+    let mut files = results
+        .iter()
+        .map(|item| item.filename.as_str())
+        .collect::<Vec<_>>();
+    files.sort_unstable();
+    files.dedup();
+    let mut modified = files
+        .into_iter()
+        .map(|file| {
+            let time = fs::metadata(cwd.join(file))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            (file, time)
+        })
+        .collect::<Vec<_>>();
+    modified.sort_by_key(|(_, time)| Reverse(*time));
+    let file_count = (modified.len().max(2) - 1) as f64;
+    let recency = modified
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (file, _))| (file.to_owned(), rank as f64 / file_count))
+        .collect::<HashMap<_, _>>();
+
+    let result_count = (results.len().max(2) - 1) as f64;
+    let mut scored = results
+        .into_iter()
+        .enumerate()
+        .map(|(position, item)| {
+            let score = (1.0 - weight) * (position as f64 / result_count)
+                + weight * recency[&item.filename];
+            (score, item)
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Records the extent of the matches when it can be deduced from the query: the whole
+/// line in exact mode, or the query itself when it's a literal string. Inverted searches
+/// have no matches to record.
+fn mark_matches(results: &mut [GrepItem], query: &str, options: &GrepOptions) {
+    let literal = !query.contains(|c| "\\.+*?()|[]{}^$".contains(c));
+    // Inverted results are the lines without matches.
+    if options.multiline || options.inverted || !(options.exact || literal) {
+        return;
+    }
+
+    // Matches found from ripgrep's colors are exact already.
+    for item in results
+        .iter_mut()
+        .filter(|item| item.kind == GrepItemKind::Match && item.match_len.is_none())
+    {
+        let start = item.column.saturating_sub(1);
+        item.match_len = if options.exact {
+            Some(item.matched_line.len())
+        } else {
+            item.matched_line
+                .get(start..start + query.len())
+                .filter(|matched| matched.eq_ignore_ascii_case(query))
+                .map(str::len)
+        };
+    }
+}
+
+/// Keeps the first `max` results of each file, counting the ones left out on the last
+/// kept result of their file.
+fn limit_per_file(results: Vec<GrepItem>, max: usize) -> Vec<GrepItem> {
+    let mut counts = HashMap::<String, (usize, usize)>::new();
+    let mut limited = Vec::with_capacity(results.len());
+    for item in results {
+        let (kept, last) = counts
+            .entry(item.filename.clone())
+            .or_insert((0, usize::MAX));
+        if *kept < max {
+            *kept += 1;
+            *last = limited.len();
+            limited.push(item);
+        } else if let Some(last) = limited.get_mut(*last) {
+            last.more_in_file += 1;
+        }
+    }
+    limited
+}
+
+/// Returns a ripgrep command.
+fn rg() -> Command {
+    Command::new(if cfg!(windows) { "rg.exe" } else { "rg" })
+}
+
+/// Spawns a ripgrep command, piping its output.
+fn spawn_rg(command: &mut Command) -> Result<Child> {
+    command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Make sure that ripgrep is killed when the search is cancelled or times out.
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| {
+            if err.kind() == ErrorKind::NotFound {
+                anyhow!("ripgrep is not installed")
+            } else {
+                anyhow!("Failed to run ripgrep: {}", err)
+            }
+        })
+}
+
+/// Waits for the future to complete, within the search timeout.
+async fn within_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = T>,
+) -> Result<T> {
+    match timeout {
+        Some(timeout) => time::timeout(timeout, future)
+            .await
+            .map_err(|_| anyhow!("Search timed out after {}ms", timeout.as_millis())),
+        None => Ok(future.await),
+    }
+}
+
+/// Waits for ripgrep to finish, within the search timeout.
+async fn wait_rg(child: Child, timeout: Option<Duration>) -> Result<Output> {
+    let output = within_timeout(timeout, child.wait_with_output())
+        .await?
+        .context("Failed to run ripgrep")?;
+
+    // Ripgrep exits with 2 when an error occurred (like an invalid regex).
+    if output.status.code() == Some(2) && output.stdout.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{}", stderr.lines().next().unwrap_or("ripgrep failed"));
+    }
+
+    Ok(output)
+}
+
+/// Returns the ripgrep flags derived from the search options.
+fn mode_args(options: &GrepOptions) -> Vec<&'static str> {
+    let mut args = Vec::new();
+    if options.filenames {
+        args.push("--files");
+    } else {
+        if options.multiline {
+            args.push("--multiline");
+        }
+        // Terms are combined with lookaheads.
+        if options.multiline || uses_terms(options) {
+            args.push("--pcre2");
+        }
+        if options.exact {
+            args.extend(["--fixed-strings", "--line-regexp"]);
+        }
+        if options.inverted {
+            args.push("--invert-match");
+        }
+    }
+    if options.broadened {
+        args.extend(["--no-ignore", "--hidden"]);
+    }
+    args.push(case_arg(options));
+    args
+}
+
+/// Returns the ripgrep flag matching the case of the query. Broadened searches ignore it.
+fn case_arg(options: &GrepOptions) -> &'static str {
+    match (options.broadened, options.case) {
+        (true, _) | (false, CaseMatching::Insensitive) => "--ignore-case",
+        (false, CaseMatching::Smart) => "--smart-case",
+        (false, CaseMatching::Sensitive) => "--case-sensitive",
+    }
+}
+
+/// Returns whether the query is split into terms. Exact searches take it literally.
+fn uses_terms(options: &GrepOptions) -> bool {
+    options.terms && !options.exact
+}
+
+/// Splits the query into groups of alternative terms: whitespace separated terms are
+/// ANDed, and a term starting with `|` (or following a lone `|`) is ORed with the
+/// previous one. For example `foo |bar baz` matches lines containing `foo` or `bar`,
+/// and `baz`.
+fn parse_terms(query: &str) -> Vec<Vec<&str>> {
+    let mut groups: Vec<Vec<&str>> = Vec::new();
+    let mut alternative = false;
+    for term in query.split_whitespace() {
+        let (or, term) = match term.strip_prefix('|') {
+            Some(term) => (true, term),
+            None => (alternative, term),
+        };
+        alternative = term.is_empty();
+        match groups.last_mut() {
+            _ if term.is_empty() => {}
+            Some(group) if or => group.push(term),
+            _ => groups.push(vec![term]),
         }
     }
+    groups
+}
+
+/// Compiles the query's terms into a PCRE2 regex matching the lines containing all of
+/// them, or returns `None` if the query is a single term.
+fn compile_terms(query: &str) -> Option<String> {
+    let groups = parse_terms(query);
+    if groups.iter().map(Vec::len).sum::<usize>() < 2 {
+        return None;
+    }
+
+    let lookaheads = groups
+        .iter()
+        .map(|group| {
+            let alternatives = group
+                .iter()
+                .map(|term| format!("(?:{})", term))
+                .collect::<Vec<_>>();
+            format!("(?=.*?(?:{}))", alternatives.join("|"))
+        })
+        .collect::<String>();
+    Some(format!("^{}", lookaheads))
+}
+
+/// Describes how the query's terms are matched, like `foo AND (bar OR baz)`, or
+/// returns `None` if the query is a single term.
+fn describe_terms(query: &str) -> Option<String> {
+    let groups = parse_terms(query);
+    if groups.iter().map(Vec::len).sum::<usize>() < 2 {
+        return None;
+    }
+
+    let groups = groups
+        .iter()
+        .map(|group| match group[..] {
+            [term] => term.to_owned(),
+            _ => format!("({})", group.join(" OR ")),
+        })
+        .collect::<Vec<_>>();
+    Some(groups.join(" AND "))
+}
+
+/// Splits the leading `path:` filters off the query (like `path:src/ TODO`), returning
+/// their values and the rest of the query. A token without a value is part of the
+/// query, taken literally.
+fn parse_path_filters(query: &str) -> (Vec<&str>, &str) {
+    let mut filters = Vec::new();
+    let mut rest = query.trim_start();
+    while let Some(token) = rest.split_whitespace().next() {
+        match token.strip_prefix("path:") {
+            Some(path) if !path.is_empty() => {
+                filters.push(path);
+                rest = rest[token.len()..].trim_start();
+            }
+            _ => break,
+        }
+    }
+    if filters.is_empty() {
+        return (filters, query);
+    }
+    (filters, rest)
+}
+
+/// Returns the `--glob` arguments restricting the search to the path filters. Globs are
+/// passed as is, and other paths match the file or the directory's contents.
+fn glob_args(options: &GrepOptions) -> Vec<String> {
+    options
+        .path_filters
+        .iter()
+        .flat_map(|filter| {
+            if filter.contains(['*', '?', '[', '{']) {
+                vec![filter.clone()]
+            } else {
+                let path = filter.trim_start_matches("./").trim_end_matches('/');
+                vec![path.to_owned(), format!("{}/**", path)]
+            }
+        })
+        .map(|glob| format!("--glob={}", glob))
+        .collect()
 }
 
-pub struct GrepPicker;
+/// Runs ripgrep and parses its results.
+async fn search(context: &SearchContext, options: &GrepOptions) -> Result<Vec<GrepItem>> {
+    if context.query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let paths = match &options.scope {
+        Scope::File(path) | Scope::Repository(path) | Scope::Subdirectory(path) => {
+            vec![path.clone()]
+        }
+        Scope::Directory => Vec::new(),
+    };
+    // Only search the recently changed files, if limited. When there are too many of
+    // them to pass to ripgrep, the results of the other files are left out instead.
+    let mut changed = None;
+    let paths = match options.changed_within {
+        Some(within) => {
+            let files = changed_files(context, options, &paths, within).await?;
+            if files.is_empty() {
+                bail!(
+                    "No files changed in the last {}",
+                    config::format_age(within)
+                );
+            }
+            if fit_in_args(&files) {
+                files.to_vec()
+            } else {
+                changed = Some(files);
+                paths
+            }
+        }
+        None => paths,
+    };
+
+    // Search the cached listing of the files by path, sparing ripgrep the directory walk.
+    let cached = match options.file_cache_ttl {
+        Some(ttl) if options.changed_within.is_none() && !options.filenames => {
+            cached_files(context, options, &paths, ttl).await?
+        }
+        _ => None,
+    };
+    let paths = match cached {
+        Some(files) if files.is_empty() => return Ok(Vec::new()),
+        Some(files) => files.to_vec(),
+        None => paths,
+    };
+
+    let pattern = uses_terms(options)
+        .then(|| compile_terms(&context.query))
+        .flatten()
+        .unwrap_or_else(|| context.query.clone());
+    let mut results = if options.filenames {
+        search_filenames(context, options, &paths, &pattern).await?
+    } else {
+        search_contents(context, options, &paths, &pattern).await?
+    };
+    if let Some(changed) = changed {
+        let changed = changed.iter().map(PathBuf::as_path).collect::<HashSet<_>>();
+        results.retain(|item| changed.contains(Path::new(&item.filename)));
+    }
+    // ripgrep searches files in parallel, so their order changes from one search to the
+    // next.
+    sort_results(&mut results, |_| ());
+    if let Some(max) = options.max_matches_per_file {
+        results = limit_per_file(results, max);
+    }
+    if options.dedupe_by == DedupeBy::Line {
+        results = dedupe_by_line(results);
+    }
+    if options.recency_weight > 0.0 {
+        let (cwd, weight) = (context.cwd.clone(), options.recency_weight);
+        results = tokio::task::spawn_blocking(move || rank_by_recency(results, &cwd, weight))
+            .await
+            .context("Failed to rank the results")?;
+    }
+    mark_matches(&mut results, &context.query, options);
+
+    Ok(results)
+}
+
+/// Handles a search without results according to the `on_empty` option, returning the
+/// results of a broadened search (if any) and a message for the user.
+async fn handle_empty_search(
+    context: &SearchContext,
+    options: &GrepOptions,
+) -> (Result<Vec<GrepItem>>, Option<String>) {
+    match options.on_empty {
+        OnEmpty::None => (Ok(Vec::new()), None),
+        OnEmpty::Suggest => {
+            let hint = if context.query.chars().any(char::is_uppercase) {
+                "No results, try a lowercase query to ignore case"
+            } else {
+                "No results, note that ignored and hidden files aren't searched"
+            };
+            (Ok(Vec::new()), Some(hint.to_owned()))
+        }
+        OnEmpty::AutoBroaden => {
+            let options = GrepOptions {
+                broadened: true,
+                ..options.clone()
+            };
+            let results = search(context, &options).await;
+            let status = results
+                .as_ref()
+                .is_ok_and(|results| !results.is_empty())
+                .then(|| {
+                    "[broadened] No results, showing matches ignoring case and ignore files"
+                        .to_owned()
+                });
+            (results, status)
+        }
+    }
+}
+
+/// Returns the files in the given paths (or the current directory), listed again if the
+/// last listing is older than `ttl`. Returns `None` if there are too many files to pass
+/// them to ripgrep.
+async fn cached_files(
+    context: &SearchContext,
+    options: &GrepOptions,
+    paths: &[PathBuf],
+    ttl: Duration,
+) -> Result<Option<Arc<Vec<PathBuf>>>> {
+    let key = (
+        paths.to_vec(),
+        options.path_filters.clone(),
+        options.broadened,
+    );
+    let files = match options.file_list.get(&key, ttl) {
+        Some(files) => files,
+        None => {
+            let mut list = rg();
+            list.current_dir(&context.cwd)
+                .envs(&options.env)
+                .args(paths)
+                .args(glob_args(options));
+            if options.broadened {
+                list.arg("--no-ignore").arg("--hidden");
+            }
+            let output = wait_rg(spawn_rg(list.arg("--files"))?, options.timeout).await?;
+            let files = Arc::new(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(PathBuf::from)
+                    .collect::<Vec<_>>(),
+            );
+            options.file_list.set(key, Arc::clone(&files));
+            files
+        }
+    };
+
+    Ok(fit_in_args(&files).then_some(files))
+}
+
+/// Lists the files in the given paths (or the current directory) that were modified
+/// within the given duration, listed again if the last listing is older than
+/// [CHANGED_FILES_TTL].
+async fn changed_files(
+    context: &SearchContext,
+    options: &GrepOptions,
+    paths: &[PathBuf],
+    within: Duration,
+) -> Result<Arc<Vec<PathBuf>>> {
+    let key = (
+        paths.to_vec(),
+        options.path_filters.clone(),
+        options.broadened,
+    );
+    if let Some(files) = options.changed_list.get(&key, CHANGED_FILES_TTL) {
+        return Ok(files);
+    }
+
+    let mut list = rg();
+    list.current_dir(&context.cwd)
+        .envs(&options.env)
+        .args(paths)
+        .args(glob_args(options));
+    let output = wait_rg(spawn_rg(list.arg("--files"))?, options.timeout).await?;
+
+    let cwd = context.cwd.clone();
+    let since = SystemTime::now()
+        .checked_sub(within)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let files = tokio::task::spawn_blocking(move || {
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(PathBuf::from)
+            .filter(|path| {
+                fs::metadata(cwd.join(path))
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| modified >= since)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .context("Failed to list the changed files")?;
+    let files = Arc::new(files);
+    options.changed_list.set(key, Arc::clone(&files));
+    Ok(files)
+}
+
+/// Searches the contents of the given paths (or the current directory) for the pattern.
+async fn search_contents(
+    context: &SearchContext,
+    options: &GrepOptions,
+    paths: &[PathBuf],
+    pattern: &str,
+) -> Result<Vec<GrepItem>> {
+    let child = spawn_rg(
+        rg().current_dir(&context.cwd)
+            .envs(&options.env)
+            .args(paths)
+            .args(glob_args(options))
+            .args(mode_args(options))
+            .arg("--regexp")
+            .arg(pattern)
+            .args(if options.ansi_colors {
+                // Only style the matches, to find their boundaries.
+                &[
+                    "--color=always",
+                    "--colors=path:none",
+                    "--colors=line:none",
+                    "--colors=column:none",
+                    "--colors=match:none",
+                    "--colors=match:style:bold",
+                ][..]
+            } else {
+                &["--color=never"][..]
+            })
+            .arg("--heading")
+            // Searching a single file omits its name otherwise.
+            .arg("--with-filename")
+            .arg("--line-number")
+            .arg("--column")
+            .arg("--no-context-separator")
+            .arg(format!(
+                "--before-context={}",
+                context.options.context_before
+            ))
+            .arg(format!("--after-context={}", context.options.context_after)),
+    )?;
+    let output = wait_rg(child, options.timeout).await?;
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    if !options.ansi_colors {
+        return parse_rg_output(
+            &output,
+            context.options.context_before,
+            context.options.context_after,
+            options.multiline,
+            options.max_context_bytes,
+        );
+    }
+    let (output, match_lens) = strip_match_colors(&output);
+    let mut results = parse_rg_output(
+        &output,
+        context.options.context_before,
+        context.options.context_after,
+        options.multiline,
+        options.max_context_bytes,
+    )?;
+    for item in &mut results {
+        item.match_len = match_lens
+            .get(&(item.filename.clone(), item.line_number))
+            .copied();
+    }
+    Ok(results)
+}
+
+/// Searches the names of the files in the given paths (or the current directory), like
+/// `rg --files | rg <pattern>`.
+async fn search_filenames(
+    context: &SearchContext,
+    options: &GrepOptions,
+    paths: &[PathBuf],
+    pattern: &str,
+) -> Result<Vec<GrepItem>> {
+    let mut list = rg();
+    list.current_dir(&context.cwd)
+        .envs(&options.env)
+        .args(paths)
+        .args(glob_args(options));
+    if options.broadened {
+        list.arg("--no-ignore").arg("--hidden");
+    }
+    let mut files = spawn_rg(list.arg("--files"))?;
+    let files_stdout: Stdio = files
+        .stdout
+        .take()
+        .context("Failed to list files")?
+        .try_into()
+        .context("Failed to list files")?;
+
+    let child = spawn_rg(
+        rg().current_dir(&context.cwd)
+            .envs(&options.env)
+            .args(uses_terms(options).then_some("--pcre2"))
+            .args(options.inverted.then_some("--invert-match"))
+            .arg("--regexp")
+            .arg(pattern)
+            .arg("--color=never")
+            .arg(case_arg(options))
+            .stdin(files_stdout),
+    )?;
+    // Both are waited for together, so that the listing's errors are read while it runs
+    // and a full pipe can't block it, within a single timeout.
+    let (output, listing) = within_timeout(options.timeout, async {
+        tokio::join!(wait_rg(child, None), wait_rg(files, None))
+    })
+    .await?;
+    let output = output?;
+    // The listing's errors (like an invalid path) are only reported when nothing was
+    // found, listing errors on some files (like unreadable directories) shouldn't hide
+    // the others.
+    if output.stdout.is_empty() {
+        listing.context("Failed to list files")?;
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(GrepItem::whole_file)
+        .collect())
+}
+
+/// Returns the debounce for the given number of files, scaling logarithmically from `min`
+/// for 1,000 files or less up to `max` for 100,000 or more.
+fn scaled_debounce(min: Duration, max: Duration, files: usize) -> Duration {
+    let scale = ((files.max(1) as f64).log10() - 3.0).clamp(0.0, 2.0) / 2.0;
+    min + max.saturating_sub(min).mul_f64(scale)
+}
+
+pub struct GrepPicker {
+    options: GrepOptions,
+    /// The configured limit on how recently searched files were modified, which can
+    /// be toggled.
+    changed_within: Option<Duration>,
+    /// The minimum and (when adaptive) maximum delay before searching.
+    debounce: (Duration, Option<Duration>),
+    /// The number of files in the current directory, once counted.
+    file_count: Arc<OnceLock<usize>>,
+    /// Whether the files were already counted (or are being counted).
+    counting_files: bool,
+    /// The file binocular was launched from, if any.
+    file: Option<PathBuf>,
+    /// The scope to restore after drilling down into a result's directory.
+    unscoped: Option<Scope>,
+    /// The options and unscoped scope the picker was launched with, restored when it's
+    /// reset.
+    launch: (GrepOptions, Option<Scope>),
+    /// How the terms of the current query are matched, if it has several.
+    terms: Option<String>,
+    /// The path filters of the current query.
+    path_filters: Vec<String>,
+    /// The search in progress, if any.
+    search: Option<JoinHandle<()>>,
+}
+
+impl GrepPicker {
+    /// Creates a new `grep` picker.
+    pub fn new(config: &Config) -> Self {
+        let options = GrepOptions {
+            dedupe_by: config.dedupe_by(),
+            multiline: config.grep_multiline(),
+            timeout: config.grep_timeout(),
+            scope: Scope::Directory,
+            filenames: false,
+            exact: false,
+            inverted: false,
+            case: config.grep_case(),
+            env: config.rg_env().clone(),
+            changed_within: config.grep_changed_within(),
+            on_empty: config.grep_on_empty(),
+            broadened: false,
+            max_context_bytes: config.grep_max_context_bytes(),
+            max_matches_per_file: config.grep_max_matches_per_file(),
+            ansi_colors: config.grep_ansi_colors(),
+            recency_weight: config.grep_recency_weight(),
+            terms: config.grep_terms(),
+            file_cache_ttl: config.grep_file_cache_ttl(),
+            file_list: FileListCache::default(),
+            changed_list: FileListCache::default(),
+            path_filters: Vec::new(),
+        };
+        Self {
+            launch: (options.clone(), None),
+            options,
+            changed_within: config.grep_changed_within(),
+            debounce: (config.debounce_min(), config.debounce_max()),
+            file_count: Arc::default(),
+            counting_files: false,
+            file: None,
+            unscoped: None,
+            terms: None,
+            path_filters: Vec::new(),
+            search: None,
+        }
+    }
+
+    /// Returns how long to wait after the input changes before searching. With an
+    /// adaptive debounce, the delay scales with the number of files (see
+    /// [scaled_debounce]).
+    /// The files are counted once in the background, until then the minimum is used.
+    fn debounce(&mut self) -> Duration {
+        let (min, Some(max)) = self.debounce else {
+            return self.debounce.0;
+        };
+
+        let Some(&count) = self.file_count.get() else {
+            if !self.counting_files {
+                self.counting_files = true;
+                let file_count = Arc::clone(&self.file_count);
+                let mut command = rg();
+                command
+                    .envs(&self.options.env)
+                    .arg("--files")
+                    .kill_on_drop(true);
+                tokio::spawn(async move {
+                    if let Ok(output) = command.output().await {
+                        let _ =
+                            file_count.set(output.stdout.iter().filter(|b| **b == b'\n').count());
+                    }
+                });
+            }
+            return min;
+        };
+
+        scaled_debounce(min, max, count)
+    }
+
+    /// Sets how the case of the query is matched, overriding the configuration.
+    pub fn with_case(mut self, case: CaseMatching) -> Self {
+        self.options.case = case;
+        self.launched()
+    }
+
+    /// Limits the search to the given directory, until the scope is changed.
+    pub fn with_directory(mut self, dir: PathBuf) -> Self {
+        self.unscoped = Some(mem::replace(
+            &mut self.options.scope,
+            Scope::Subdirectory(dir),
+        ));
+        self.launched()
+    }
+
+    /// Makes the current options the ones restored when the picker is reset.
+    fn launched(mut self) -> Self {
+        self.launch = (self.options.clone(), self.unscoped.clone());
+        self
+    }
+
+    /// Sets the file binocular was launched from, which can then be searched on its own.
+    pub fn with_file(mut self, file: PathBuf) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    /// Switches to the next search scope that's available: the current file,
+    /// the current directory, or the repository.
+    fn cycle_scope(&mut self) {
+        let file = self.file.clone().map(Scope::File);
+        let repository = || git::root().map(Scope::Repository);
+        self.options.scope = match self.options.scope {
+            Scope::File(_) | Scope::Subdirectory(_) => Scope::Directory,
+            Scope::Directory => repository().or(file).unwrap_or(Scope::Directory),
+            Scope::Repository(_) => file.unwrap_or(Scope::Directory),
+        };
+        self.unscoped = None;
+    }
+
+    /// Narrows the search scope to the directory containing the result's file,
+    /// remembering the scope to restore with [Action::ResetScope].
+    fn scope_to_directory(&mut self, item: &GrepItem) {
+        let dir = match Path::new(&item.filename).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let scope = mem::replace(&mut self.options.scope, Scope::Subdirectory(dir));
+        self.unscoped.get_or_insert(scope);
+    }
+}
 
 impl Picker<GrepItem> for GrepPicker {
+    fn id(&self) -> &'static str {
+        ID
+    }
+
     fn name(&self) -> &'static str {
         "Live Grep"
     }
 
+    fn icon(&self) -> Option<&'static str> {
+        Some("⌕")
+    }
+
+    fn case(&self) -> CaseMatching {
+        if self.options.broadened {
+            CaseMatching::Insensitive
+        } else {
+            self.options.case
+        }
+    }
+
+    fn modes(&self) -> Vec<ModeFlag> {
+        let terms = self.terms.clone().filter(|_| uses_terms(&self.options));
+        let scope = match &self.options.scope {
+            Scope::File(_) => Some("file".to_owned()),
+            Scope::Directory => None,
+            Scope::Repository(_) => Some("repo".to_owned()),
+            Scope::Subdirectory(dir) => Some(format!("in {}", dir.display())),
+        };
+        let paths = (!self.path_filters.is_empty())
+            .then(|| format!("path {}", self.path_filters.join(", ")));
+        let multiline = self.options.multiline.then_some("multiline");
+        let filenames = self.options.filenames.then_some("names");
+        let exact = self.options.exact.then_some("exact");
+        let inverted = self.options.inverted.then_some("inverted");
+        let changed = self
+            .options
+            .changed_within
+            .map(|within| format!("changed <{}", config::format_age(within)));
+        let toggles = multiline
+            .into_iter()
+            .chain(filenames)
+            .chain(exact)
+            .chain(inverted)
+            .map(str::to_owned);
+        scope
+            .into_iter()
+            .chain(paths)
+            .chain(toggles)
+            .chain(changed)
+            .chain(terms)
+            .map(ModeFlag)
+            .collect()
+    }
+
+    fn flags(&self) -> Vec<String> {
+        mode_args(&self.options)
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+
     fn preview_title(&self) -> &'static str {
         "Grep Preview"
     }
 
-    fn handle_input_change(&self, input: String, sender: Sender<Vec<GrepItem>>) {
-        tokio::spawn(async move {
-            let results = if input.is_empty() {
-                Vec::new()
-            } else {
-                match Command::new(if cfg!(windows) { "rg.exe" } else { "rg" })
-                    .arg(input)
-                    .arg("--color=never")
-                    .arg("--heading")
-                    .arg("--line-number")
-                    .arg("--smart-case")
-                    .arg("--no-context-separator")
-                    .arg(format!("--context={}", CTX_LINES))
-                    .output()
-                    .await
-                {
-                    Err(err) => {
-                        if err.kind() == ErrorKind::NotFound {
-                            bail!("ripgrep is not installed");
-                        } else {
-                            bail!("Failed to run ripgrep: {}", err);
-                        }
-                    }
-                    Ok(output) => {
-                        // Split the results.
-                        let output = String::from_utf8_lossy(&output.stdout);
-                        let mut output = output.split('\n');
-
-                        // Parse each item, keeping track of the context lines around each match.
-                        let mut file = output
-                            .next()
-                            .context("first output line should be a file name")?;
-                        let mut ctx = HashMap::with_capacity(CTX_LINES as usize * 2);
-                        let mut builder: Option<GrepItemBuilder> = None;
-                        let mut results = Vec::new();
-                        for output_line in output {
-                            if output_line.starts_with(|c: char| c.is_ascii_digit()) {
-                                match output_line
-                                    .trim_start_matches(|c: char| c.is_ascii_digit())
-                                    .chars()
-                                    .next()
-                                {
-                                    Some(c @ ('-' | ':')) => {
-                                        let (line_number, line) =
-                                            output_line.split_once(c).context(
-                                                "output line should contain the matched character",
-                                            )?;
-                                        let line_number = line_number
-                                            .parse::<u16>()
-                                            .context("output line should start with digits")?;
-
-                                        // Add the line to the context.
-                                        ctx.insert(line_number, line);
-
-                                        if c == ':' {
-                                            // We have a match.
-                                            if let Some(builder) = builder {
-                                                // The current context is the post-context for the previous item
-                                                // (if any).
-                                                results
-                                                    .push(builder.add_post_context(&ctx).build());
-                                            }
-
-                                            // The current context is the pre-context for this item.
-                                            builder = Some(
-                                                GrepItem::builder(file, line_number, line)
-                                                    .add_pre_context(&ctx),
-                                            );
-                                        }
-                                    }
-                                    // This is technically impossible because we're matching ripgrep's
-                                    // format, but we'll handle it anyway.
-                                    _ => bail!(
-                                        "expected a context or a matching line but found: {}",
-                                        output_line
-                                    ),
-                                }
-                            } else if !output_line.is_empty() {
-                                // Must be a line with the file name.
-                                file = output_line;
-                            } else {
-                                // Changing files, so clear the context.
-                                ctx.clear();
-                            }
-                        }
-
-                        // Add the last item.
-                        if let Some(builder) = builder {
-                            results.push(builder.add_post_context(&ctx).build());
-                        }
-                        results
-                    }
+    fn handle_input_change(
+        &mut self,
+        context: SearchContext,
+        sender: Sender<PickerEvent<GrepItem>>,
+    ) {
+        // Cancel the previous search (killing its ripgrep process), its results are outdated.
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+        // The path filters restrict the files searched for the rest of the query.
+        let (path_filters, query) = parse_path_filters(&context.query);
+        let path_filters = path_filters
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        self.terms = describe_terms(query);
+        self.path_filters = path_filters.clone();
+        let context = SearchContext {
+            query: query.to_owned(),
+            ..context
+        };
+
+        let options = GrepOptions {
+            path_filters,
+            ..self.options.clone()
+        };
+        let debounce = self.debounce();
+        self.search = Some(tokio::spawn(async move {
+            // Wait for the user to stop typing, the search is cancelled on new input.
+            if !debounce.is_zero() {
+                time::sleep(debounce).await;
+            }
+
+            let (results, status) = match search(&context, &options).await {
+                Ok(results) if results.is_empty() && !context.query.is_empty() => {
+                    handle_empty_search(&context, &options).await
                 }
+                results => (results, None),
+            };
+            let events = match results {
+                Ok(results) => results_events(results, context.options.max_results)
+                    .into_iter()
+                    .chain(status.map(PickerEvent::Status))
+                    .collect(),
+                Err(err) => vec![
+                    PickerEvent::Results(Vec::new()),
+                    PickerEvent::Status(format!("{:#}", err)),
+                ],
             };
 
-            // Send the results to the application.
-            sender
-                .send(results)
-                .await
-                .context("Failed to send grep results")
-        });
+            // Send the results to the application. If this fails the application is
+            // shutting down, so there's no one to report the error to.
+            for event in events {
+                let _ = sender.send(event).await;
+            }
+        }));
     }
 
-    fn handle_selection(&self, item: &GrepItem) -> Result<()> {
-        // Open the `grep` match in VS Code.
-        Command::new(if cfg!(windows) {
-            "code-insiders.cmd"
-        } else {
-            "code-insiders"
-        })
-        .arg("--goto")
-        .arg(format!("{}:{}", item.filename, item.line_number))
-        .spawn()
-        .context("Failed to open file in VS Code")
-        .map(|_| ())
+    fn handle_selection(&self, item: &GrepItem, config: &Config) -> Result<()> {
+        // Open the `grep` match with the configured command.
+        open::open(config, &item.filename, item.line_number, item.char_column())
+    }
+
+    fn handle_action(&mut self, action: Action, selected: Option<&GrepItem>) -> bool {
+        match action {
+            Action::ScopeToDirectory => match selected {
+                Some(item) => {
+                    self.scope_to_directory(item);
+                    true
+                }
+                None => false,
+            },
+            Action::ResetScope => match self.unscoped.take() {
+                Some(scope) => {
+                    self.options.scope = scope;
+                    true
+                }
+                None => false,
+            },
+            Action::ToggleMultiline => {
+                self.options.multiline = !self.options.multiline;
+                true
+            }
+            Action::CycleScope => {
+                self.cycle_scope();
+                true
+            }
+            Action::ToggleFilenames => {
+                self.options.filenames = !self.options.filenames;
+                true
+            }
+            Action::ToggleExact => {
+                self.options.exact = !self.options.exact;
+                true
+            }
+            Action::ToggleInvert => {
+                self.options.inverted = !self.options.inverted;
+                true
+            }
+            Action::ToggleChanged if self.changed_within.is_some() => {
+                self.options.changed_within = match self.options.changed_within {
+                    Some(_) => None,
+                    None => self.changed_within,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn reset(&mut self) {
+        (self.options, self.unscoped) = self.launch.clone();
+        self.options.file_list.clear();
+        self.options.changed_list.clear();
+        self.terms = None;
+        self.path_filters.clear();
+        if let Some(search) = self.search.take() {
+            search.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pickers::SearchOptions;
+
+    #[test]
+    fn parses_filenames_with_leading_digits() {
+        let output = "123_file.rs\n1:foo\n\n42\n7-bar\n8:foo\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].filename, "123_file.rs");
+        assert_eq!(results[0].line_number, 1);
+        assert_eq!(results[1].filename, "42");
+        assert_eq!(results[1].line_number, 8);
+        assert_eq!(results[1].context, "bar\nfoo");
+    }
+
+    #[test]
+    fn parses_filenames_with_colons() {
+        let output = "123:weird/file.rs\n3:foo: bar\n\n4-2:file.rs\n1-ctx\n2:foo\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].filename, "123:weird/file.rs");
+        assert_eq!(results[0].line_number, 3);
+        assert_eq!(results[0].matched_line, "foo: bar");
+        assert_eq!(results[1].filename, "4-2:file.rs");
+        assert_eq!(results[1].line_number, 2);
+    }
+
+    #[test]
+    fn merges_consecutive_lines_in_multiline_mode() {
+        let output = "file.rs\n1:fn foo() {\n2:}\n3-\n";
+
+        let results = parse_rg_output(output, 4, 4, true, usize::MAX).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].line_number, 1);
+        assert_eq!(results[0].end_line_number, 2);
+        assert_eq!(results[0].matched_line, "fn foo() {\n}");
+
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn parses_lines_past_u16() {
+        let output = "big.rs\n65535:fn foo() {\n65536:}\n65537-\n";
+
+        let results = parse_rg_output(output, 4, 4, true, usize::MAX).unwrap();
+        assert_eq!(results[0].line_number, 65535);
+        assert_eq!(results[0].end_line_number, 65536);
+        assert_eq!(results[0].context, "fn foo() {\n}\n");
+    }
+
+    #[test]
+    fn separates_matches_on_adjacent_lines_in_multiline_mode() {
+        let output = "file.rs\n1:4:foo(\n2:1:bar)\n3:5:foo(\n";
+
+        let results = parse_rg_output(output, 0, 0, true, usize::MAX).unwrap();
+        let lines = results
+            .iter()
+            .map(|item| (item.line_number, item.end_line_number))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(1, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn rejects_unnumbered_content_lines() {
+        assert!(parse_rg_output("file.rs\nfoo\n", 4, 4, false, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn parses_empty_output() {
+        assert!(parse_rg_output("", 4, 4, false, usize::MAX)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn parses_multiple_files() {
+        let output = "a.rs\n1:foo\n2-ctx\n\nb.rs\n1-ctx\n2:foo\n3:foo\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        let locations = results
+            .iter()
+            .map(|item| (item.filename.as_str(), item.line_number))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, [("a.rs", 1), ("b.rs", 2), ("b.rs", 3)]);
+    }
+
+    #[test]
+    fn parses_match_columns() {
+        let output = "a.rs\n1-ctx\n2:5:foo\n3:bar\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results[0].column, 5);
+        assert_eq!(results[0].context, "ctx\nfoo\nbar");
+        assert_eq!(results[1].column, 1);
+    }
+
+    #[test]
+    fn counts_match_columns_in_characters() {
+        let output = "a.rs
+1:7:héhé foo
+2:70000:foo
+";
+        let results = parse_rg_output(output, 0, 0, false, usize::MAX).unwrap();
+
+        assert_eq!(results[0].column, 7);
+        assert_eq!(results[0].char_column(), 5);
+        assert_eq!(results[1].column, 70000);
+    }
+
+    #[test]
+    fn context_does_not_cross_files() {
+        let output = "a.rs\n1:foo\n2-a2\n\nb.rs\n1-b1\n2:foo\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results[0].context, "foo\na2");
+        assert_eq!(results[1].context, "b1\nfoo");
+    }
+
+    #[test]
+    fn context_is_limited_to_ctx_lines() {
+        let output = "a.rs\n1-l1\n2-l2\n3-l3\n4:foo\n5-l5\n6-l6\n7-l7\n";
+        let results = parse_rg_output(output, 2, 2, false, usize::MAX).unwrap();
+
+        assert_eq!(results[0].context, "l2\nl3\nfoo\nl5\nl6");
+    }
+
+    #[test]
+    fn context_can_be_asymmetric() {
+        let output = "a.rs\n1-l1\n2-l2\n3-l3\n4:foo\n5-l5\n6-l6\n7-l7\n";
+        let results = parse_rg_output(output, 1, 3, false, usize::MAX).unwrap();
+
+        assert_eq!(results[0].context, "l3\nfoo\nl5\nl6\nl7");
+    }
+
+    #[test]
+    fn shares_context_between_close_matches() {
+        let output = "a.rs\n1:foo\n2-l2\n3:foo\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results[0].context, "foo\nl2\nfoo");
+        assert_eq!(results[1].context, "foo\nl2\nfoo");
+    }
+
+    #[test]
+    fn flushes_the_last_item_with_its_post_context() {
+        let output = "a.rs\n1:foo\n2-l2\n3-l3";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].context, "foo\nl2\nl3");
+    }
+
+    #[test]
+    fn parses_binary_file_notices() {
+        let output = "a.rs\n1:foo\n\nimage.png\nbinary file matches (found \"\\0\" byte around offset 12)\n\nb.rs\n2:foo\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[1].kind == GrepItemKind::BinaryFile);
+        assert_eq!(results[1].filename, "image.png");
+        assert_eq!(results[1].line_number, 1);
+        assert_eq!(
+            results[1].preview(&Config::default()),
+            "Binary file — binary file matches (found \"\\0\" byte around offset 12)"
+        );
+        assert_eq!(results[2].filename, "b.rs");
+    }
+
+    #[test]
+    fn skips_binary_file_warnings() {
+        let output =
+            "data.bin\n1:foo\nWARNING: stopped searching binary file after match (found \"\\0\" byte around offset 9)\n";
+        let results = parse_rg_output(output, 4, 4, false, usize::MAX).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].matched_line, "foo");
+    }
+
+    #[test]
+    fn derives_flags_from_modes() {
+        let mut picker = GrepPicker::new(&Config::default());
+        assert_eq!(picker.flags(), ["--smart-case"]);
+
+        picker.options.exact = true;
+        picker.options.broadened = true;
+        assert_eq!(
+            picker.flags(),
+            [
+                "--fixed-strings",
+                "--line-regexp",
+                "--no-ignore",
+                "--hidden",
+                "--ignore-case"
+            ]
+        );
+
+        assert!(picker.handle_action(Action::ToggleInvert, None));
+        assert_eq!(picker.flags()[2], "--invert-match");
+        assert!(picker
+            .modes()
+            .iter()
+            .any(|ModeFlag(mode)| mode == "inverted"));
+
+        picker.options.filenames = true;
+        assert_eq!(
+            picker.flags(),
+            ["--files", "--no-ignore", "--hidden", "--ignore-case"]
+        );
+    }
+
+    #[test]
+    fn limits_the_size_of_the_context() {
+        let output = "a.rs\n1-ctx\n2:foo\n3-ctx\n\nb.rs\n1-ctx\n2:bar\n3-ctx\n";
+        let results = parse_rg_output(output, 1, 1, false, 15).unwrap();
+
+        assert_eq!(results[0].context, "ctx\nfoo\nctx");
+        assert_eq!(results[1].context, "bar");
+    }
+
+    #[test]
+    fn ranks_results_in_recent_files_first() {
+        let dir = std::env::temp_dir().join(format!("binocular-recency-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, age) in [("old.rs", 3600), ("new.rs", 60)] {
+            fs::File::create(dir.join(file))
+                .and_then(|f| f.set_modified(SystemTime::now() - Duration::from_secs(age)))
+                .unwrap();
+        }
+        let output = "old.rs\n1:foo\n2:foo\n\nnew.rs\n1:foo\n";
+        let results = parse_rg_output(output, 0, 0, false, usize::MAX).unwrap();
+        let order = |results: Vec<GrepItem>| {
+            results
+                .into_iter()
+                .map(|item| format!("{}:{}", item.filename, item.line_number))
+                .collect::<Vec<_>>()
+        };
+
+        let unweighted = order(rank_by_recency(results.clone(), &dir, 0.0));
+        let blended = order(rank_by_recency(results.clone(), &dir, 0.4));
+        let recent = order(rank_by_recency(results, &dir, 1.0));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(unweighted, ["old.rs:1", "old.rs:2", "new.rs:1"]);
+        assert_eq!(blended, ["old.rs:1", "new.rs:1", "old.rs:2"]);
+        assert_eq!(recent, ["new.rs:1", "old.rs:1", "old.rs:2"]);
+    }
+
+    #[test]
+    fn marks_literal_matches() {
+        let mut options = GrepPicker::new(&Config::default()).options;
+        let output = "a.rs\n1:3:a Foo b\n";
+        let mut results = parse_rg_output(output, 0, 0, false, usize::MAX).unwrap();
+        mark_matches(&mut results, "foo", &options);
+        assert_eq!(results[0].match_len, Some(3));
+
+        let mut results = parse_rg_output(output, 0, 0, false, usize::MAX).unwrap();
+        mark_matches(&mut results, "fo+", &options);
+        assert_eq!(results[0].match_len, None);
+
+        options.exact = true;
+        mark_matches(&mut results, "a Foo b", &options);
+        assert_eq!(results[0].match_len, Some(7));
+    }
+
+    #[test]
+    fn strips_match_colors() {
+        let output = "a.rs\n3:5:let \x1b[0m\x1b[1mfoo\x1b[0m = \x1b[1mfoo\x1b[0m;\n4-ctx\n";
+        let (plain, match_lens) = strip_match_colors(output);
+
+        assert_eq!(plain, "a.rs\n3:5:let foo = foo;\n4-ctx\n");
+        assert_eq!(match_lens.len(), 1);
+        assert_eq!(match_lens[&("a.rs".to_owned(), 3)], 3);
+    }
+
+    #[test]
+    fn scopes_to_the_directory_of_a_result() {
+        let mut picker = GrepPicker::new(&Config::default());
+        let modes = |picker: &GrepPicker| {
+            picker
+                .modes()
+                .into_iter()
+                .map(|ModeFlag(mode)| mode)
+                .collect::<Vec<_>>()
+        };
+
+        let item = GrepItem::whole_file("src/pickers/grep.rs");
+        assert!(picker.handle_action(Action::ScopeToDirectory, Some(&item)));
+        assert_eq!(modes(&picker), ["in src/pickers"]);
+
+        // Drilling down again still restores the original scope.
+        let item = GrepItem::whole_file("main.rs");
+        assert!(picker.handle_action(Action::ScopeToDirectory, Some(&item)));
+        assert_eq!(modes(&picker), ["in ."]);
+        assert!(picker.handle_action(Action::ResetScope, None));
+        assert!(modes(&picker).is_empty());
+        assert!(!picker.handle_action(Action::ResetScope, None));
+    }
+
+    #[test]
+    fn resets_to_the_launch_options() {
+        let mut picker = GrepPicker::new(&Config::default()).with_directory("src".into());
+        let modes = |picker: &GrepPicker| {
+            picker
+                .modes()
+                .into_iter()
+                .map(|ModeFlag(mode)| mode)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(picker.handle_action(Action::ResetScope, None));
+        assert!(picker.handle_action(Action::ToggleExact, None));
+        assert_eq!(modes(&picker), ["exact"]);
+
+        picker.reset();
+        assert_eq!(modes(&picker), ["in src"]);
+        assert!(picker.handle_action(Action::ResetScope, None));
+    }
+
+    #[test]
+    fn compiles_query_terms() {
+        assert_eq!(
+            parse_terms("foo |bar  baz"),
+            [vec!["foo", "bar"], vec!["baz"]]
+        );
+        assert_eq!(parse_terms("foo | bar"), [vec!["foo", "bar"]]);
+        assert_eq!(compile_terms("foo"), None);
+        assert_eq!(
+            compile_terms("foo |bar baz").as_deref(),
+            Some("^(?=.*?(?:(?:foo)|(?:bar)))(?=.*?(?:(?:baz)))")
+        );
+        assert_eq!(
+            describe_terms("foo |bar baz").as_deref(),
+            Some("(foo OR bar) AND baz")
+        );
+    }
+
+    #[test]
+    fn parses_path_filters() {
+        assert_eq!(
+            parse_path_filters("path:src/ path:*.rs  TODO  fix"),
+            (vec!["src/", "*.rs"], "TODO  fix")
+        );
+        // Filters only lead the query, and need a value.
+        assert_eq!(
+            parse_path_filters(" foo path:src/"),
+            (vec![], " foo path:src/")
+        );
+        assert_eq!(parse_path_filters("path: foo"), (vec![], "path: foo"));
+        assert_eq!(parse_path_filters("path:src/"), (vec!["src/"], ""));
+
+        let options = GrepOptions {
+            path_filters: vec!["./src/".to_owned(), "*.rs".to_owned()],
+            ..GrepPicker::new(&Config::default()).options
+        };
+        assert_eq!(
+            glob_args(&options),
+            ["--glob=src", "--glob=src/**", "--glob=*.rs"]
+        );
+    }
+
+    #[test]
+    fn orders_results_the_same_on_every_search() {
+        let keys = |output| {
+            let mut results = parse_rg_output(output, 0, 0, false, usize::MAX).unwrap();
+            sort_results(&mut results, |_| ());
+            results.iter().map(GrepItem::key).collect::<Vec<_>>()
+        };
+        let first = keys("src/b.rs\n3:foo\n9:foo\n\nsrc/a.rs\n7:foo\n");
+        let second = keys("src/a.rs\n7:foo\n\nsrc/b.rs\n3:foo\n9:foo\n");
+        assert_eq!(first, ["src/a.rs:7", "src/b.rs:3", "src/b.rs:9"]);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn limits_the_matches_per_file() {
+        let output = "a.rs\n1:foo\n2:foo\n3:foo\n\nb.rs\n1:foo\n";
+        let results = limit_per_file(parse_rg_output(output, 0, 0, false, usize::MAX).unwrap(), 2);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].line_number, 2);
+        assert_eq!(results[1].more_in_file, 1);
+        assert_eq!(results[2].filename, "b.rs");
+        assert_eq!(results[2].more_in_file, 0);
+    }
+
+    #[test]
+    fn scales_the_debounce_with_the_number_of_files() {
+        let (min, max) = (Duration::from_millis(20), Duration::from_millis(220));
+        assert_eq!(scaled_debounce(min, max, 0), min);
+        assert_eq!(scaled_debounce(min, max, 1_000), min);
+        assert_eq!(
+            scaled_debounce(min, max, 10_000),
+            Duration::from_millis(120)
+        );
+        assert_eq!(scaled_debounce(min, max, 100_000), max);
+        assert_eq!(scaled_debounce(min, max, 5_000_000), max);
+        // A maximum below the minimum keeps the minimum.
+        assert_eq!(scaled_debounce(max, min, 100_000), max);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires ripgrep"]
+    async fn reports_listing_errors_when_searching_filenames() {
+        let dir = std::env::temp_dir().join(format!("binocular-filenames-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.rs"), "").unwrap();
+        let context = SearchContext {
+            query: "main".to_owned(),
+            options: SearchOptions::default(),
+            cwd: dir.clone(),
+        };
+        let options = GrepPicker::new(&Config::default()).options;
+
+        let found = search_filenames(&context, &options, &[], "main").await;
+        let missing =
+            search_filenames(&context, &options, &[PathBuf::from("missing")], "main").await;
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(found.unwrap()[0].filename, "main.rs");
+        let Err(err) = missing else {
+            panic!("listing a missing path succeeded");
+        };
+        assert!(format!("{:#}", err).contains("missing"));
+    }
+
+    #[test]
+    fn caches_file_listings_until_they_expire() {
+        let cache = FileListCache::default();
+        let key = (vec![PathBuf::from("src")], Vec::new(), false);
+        let files = Arc::new(vec![PathBuf::from("src/main.rs")]);
+        assert!(cache.get(&key, CHANGED_FILES_TTL).is_none());
+
+        cache.set(key.clone(), Arc::clone(&files));
+        assert_eq!(cache.get(&key, CHANGED_FILES_TTL), Some(Arc::clone(&files)));
+        // Listings in other paths or with other options aren't reused.
+        assert!(cache
+            .get(&(Vec::new(), Vec::new(), false), CHANGED_FILES_TTL)
+            .is_none());
+        assert!(cache
+            .get(&(key.0.clone(), Vec::new(), true), CHANGED_FILES_TTL)
+            .is_none());
+
+        let expired = FileListCache(Arc::new(Mutex::new(Some(FileList {
+            key: key.clone(),
+            listed_at: Instant::now() - CHANGED_FILES_TTL,
+            files,
+        }))));
+        assert!(expired.get(&key, CHANGED_FILES_TTL).is_none());
+    }
+
+    #[test]
+    fn limits_the_size_of_paths_passed_to_ripgrep() {
+        assert!(fit_in_args(&[PathBuf::from("src/main.rs")]));
+        let paths = vec![PathBuf::from("a".repeat(99)); MAX_ARGS_BYTES / 100];
+        assert!(fit_in_args(&paths));
+        assert!(!fit_in_args(&[paths, vec![PathBuf::from("b")]].concat()));
+    }
+
+    #[tokio::test]
+    async fn walks_directories_with_too_many_cached_files() {
+        let context = SearchContext {
+            query: String::new(),
+            options: SearchOptions::default(),
+            cwd: PathBuf::from("."),
+        };
+        let options = GrepPicker::new(&Config::default()).options;
+        let ttl = Duration::from_secs(60);
+        let key = (Vec::new(), Vec::new(), false);
+
+        let files = vec![PathBuf::from("a".repeat(99)); MAX_ARGS_BYTES / 100];
+        options.file_list.set(key.clone(), Arc::new(files.clone()));
+        let cached = cached_files(&context, &options, &[], ttl).await.unwrap();
+        assert_eq!(cached.as_deref(), Some(&files));
+
+        let files = vec![PathBuf::from("a".repeat(99)); MAX_ARGS_BYTES / 100 + 1];
+        options.file_list.set(key, Arc::new(files));
+        let cached = cached_files(&context, &options, &[], ttl).await.unwrap();
+        assert!(cached.is_none());
     }
 }
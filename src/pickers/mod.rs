@@ -1,33 +1,365 @@
 use anyhow::Result;
-use ratatui::widgets::ListItem;
+use ratatui::{style::Color, widgets::ListItem};
+use std::{ops::RangeInclusive, path::PathBuf};
 use tokio::sync::mpsc::Sender;
 
-use crate::Config;
-pub use grep::{GrepItem, GrepPicker};
+use crate::{
+    config::{CaseMatching, PreviewLayout},
+    Action, Config,
+};
+pub use buffers::{BufferItem, BufferPicker};
+pub use command::{CommandItem, CommandPicker};
+pub use files::{FileItem, FilePicker};
+pub use grep::{parse_rg_output, GrepItem, GrepPicker};
 
+mod buffers;
+mod command;
+pub(crate) mod files;
 mod grep;
 
 /// An item returned by a Binocular picker.
-pub trait PickerItem {
-    /// Returns a `ratatui` list item representing the match.
-    fn as_list_item(&self, config: &Config) -> ListItem;
+pub trait PickerItem: Clone + Send + 'static {
+    /// Returns a `ratatui` list item representing the match. List items are cached
+    /// until the results or their style change, so they own their contents.
+    fn as_list_item(&self, config: &Config, style: ListStyle) -> ListItem<'static>;
 
     /// Returns a preview of the match to be displayed in the TUI.
-    fn preview(&self) -> String;
+    fn preview(&self, config: &Config) -> String;
+
+    /// Returns a function building the item's preview, for previews that are slow to
+    /// build (like reading a file). They're then built in the background and cached
+    /// until the item's file is modified. Defaults to `None`, building the preview with
+    /// [PickerItem::preview] while rendering.
+    fn preview_loader(&self, _config: &Config) -> Option<PreviewLoader> {
+        None
+    }
+
+    /// Returns a compact preview of the match without its context, like the matched line
+    /// alone. Defaults to `None`, previewing the item in full.
+    fn compact_preview(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the item's text as displayed in the results list (like a path or a matched
+    /// line), passed to user-defined actions.
+    fn text(&self) -> String;
+
+    /// Returns a key identifying the item across searches. Defaults to the item's
+    /// location (like `src/main.rs:12`), or to its text for items without one.
+    fn key(&self) -> String {
+        match self.location() {
+            Some(location) => format!("{}:{}", location.path, location.lines.start()),
+            None => self.text(),
+        }
+    }
+
+    /// Returns whether the item matches the needle of the in-memory results filter,
+    /// matching case like the picker's search does. Defaults to a substring match
+    /// against the item's text.
+    fn matches_filter(&self, needle: &str, case: CaseMatching) -> bool {
+        smart_case_contains(&self.text(), needle, case)
+    }
+
+    /// Returns how well the item matches the query, for pickers that score their
+    /// results. Defaults to `None`.
+    fn score(&self) -> Option<i64> {
+        None
+    }
+
+    /// Returns the location of the item within a file, if it has one.
+    fn location(&self) -> Option<Location<'_>> {
+        None
+    }
+}
+
+/// Builds the preview of a [PickerItem] in the background.
+pub type PreviewLoader = Box<dyn FnOnce() -> String + Send>;
+
+/// The location of a [PickerItem] within a file.
+pub struct Location<'a> {
+    /// Path to the file.
+    pub path: &'a str,
+    /// Lines of the file covered by the item.
+    pub lines: RangeInclusive<u32>,
+}
+
+/// How the results are displayed in the results list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ListStyle {
+    /// Whether the results are grouped by file, in which case their file is displayed in
+    /// a header above them rather than by each of them.
+    pub grouped: bool,
+    /// Whether the results display their line number.
+    pub line_numbers: bool,
+}
+
+/// A short label for an active picker mode (like `multiline`).
+pub struct ModeFlag(pub String);
+
+/// A message sent by a picker to the application.
+pub enum PickerEvent<I: PickerItem> {
+    /// Replaces the current results.
+    Results(Vec<I>),
+    /// Reports how many results were left out of the last results by the limit.
+    Truncated(usize),
+    /// Shows a status message to the user.
+    Status(String),
+}
+
+/// Returns the events reporting the results, truncated to `max_results` if needed.
+fn results_events<I: PickerItem>(
+    mut results: Vec<I>,
+    max_results: Option<usize>,
+) -> Vec<PickerEvent<I>> {
+    let truncated = max_results.map_or(0, |max_results| results.len().saturating_sub(max_results));
+    results.truncate(results.len() - truncated);
+    let mut events = vec![PickerEvent::Results(results)];
+    if truncated > 0 {
+        events.push(PickerEvent::Truncated(truncated));
+    }
+    events
+}
+
+/// Sorts the results by `key`, breaking ties by their location (their file, then their
+/// first line) and then by their key. Results found in a different order, like by a
+/// parallel search, are thus sorted the same way every time.
+fn sort_results<I: PickerItem, K: Ord>(results: &mut [I], mut key: impl FnMut(&I) -> K) {
+    results.sort_by_cached_key(|item| {
+        let location = item
+            .location()
+            .map(|location| (location.path.to_owned(), *location.lines.start()));
+        (key(item), location, item.key())
+    });
+}
+
+/// Returns whether the haystack contains the needle, matching case as given. Smart case
+/// ignores case unless the needle has uppercase characters, like ripgrep's `--smart-case`.
+pub fn smart_case_contains(haystack: &str, needle: &str, case: CaseMatching) -> bool {
+    let sensitive = match case {
+        CaseMatching::Smart => needle.chars().any(char::is_uppercase),
+        CaseMatching::Sensitive => true,
+        CaseMatching::Insensitive => false,
+    };
+    if sensitive {
+        haystack.contains(needle)
+    } else {
+        haystack.to_lowercase().contains(&needle.to_lowercase())
+    }
+}
+
+/// An action that only runs once the user confirms it, like one that modifies files.
+pub struct PendingAction {
+    /// The question shown to the user.
+    prompt: String,
+    /// Runs the action.
+    action: Box<dyn FnOnce() -> Result<()> + Send>,
+}
+
+impl PendingAction {
+    /// Creates an action that runs `action` if the user answers yes to `prompt`.
+    pub fn new(
+        prompt: impl Into<String>,
+        action: impl FnOnce() -> Result<()> + Send + 'static,
+    ) -> Self {
+        Self {
+            prompt: prompt.into(),
+            action: Box::new(action),
+        }
+    }
+
+    /// Returns the question shown to the user.
+    pub(crate) fn prompt(&self) -> &str {
+        &self.prompt
+    }
+
+    /// Runs the confirmed action.
+    pub(crate) fn run(self) -> Result<()> {
+        (self.action)()
+    }
+}
+
+/// Everything a picker needs to run a search, snapshotted when the input changes.
+#[derive(Clone, Debug)]
+pub struct SearchContext {
+    /// The search input.
+    pub query: String,
+    /// The application's search options.
+    pub options: SearchOptions,
+    /// The directory searches run in.
+    pub cwd: PathBuf,
+}
+
+/// The search options held by the application (rather than by the picker), as they
+/// were when the search started.
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    /// Number of context lines to keep before matches.
+    pub context_before: u16,
+    /// Number of context lines to keep after matches.
+    pub context_after: u16,
+    /// Maximum number of results to keep, if any. Pickers report the results left out
+    /// with [PickerEvent::Truncated].
+    pub max_results: Option<usize>,
 }
 
 /// A Binocular picker.
 pub trait Picker<I: PickerItem> {
+    /// Returns the picker's identifier, used to namespace its configuration.
+    fn id(&self) -> &'static str;
+
     /// Returns the picker's name.
     fn name(&self) -> &'static str;
 
+    /// Returns the icon shown before the picker's name in the input title, telling the
+    /// pickers apart at a glance. Defaults to `None`.
+    fn icon(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the color of the picker's icon and of the input field's border.
+    /// Defaults to the base color.
+    fn prompt_color(&self, config: &Config) -> Color {
+        config.base_color()
+    }
+
+    /// Returns flags for the picker's modes that differ from their defaults,
+    /// displayed in the status line.
+    fn modes(&self) -> Vec<ModeFlag> {
+        Vec::new()
+    }
+
+    /// Returns the command-line flags derived from the picker's modes, displayed in the
+    /// input title when debugging.
+    fn flags(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     /// Returns the picker's preview title.
     fn preview_title(&self) -> &'static str;
 
-    /// Handles changes in the search input field.
+    /// Returns the layout suiting the picker's results, unless another one is configured.
+    /// Defaults to [PreviewLayout::Vertical].
+    fn layout(&self) -> PreviewLayout {
+        PreviewLayout::Vertical
+    }
+
+    /// Returns how the in-memory results filter matches case, which should follow the
+    /// picker's search. Defaults to [CaseMatching::Smart].
+    fn case(&self) -> CaseMatching {
+        CaseMatching::Smart
+    }
+
+    /// Handles changes in the search input field (or in the search options).
     /// `sender` can be used to communicate back with the application.
-    fn handle_input_change(&self, input: String, sender: Sender<Vec<I>>);
+    fn handle_input_change(&mut self, context: SearchContext, sender: Sender<PickerEvent<I>>);
+
+    /// Handles changes in the search input field synchronously, returning the events
+    /// of the search if it's quick enough to run before the next render (like when its
+    /// results are cached). Otherwise returns `None` and the search is run with
+    /// [Picker::handle_input_change]. A picker returning results here should cancel
+    /// its search in progress, if any.
+    fn try_handle_input_sync(&mut self, _context: &SearchContext) -> Option<Vec<PickerEvent<I>>> {
+        None
+    }
 
     /// Handles selection events.
-    fn handle_selection(&self, item: &I) -> Result<()>;
+    fn handle_selection(&self, item: &I, config: &Config) -> Result<()>;
+
+    /// Returns an action to run only once the user confirms it, for picker-specific
+    /// actions that are destructive (like deleting the selected file), given the selected
+    /// item if any. Actions needing confirmation aren't passed to [Picker::handle_action].
+    /// Defaults to `None`.
+    fn confirm_action(&mut self, _action: Action, _selected: Option<&I>) -> Option<PendingAction> {
+        None
+    }
+
+    /// Handles picker-specific actions (such as search mode toggles), given the
+    /// selected item if any. Returns `true` if the action was handled, in which case
+    /// the current search is re-run.
+    fn handle_action(&mut self, _action: Action, _selected: Option<&I>) -> bool {
+        false
+    }
+
+    /// Restores the state the picker was launched with, like its search modes, and
+    /// cancels the search in progress. Defaults to doing nothing.
+    fn reset(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_case_like_the_search() {
+        // Smart case only matches case when the needle has uppercase characters.
+        assert!(smart_case_contains(
+            "fn parseConfig",
+            "config",
+            CaseMatching::Smart
+        ));
+        assert!(smart_case_contains(
+            "fn parseConfig",
+            "Config",
+            CaseMatching::Smart
+        ));
+        assert!(!smart_case_contains(
+            "fn parse_config",
+            "Config",
+            CaseMatching::Smart
+        ));
+
+        assert!(smart_case_contains(
+            "TODO: Fix",
+            "Fix",
+            CaseMatching::Sensitive
+        ));
+        assert!(!smart_case_contains(
+            "TODO: Fix",
+            "fix",
+            CaseMatching::Sensitive
+        ));
+
+        assert!(smart_case_contains(
+            "TODO: Fix",
+            "todo: FIX",
+            CaseMatching::Insensitive
+        ));
+        assert!(!smart_case_contains(
+            "TODO: Fix",
+            "done",
+            CaseMatching::Insensitive
+        ));
+    }
+
+    /// An item implementing only the required methods.
+    #[derive(Clone)]
+    struct Item(Option<&'static str>);
+
+    impl PickerItem for Item {
+        fn as_list_item(&self, _config: &Config, _style: ListStyle) -> ListItem<'static> {
+            ListItem::new("item")
+        }
+
+        fn preview(&self, _config: &Config) -> String {
+            "preview".to_owned()
+        }
+
+        fn text(&self) -> String {
+            "text".to_owned()
+        }
+
+        fn location(&self) -> Option<Location<'_>> {
+            Some(Location {
+                path: self.0?,
+                lines: 3..=4,
+            })
+        }
+    }
+
+    #[test]
+    fn keys_items_by_location_by_default() {
+        assert_eq!(Item(Some("src/main.rs")).key(), "src/main.rs:3");
+        assert_eq!(Item(None).key(), "text");
+        assert!(Item(None).matches_filter("TEXT", CaseMatching::Insensitive));
+    }
 }
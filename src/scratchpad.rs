@@ -0,0 +1,145 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{git, Config};
+
+/// A query saved under a name.
+#[derive(Clone, Deserialize, Serialize)]
+struct SavedQuery {
+    name: String,
+    /// The identifier of the picker the query is searched with.
+    picker: String,
+    query: String,
+}
+
+/// The saved queries of each project, keyed by project.
+type Projects = BTreeMap<String, Vec<SavedQuery>>;
+
+/// Named queries saved for the current project, persisted in the configuration directory
+/// across sessions. Projects are identified by the root of their git repository, or by
+/// their directory outside of one.
+#[derive(Default)]
+pub struct Scratchpad {
+    /// The file the queries of all projects are persisted in, if there's a configuration
+    /// directory.
+    path: Option<PathBuf>,
+    project: String,
+    queries: Vec<SavedQuery>,
+}
+
+impl Scratchpad {
+    /// Loads the current project's queries, which are empty if they can't be read.
+    pub fn load(config: &Config) -> Self {
+        let project = git::root()
+            .or_else(|| env::current_dir().ok())
+            .map_or_else(String::new, |dir| dir.to_string_lossy().into_owned());
+        Self::load_project(config.scratchpad_path(), project)
+    }
+
+    /// Loads the project's queries from the given file.
+    fn load_project(path: Option<PathBuf>, project: String) -> Self {
+        let queries = path
+            .as_deref()
+            .and_then(|path| read_projects(path).remove(&project))
+            .unwrap_or_default();
+        Self {
+            path,
+            project,
+            queries,
+        }
+    }
+
+    /// Returns the names and queries saved for the picker, sorted by name.
+    pub(crate) fn queries(&self, picker: &str) -> Vec<(String, String)> {
+        let mut queries = self
+            .queries
+            .iter()
+            .filter(|saved| saved.picker == picker)
+            .map(|saved| (saved.name.clone(), saved.query.clone()))
+            .collect::<Vec<_>>();
+        queries.sort();
+        queries
+    }
+
+    /// Saves the query under the name, replacing the picker's query of the same name.
+    pub(crate) fn save(&mut self, picker: &str, name: &str, query: &str) -> Result<()> {
+        let path = self
+            .path
+            .clone()
+            .context("Failed to save the query: no configuration directory")?;
+
+        // Other sessions may have saved queries since the scratchpad was loaded.
+        let mut projects = read_projects(&path);
+        let queries = projects.entry(self.project.clone()).or_default();
+        match queries
+            .iter_mut()
+            .find(|saved| saved.picker == picker && saved.name == name)
+        {
+            Some(saved) => saved.query = query.to_owned(),
+            None => queries.push(SavedQuery {
+                name: name.to_owned(),
+                picker: picker.to_owned(),
+                query: query.to_owned(),
+            }),
+        }
+        self.queries = queries.clone();
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to save the query")?;
+        }
+        let json = serde_json::to_string(&projects).context("Failed to save the query")?;
+        fs::write(path, json).context("Failed to save the query")
+    }
+}
+
+/// Reads the scratchpad file, ignoring missing and corrupted ones.
+fn read_projects(path: &Path) -> Projects {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_queries_per_project() {
+        let path =
+            env::temp_dir().join(format!("binocular-scratchpad-{}.json", std::process::id()));
+        let mut app = Scratchpad::load_project(Some(path.clone()), "app".to_owned());
+        app.save("grep", "todos", "TODO").unwrap();
+        app.save("grep", "errors", "bail!").unwrap();
+        app.save("grep", "todos", "TODO|FIXME").unwrap();
+        app.save("files", "tests", "_test").unwrap();
+        let mut lib = Scratchpad::load_project(Some(path.clone()), "lib".to_owned());
+        lib.save("grep", "todos", "XXX").unwrap();
+
+        let app = Scratchpad::load_project(Some(path.clone()), "app".to_owned());
+        let lib = Scratchpad::load_project(Some(path.clone()), "lib".to_owned());
+        fs::remove_file(path).unwrap();
+
+        // Saving a name again replaces its query.
+        assert_eq!(
+            app.queries("grep"),
+            [
+                ("errors".to_owned(), "bail!".to_owned()),
+                ("todos".to_owned(), "TODO|FIXME".to_owned())
+            ]
+        );
+        assert_eq!(
+            app.queries("files"),
+            [("tests".to_owned(), "_test".to_owned())]
+        );
+        assert_eq!(
+            lib.queries("grep"),
+            [("todos".to_owned(), "XXX".to_owned())]
+        );
+    }
+}
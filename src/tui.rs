@@ -1,23 +1,134 @@
 use anyhow::{Context, Result};
 use crossterm::terminal;
 use ratatui::{
-    backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Layout},
-    style::{Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, BorderType, Borders, Clear, List, ListState, Paragraph},
+    backend::{Backend, CrosstermBackend},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph,
+    },
     Terminal,
 };
-use std::io::{self, Stdout};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    iter,
+};
 use tui_input::Input;
 
-use crate::{pickers::PickerItem, Config};
+use crate::{
+    color,
+    config::{HelpStyle, PreviewLayout, VisitedStyle},
+    keys::Action,
+    pickers::{ListStyle, ModeFlag, PickerItem},
+    Config,
+};
+
+/// Maximum number of lines the input field grows to when wrapping long queries.
+const MAX_INPUT_LINES: usize = 5;
+
+/// Number of lines of the help dialog: one per action, and the keys that behave
+/// differently while editing the filter.
+pub(crate) const HELP_LINES: usize = Action::ALL.len() + 2;
+
+/// The application state to render in a frame.
+pub(crate) struct View<'a, I: PickerItem> {
+    /// The search input field.
+    pub(crate) input: &'a Input,
+    /// The search results.
+    pub(crate) results: &'a [I],
+    /// Indices of the displayed results, in display order.
+    pub(crate) visible: &'a [usize],
+    /// Changes whenever the results or the displayed ones change.
+    pub(crate) visible_revision: u64,
+    /// Whether the results display their line number.
+    pub(crate) line_numbers: bool,
+    /// Whether to show the help dialog.
+    pub(crate) show_help: bool,
+    /// How many lines the help dialog is scrolled down.
+    pub(crate) help_scroll: u16,
+    /// Where the preview is placed relative to the results.
+    pub(crate) layout: PreviewLayout,
+    /// The menu of queries to search, if it's open.
+    pub(crate) menu: Option<Menu<'a>>,
+    /// Whether to show the preview window.
+    pub(crate) show_preview: bool,
+    /// The preview of the selected result.
+    pub(crate) preview: Text<'a>,
+    /// How many lines the preview is scrolled down.
+    pub(crate) preview_scroll: u16,
+    /// The title of the preview window.
+    pub(crate) preview_title: &'a str,
+    /// The title of the search input field.
+    pub(crate) input_title: &'a str,
+    /// The picker's icon, shown before the input title, if any.
+    pub(crate) prompt_icon: Option<&'a str>,
+    /// The color of the picker's icon and of the input field's border.
+    pub(crate) prompt_color: Color,
+    /// The picker's active modes.
+    pub(crate) modes: &'a [ModeFlag],
+    /// The keys of the marked results.
+    pub(crate) marked: &'a [String],
+    /// The keys of the results that were already selected.
+    pub(crate) visited: &'a HashSet<String>,
+    /// The question waiting for the user's confirmation (like quitting), if any.
+    pub(crate) confirm: Option<&'a str>,
+    /// A status message for the user, if any.
+    pub(crate) status: Option<&'a str>,
+    /// Number of results left out by the result limit, if any.
+    pub(crate) truncated: Option<usize>,
+    /// Whether to emphasize the selected result because it just moved.
+    pub(crate) flash_selection: bool,
+    /// Whether to label the first results in view with the digit jumping to them.
+    pub(crate) numbered: bool,
+    /// The frame of the searching spinner, while a search has been running long enough
+    /// to show it.
+    pub(crate) searching: Option<usize>,
+}
+
+/// The frames of the spinner shown while searching.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Maximum number of results labeled for jumping to them, one per digit key.
+pub(crate) const MAX_NUMBERED: usize = 9;
+
+/// A menu of queries to search, shown over the results.
+pub(crate) struct Menu<'a> {
+    pub(crate) title: &'a str,
+    /// The labels of the entries, with their queries.
+    pub(crate) entries: &'a [(String, String)],
+    /// The position of the selected entry.
+    pub(crate) selected: usize,
+}
+
+/// Where the terminal user interface is drawn.
+pub type TerminalOutput = BufWriter<Box<dyn Write + Send>>;
+
+/// Returns the output the terminal user interface is drawn to: the controlling terminal,
+/// falling back to stderr. Stdout is left to the printed results (like with `--print0`),
+/// which can thus be piped to other commands.
+fn terminal_output() -> TerminalOutput {
+    let output: Box<dyn Write + Send> = match OpenOptions::new().write(true).open("/dev/tty") {
+        Ok(tty) if cfg!(unix) => Box::new(tty),
+        _ => Box::new(io::stderr()),
+    };
+    BufWriter::new(output)
+}
 
 /// Wrapper around the terminal user interface.
 /// Responsible for its setup and shutdown.
-pub struct Tui<'a> {
+pub struct Tui<'a, B: Backend = CrosstermBackend<TerminalOutput>> {
     config: &'a Config,
-    terminal: Terminal<CrosstermBackend<Stdout>>,
+    terminal: Terminal<B>,
+    /// The results grouped by file, with the revision of the displayed results they
+    /// were grouped from.
+    grouped: Option<(u64, Rows)>,
+    /// The list items of the results rendered so far by their index, with the revision
+    /// of the displayed results and the style they were rendered with.
+    list_items: Option<(u64, ListStyle, HashMap<usize, ListItem<'static>>)>,
 }
 
 impl<'a> Tui<'a> {
@@ -27,13 +138,18 @@ impl<'a> Tui<'a> {
         terminal::enable_raw_mode().context("Failed to enable raw mode")?;
 
         // Configure terminal properties.
-        let mut stdout = io::stdout();
-        crossterm::execute!(stdout, terminal::EnterAlternateScreen)
+        let mut output = terminal_output();
+        crossterm::execute!(output, terminal::EnterAlternateScreen)
             .context("Failed to enter alternate screen")?;
 
         // Initialize the terminal.
-        Terminal::new(CrosstermBackend::new(stdout))
-            .map(|terminal| Self { terminal, config })
+        Terminal::new(CrosstermBackend::new(output))
+            .map(|terminal| Self {
+                terminal,
+                config,
+                grouped: None,
+                list_items: None,
+            })
             .context("Failed to create terminal")
     }
 
@@ -47,22 +163,63 @@ impl<'a> Tui<'a> {
         }
 
         // Restore terminal properties.
-        if let Err(err) = crossterm::execute!(io::stdout(), terminal::LeaveAlternateScreen) {
+        if let Err(err) = crossterm::execute!(terminal_output(), terminal::LeaveAlternateScreen) {
             eprintln!("Failed to leave alternate screen: {}", err);
         }
     }
+}
+
+impl<'a, B: Backend> Tui<'a, B> {
+    /// Creates a user interface drawing to the given backend, without setting up the
+    /// terminal (see [Tui::setup]).
+    pub fn with_backend(backend: B, config: &'a Config) -> Result<Self> {
+        Terminal::new(backend)
+            .map(|terminal| Self {
+                terminal,
+                config,
+                grouped: None,
+                list_items: None,
+            })
+            .context("Failed to create terminal")
+    }
 
-    /// Renders the terminal's widgets.
+    /// Renders the terminal's widgets. Returns the positions among the visible results
+    /// of the ones labeled with a number, if numbered.
     pub(crate) fn render<I: PickerItem>(
         &mut self,
-        input: &Input,
-        results: &[I],
+        view: View<I>,
         state: &mut ListState,
-        show_help: bool,
-        preview_title: &str,
-        input_title: &str,
-    ) -> Result<()> {
-        let block = |title| {
+    ) -> Result<Vec<usize>> {
+        let View {
+            input,
+            results,
+            visible,
+            visible_revision,
+            line_numbers,
+            show_help,
+            help_scroll,
+            layout,
+            menu,
+            show_preview,
+            preview,
+            preview_scroll,
+            preview_title,
+            input_title,
+            prompt_icon,
+            prompt_color,
+            modes,
+            marked,
+            visited,
+            confirm,
+            status,
+            truncated,
+            flash_selection,
+            numbered,
+            searching,
+        } = view;
+        let mut labeled = Vec::new();
+
+        let block = |title: &str| {
             Block::default()
                 .title(format!(" {} ", title))
                 .title_alignment(Alignment::Center)
@@ -71,7 +228,25 @@ impl<'a> Tui<'a> {
                 .border_style(Style::default().fg(self.config.base_color()))
         };
 
-        let help_line = |key, desc| {
+        // The input field is styled after the picker, prefixing its title with its icon.
+        let input_block = || {
+            let mut title = vec![Span::raw(" ")];
+            if let Some(icon) = prompt_icon {
+                title.push(Span::styled(
+                    format!("{} ", icon),
+                    Style::default().fg(prompt_color),
+                ));
+            }
+            title.push(Span::raw(format!("{} ", input_title)));
+            Block::default()
+                .title(Line::from(title))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(prompt_color))
+        };
+
+        let help_line = |key: String, desc| {
             Line::from(vec![
                 Span::styled(
                     format!("  {:<15}", key),
@@ -83,107 +258,786 @@ impl<'a> Tui<'a> {
             ])
         };
 
+        // Grouping walks all the displayed results, so it's only redone when they change.
+        let group_by_file = self.config.group_by_file();
+        if group_by_file
+            && self
+                .grouped
+                .as_ref()
+                .is_none_or(|(revision, _)| *revision != visible_revision)
+        {
+            self.grouped = Some((visible_revision, Rows::grouped(results, visible)));
+        }
+        let style = ListStyle {
+            grouped: group_by_file,
+            line_numbers,
+        };
+        // List items are only rendered again when the displayed results or their style
+        // change, and only once they're in view.
+        if self
+            .list_items
+            .as_ref()
+            .is_none_or(|(revision, rendered_style, _)| {
+                *revision != visible_revision || *rendered_style != style
+            })
+        {
+            self.list_items = Some((visible_revision, style, HashMap::new()));
+        }
+        let (_, _, list_items) = self
+            .list_items
+            .get_or_insert_with(|| (visible_revision, style, HashMap::new()));
+        let flat = Rows::Flat(visible.len());
+        let rows = match &self.grouped {
+            Some((_, rows)) if group_by_file => rows,
+            _ => &flat,
+        };
+
         self.terminal
             .draw(|f| {
+                // Long queries wrap over several lines when enabled, otherwise they
+                // scroll horizontally.
+                let input_width = f.size().width.saturating_sub(4).max(1) as usize;
+                let input_chars = input.value().chars().collect::<Vec<_>>();
+                let input_lines = if self.config.wrap_input() {
+                    (input_chars.len() / input_width + 1).min(MAX_INPUT_LINES)
+                } else {
+                    1
+                };
+
                 // Define the layout.
+                let preview_above = show_preview && layout == PreviewLayout::Vertical;
                 let chunks = Layout::default()
                     .direction(Direction::Vertical)
                     .constraints(
                         [
-                            Constraint::Length(10),
+                            // The results list takes over the preview's space when it's hidden.
+                            Constraint::Length(if preview_above { 10 } else { 0 }),
                             Constraint::Min(20),
-                            Constraint::Length(3),
+                            Constraint::Length(input_lines as u16 + 2),
                             Constraint::Length(1),
                         ]
                         .as_ref(),
                     )
                     .margin(1)
                     .split(f.size());
+                let (preview_area, results_area) = if show_preview && !preview_above {
+                    let columns = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints(
+                            [Constraint::Percentage(50), Constraint::Percentage(50)].as_ref(),
+                        )
+                        .split(chunks[1]);
+                    (columns[1], columns[0])
+                } else {
+                    (chunks[0], chunks[1])
+                };
 
-                // Previewer's title.
-                let preview = results
-                    .get(state.selected().unwrap_or(0))
-                    .map_or(String::new(), |item| item.preview());
-                f.render_widget(
-                    Paragraph::new(preview).block(block(preview_title)),
-                    chunks[0],
-                );
+                // Previewer.
+                if show_preview {
+                    // Show how many lines are hidden above and below the preview.
+                    let height = preview_area.height.saturating_sub(2) as usize;
+                    let (above, below) =
+                        hidden_lines(preview.lines.len(), height, preview_scroll as usize);
+                    let indicator = |symbol, hidden, position| {
+                        Title::from(Span::styled(
+                            format!(" {} {} more ", symbol, hidden),
+                            Style::default().add_modifier(Modifier::DIM),
+                        ))
+                        .alignment(Alignment::Right)
+                        .position(position)
+                    };
+                    let mut preview_block = block(preview_title);
+                    if above > 0 {
+                        preview_block = preview_block.title(indicator('▲', above, Position::Top));
+                    }
+                    if below > 0 {
+                        preview_block =
+                            preview_block.title(indicator('▼', below, Position::Bottom));
+                    }
+                    f.render_widget(
+                        Paragraph::new(preview)
+                            .scroll((preview_scroll, 0))
+                            .block(preview_block),
+                        preview_area,
+                    );
+                }
 
-                // List of results.
+                // List of results. Only the items in the visible window are built, which
+                // keeps rendering cheap for huge result sets.
+                let row_state = ListState::default()
+                    .with_offset(state.offset())
+                    .with_selected(state.selected().map(|selected| rows.item_row(selected)));
+                // Truncated results end with a row telling how to load more.
+                let len = rows.len() + usize::from(truncated.is_some());
+                let height = results_area.height.saturating_sub(2) as usize;
+                // Results are followed by blank padding lines, which aren't selectable.
+                let padding = self.config.row_padding();
+                let mut offset = visible_offset(
+                    &row_state,
+                    len,
+                    padded_rows(height, padding),
+                    self.config.scrolloff(),
+                );
+                // Keep the header of the selected result's file in view.
+                if row_state.selected().is_some_and(|selected| {
+                    selected == offset
+                        && offset > 0
+                        && matches!(rows.get(offset - 1), Row::Header(..))
+                }) {
+                    offset -= 1;
+                }
+                let mut list_item = |row| match (row < rows.len()).then(|| rows.get(row)) {
+                    None => ListItem::new(Span::styled(
+                        format!(
+                            "… {} more, press {} to load more",
+                            truncated.unwrap_or_default(),
+                            self.config.key_binding(Action::LoadMore)
+                        ),
+                        Style::default()
+                            .fg(self.config.base_color())
+                            .add_modifier(Modifier::DIM),
+                    )),
+                    Some(Row::Header(i, count)) => ListItem::new(Line::from(vec![
+                        Span::styled(
+                            results[visible[i]]
+                                .location()
+                                .map_or_else(String::new, |location| location.path.to_owned()),
+                            Style::default()
+                                .fg(self.config.filepath_color())
+                                .add_modifier(Modifier::DIM),
+                        ),
+                        Span::styled(
+                            format!(" ({})", count),
+                            Style::default().fg(self.config.filepath_color()),
+                        ),
+                    ])),
+                    Some(Row::Item(i)) => {
+                        let result = &results[visible[i]];
+                        let item = list_items
+                            .entry(visible[i])
+                            .or_insert_with(|| result.as_list_item(self.config, style))
+                            .clone();
+                        // Marked results are emphasized, and visited ones styled as configured.
+                        let key = (!marked.is_empty() || !visited.is_empty()).then(|| result.key());
+                        match (key, self.config.visited_style()) {
+                            (Some(key), _) if marked.contains(&key) => item.style(
+                                Style::default()
+                                    .fg(self.config.selection_color())
+                                    .add_modifier(Modifier::BOLD),
+                            ),
+                            (Some(key), VisitedStyle::Dim) if visited.contains(&key) => {
+                                item.style(Style::default().add_modifier(Modifier::DIM))
+                            }
+                            (Some(key), VisitedStyle::Strikethrough) if visited.contains(&key) => {
+                                item.style(Style::default().add_modifier(Modifier::CROSSED_OUT))
+                            }
+                            _ => item,
+                        }
+                    }
+                };
+                // Results can take up several lines (like multiline matches), so scroll
+                // further until the selected one fits entirely.
+                let items = loop {
+                    let items = (offset..(offset + height).min(len))
+                        .map(&mut list_item)
+                        .collect::<Vec<_>>();
+                    let selected_bottom = row_state
+                        .selected()
+                        .filter(|selected| *selected >= offset)
+                        .map_or(0, |selected| {
+                            row_bottom(
+                                items.iter().map(ListItem::height),
+                                selected - offset,
+                                padding,
+                            )
+                        });
+                    if selected_bottom <= height || row_state.selected() <= Some(offset) {
+                        break items;
+                    }
+                    offset += 1;
+                };
+                *state.offset_mut() = offset;
+                // Label the first results in view, with the line they start on.
+                let labels = if numbered {
+                    (offset..)
+                        .zip(&items)
+                        .scan(0, |top, (row, item)| {
+                            let row_top = *top;
+                            *top += item.height() + padding;
+                            Some((row, row_top))
+                        })
+                        .filter_map(
+                            |(row, top)| match (row < rows.len()).then(|| rows.get(row)) {
+                                Some(Row::Item(i)) if top < height => Some((i, top)),
+                                _ => None,
+                            },
+                        )
+                        .take(MAX_NUMBERED)
+                        .collect::<Vec<_>>()
+                } else {
+                    Vec::new()
+                };
+                let items = items
+                    .into_iter()
+                    .flat_map(|item| {
+                        iter::once(item)
+                            .chain(iter::repeat_with(|| ListItem::new("")).take(padding))
+                    })
+                    .collect::<Vec<_>>();
+                let mut window_state = ListState::default().with_selected(
+                    row_state
+                        .selected()
+                        .map(|selected| (selected - offset) * (padding + 1)),
+                );
                 f.render_stateful_widget(
-                    List::new(
-                        results
-                            .into_iter()
-                            .map(|result| result.as_list_item(self.config))
-                            .collect::<Vec<_>>(),
-                    )
-                    .block(block("Results"))
-                    .highlight_symbol(">> ")
-                    .highlight_style(Style::default().fg(self.config.selection_color())),
-                    chunks[1],
-                    state,
+                    List::new(items)
+                        .block(block(&match searching {
+                            Some(frame) => {
+                                format!("Results {} searching…", SPINNER[frame % SPINNER.len()])
+                            }
+                            None => "Results".to_owned(),
+                        }))
+                        .highlight_symbol(">> ")
+                        // Without colors, the selection is only visible in reverse video.
+                        .highlight_style(if flash_selection || color::no_color() {
+                            Style::default()
+                                .fg(self.config.selection_color())
+                                .add_modifier(Modifier::REVERSED)
+                        } else {
+                            Style::default().fg(self.config.selection_color())
+                        }),
+                    results_area,
+                    &mut window_state,
                 );
+                // Draw the labels over the highlight symbol's margin.
+                for (n, (_, top)) in labels.iter().enumerate() {
+                    f.render_widget(
+                        Paragraph::new(Span::styled(
+                            (n + 1).to_string(),
+                            Style::default()
+                                .fg(self.config.base_color())
+                                .add_modifier(Modifier::BOLD),
+                        )),
+                        Rect::new(results_area.x + 2, results_area.y + 1 + *top as u16, 1, 1),
+                    );
+                }
+                labeled = labels.into_iter().map(|(i, _)| i).collect();
 
-                f.render_widget(
-                    Paragraph::new(input.value()).block(block(input_title)),
-                    chunks[2],
-                );
+                if self.config.wrap_input() {
+                    // Keep the cursor's line in view when the query is too long to fit.
+                    let cursor = input.cursor();
+                    let scroll = (cursor / input_width).saturating_sub(input_lines - 1);
+                    f.render_widget(
+                        Paragraph::new(
+                            input_chars
+                                .chunks(input_width)
+                                .map(|line| Line::from(line.iter().collect::<String>()))
+                                .collect::<Vec<_>>(),
+                        )
+                        .scroll((scroll as u16, 0))
+                        .block(input_block()),
+                        chunks[2],
+                    );
+                    f.set_cursor(
+                        chunks[2].x + (cursor % input_width) as u16 + 1,
+                        chunks[2].y + (cursor / input_width - scroll) as u16 + 1,
+                    );
+                } else {
+                    f.render_widget(
+                        Paragraph::new(input.value()).block(input_block()),
+                        chunks[2],
+                    );
 
-                // Keep the cursor in sync with the input field.
-                let width = chunks[0].width - 2;
-                let scroll = input.visual_scroll(width as usize);
-                f.set_cursor(
-                    chunks[2].x + ((input.visual_cursor()).max(scroll) - scroll) as u16 + 1,
-                    chunks[2].y + 1,
-                );
+                    // Keep the cursor in sync with the input field.
+                    let width = chunks[2].width.saturating_sub(2);
+                    let scroll = input.visual_scroll(width as usize);
+                    f.set_cursor(
+                        chunks[2].x + ((input.visual_cursor()).max(scroll) - scroll) as u16 + 1,
+                        chunks[2].y + 1,
+                    );
+                }
+
+                // Status message.
+                if let Some(status) = status {
+                    f.render_widget(
+                        Paragraph::new(status).style(Style::default().fg(self.config.base_color())),
+                        chunks[3],
+                    );
+                }
 
-                // Help label.
+                // Active modes and help label.
                 f.render_widget(
-                    Paragraph::new("Help (?)")
-                        .style(Style::default().fg(self.config.base_color()))
-                        .alignment(Alignment::Right),
+                    Paragraph::new(
+                        modes
+                            .iter()
+                            .map(|ModeFlag(mode)| format!("[{}] ", mode))
+                            .chain(iter::once(format!(
+                                "Help ({})",
+                                self.config.key_binding(Action::ToggleHelp)
+                            )))
+                            .collect::<String>(),
+                    )
+                    .style(Style::default().fg(self.config.base_color()))
+                    .alignment(Alignment::Right),
                     chunks[3],
                 );
 
                 if show_help {
-                    // Show the help dialog.
-                    let layout = Layout::default()
-                        .direction(Direction::Vertical)
-                        .constraints(
-                            [
-                                Constraint::Percentage(35),
-                                Constraint::Max(7),
-                                Constraint::Percentage(35),
-                            ]
-                            .as_ref(),
+                    // Show the help dialog, scrolled no further than its last line.
+                    let chunk = match self.config.help_style() {
+                        HelpStyle::Popup => popup_area(f.size(), HELP_LINES as u16 + 2),
+                        HelpStyle::Fullscreen => f.size(),
+                    };
+                    let help_height = chunk.height.saturating_sub(2);
+                    let help_scroll =
+                        help_scroll.min((HELP_LINES as u16).saturating_sub(help_height));
+                    f.render_widget(Clear, chunk);
+                    f.render_widget(
+                        Paragraph::new(
+                            Action::ALL
+                                .into_iter()
+                                .map(|action| {
+                                    help_line(
+                                        self.config.key_binding(action).to_string(),
+                                        action.description(),
+                                    )
+                                })
+                                // Keys that behave differently while editing the filter.
+                                .chain([
+                                    help_line(
+                                        format!(
+                                            "{} (filter)",
+                                            self.config.key_binding(Action::Select)
+                                        ),
+                                        "Accept the highlighted filtered result",
+                                    ),
+                                    help_line(
+                                        format!(
+                                            "{} (filter)",
+                                            self.config.key_binding(Action::Quit)
+                                        ),
+                                        "Close the filter, back to all results",
+                                    ),
+                                ])
+                                .collect::<Vec<_>>(),
                         )
-                        .split(f.size());
-                    let chunk = Layout::default()
-                        .direction(Direction::Horizontal)
-                        .constraints(
-                            [
-                                Constraint::Percentage(40),
-                                Constraint::Min(40),
-                                Constraint::Percentage(40),
-                            ]
-                            .as_ref(),
+                        .scroll((help_scroll, 0))
+                        .block(block("Help")),
+                        chunk,
+                    );
+                }
+
+                if let Some(Menu {
+                    title,
+                    entries,
+                    selected,
+                }) = menu
+                {
+                    // Show the query menu.
+                    let chunk = popup_area(f.size(), entries.len() as u16 + 2);
+                    let mut state = ListState::default().with_selected(Some(selected));
+                    f.render_widget(Clear, chunk);
+                    f.render_stateful_widget(
+                        List::new(
+                            entries
+                                .iter()
+                                .map(|(label, _)| ListItem::new(label.as_str()))
+                                .collect::<Vec<_>>(),
                         )
-                        .split(layout[1])[1];
+                        .highlight_symbol(">> ")
+                        .highlight_style(Style::default().fg(self.config.selection_color()))
+                        .block(block(title)),
+                        chunk,
+                        &mut state,
+                    );
+                }
+
+                if let Some(confirm) = confirm {
+                    // Show the confirmation prompt.
+                    let chunk = popup_area(f.size(), 4);
                     f.render_widget(Clear, chunk);
                     f.render_widget(
                         Paragraph::new(vec![
-                            help_line("<esc>", "Quit"),
-                            help_line("<up>", "Previous result"),
-                            help_line("<down>", "Next result"),
-                            help_line("<enter>", "Select result"),
-                            help_line("?", "Toggle help"),
+                            Line::from(format!("  {}", confirm)),
+                            help_line("y/n".to_owned(), "Confirm/Cancel"),
                         ])
-                        .block(block("Help")),
+                        .block(block("Confirm")),
                         chunk,
                     );
                 }
             })
-            .map(|_| ())
-            .context("Failed to draw terminal")
+            .context("Failed to draw terminal")?;
+        Ok(labeled)
+    }
+}
+
+/// A row of the results list.
+#[derive(Clone, Copy, PartialEq)]
+enum Row {
+    /// A file header, above the result at the given position, with the file's number
+    /// of results.
+    Header(usize, usize),
+    /// The result at the given position.
+    Item(usize),
+}
+
+/// The rows of the results list.
+enum Rows {
+    /// One row per result.
+    Flat(usize),
+    /// Results grouped by file, with a header above each file's results, and the row of
+    /// each result.
+    Grouped(Vec<Row>, Vec<usize>),
+}
+
+impl Rows {
+    /// Groups the displayed results by file. Results without a file aren't grouped.
+    fn grouped<I: PickerItem>(results: &[I], visible: &[usize]) -> Self {
+        let mut rows = Vec::with_capacity(visible.len());
+        let mut item_rows = Vec::with_capacity(visible.len());
+        let mut file = None;
+        let mut header = None;
+        for (i, index) in visible.iter().enumerate() {
+            let location = results[*index].location();
+            let path = location.as_ref().map(|location| location.path);
+            if path != file {
+                header = path.is_some().then_some(rows.len());
+                if path.is_some() {
+                    rows.push(Row::Header(i, 0));
+                }
+            }
+            if let Some(Row::Header(_, count)) = header.map(|header| &mut rows[header]) {
+                *count += 1;
+            }
+            file = path;
+            item_rows.push(rows.len());
+            rows.push(Row::Item(i));
+        }
+
+        Rows::Grouped(rows, item_rows)
+    }
+
+    /// Returns the number of rows.
+    fn len(&self) -> usize {
+        match self {
+            Rows::Flat(len) => *len,
+            Rows::Grouped(rows, _) => rows.len(),
+        }
+    }
+
+    /// Returns the row at the given index.
+    fn get(&self, row: usize) -> Row {
+        match self {
+            Rows::Flat(_) => Row::Item(row),
+            Rows::Grouped(rows, _) => rows[row],
+        }
+    }
+
+    /// Returns the row of the result at the given position.
+    fn item_row(&self, i: usize) -> usize {
+        match self {
+            Rows::Flat(_) => i,
+            Rows::Grouped(_, item_rows) => item_rows.get(i).copied().unwrap_or(0),
+        }
+    }
+}
+
+/// Returns the area of a dialog with the given height, centered in the given area.
+fn popup_area(area: Rect, height: u16) -> Rect {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage(35),
+                Constraint::Max(height),
+                Constraint::Percentage(35),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage(40),
+                Constraint::Min(40),
+                Constraint::Percentage(40),
+            ]
+            .as_ref(),
+        )
+        .split(layout[1])[1]
+}
+
+/// Returns how many results fit in a list of the given height (at least one), when each
+/// takes up a single line followed by `padding` blank lines.
+fn padded_rows(height: usize, padding: usize) -> usize {
+    (height / (padding + 1)).max(1)
+}
+
+/// Returns the line on which the result at position `row` ends, given the heights of the
+/// results from the top of the list, each followed by `padding` blank lines.
+fn row_bottom(heights: impl IntoIterator<Item = usize>, row: usize, padding: usize) -> usize {
+    heights
+        .into_iter()
+        .take(row + 1)
+        .enumerate()
+        .map(|(i, height)| if i < row { height + padding } else { height })
+        .sum()
+}
+
+/// Returns how many lines of a text are hidden above and below a pane of the given
+/// height, scrolled down by the given number of lines.
+fn hidden_lines(lines: usize, height: usize, scroll: usize) -> (usize, usize) {
+    let above = scroll.min(lines);
+    (above, lines.saturating_sub(above + height))
+}
+
+/// Returns the index of the first result visible in a list of the given height, scrolling
+/// the list from its current offset just enough to keep the selected result visible, with
+/// at least `scrolloff` results around it (except at the edges of the list).
+/// Results are assumed to take up a single line each, taller ones are scrolled into view
+/// when rendering.
+fn visible_offset(state: &ListState, len: usize, height: usize, scrolloff: usize) -> usize {
+    let scrolloff = scrolloff.min(height.saturating_sub(1) / 2);
+    let offset = state.offset().min(len.saturating_sub(1));
+    match state.selected() {
+        Some(selected) if selected < offset + scrolloff => selected.saturating_sub(scrolloff),
+        Some(selected) if height > 0 && selected + scrolloff >= offset + height => {
+            (selected + scrolloff + 1 - height).min(len.saturating_sub(height))
+        }
+        _ => offset,
+    }
+}
+
+/// Expands the tabs in the text to spaces, aligning them to multiples of `tab_width` columns.
+/// With `render_whitespace`, tabs and trailing spaces are shown as dimmed `→` and `·` glyphs.
+pub(crate) fn expand_whitespace(
+    text: Text<'_>,
+    tab_width: usize,
+    render_whitespace: bool,
+) -> Text<'static> {
+    let tab_width = tab_width.max(1);
+    let lines = text.lines.into_iter().map(|line| {
+        let len: usize = line
+            .spans
+            .iter()
+            .map(|span| span.content.chars().count())
+            .sum();
+        let trailing = line
+            .spans
+            .iter()
+            .flat_map(|span| span.content.chars())
+            .rev()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        let trailing_start = len - trailing;
+
+        let mut spans = Vec::new();
+        let (mut index, mut column) = (0, 0);
+        for span in line.spans {
+            let dim = span.style.add_modifier(Modifier::DIM);
+            let mut content = String::new();
+            for c in span.content.chars() {
+                let glyph = match c {
+                    '\t' => {
+                        let width = tab_width - column % tab_width;
+                        column += width;
+                        if render_whitespace {
+                            Some(format!("→{}", " ".repeat(width - 1)))
+                        } else {
+                            content.push_str(&" ".repeat(width));
+                            None
+                        }
+                    }
+                    ' ' if render_whitespace && index >= trailing_start => {
+                        column += 1;
+                        Some("·".to_owned())
+                    }
+                    c => {
+                        column += 1;
+                        content.push(c);
+                        None
+                    }
+                };
+                if let Some(glyph) = glyph {
+                    if !content.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut content), span.style));
+                    }
+                    spans.push(Span::styled(glyph, dim));
+                }
+                index += 1;
+            }
+            if !content.is_empty() {
+                spans.push(Span::styled(content, span.style));
+            }
+        }
+
+        Line::from(spans)
+    });
+
+    Text::from(lines.collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pickers::Location;
+
+    fn state(selected: Option<usize>, offset: usize) -> ListState {
+        ListState::default()
+            .with_selected(selected)
+            .with_offset(offset)
+    }
+
+    /// An item in the given file, if any.
+    #[derive(Clone)]
+    struct FileItem(Option<&'static str>);
+
+    impl PickerItem for FileItem {
+        fn as_list_item(&self, _config: &Config, _style: ListStyle) -> ListItem<'static> {
+            ListItem::new(self.0.unwrap_or_default())
+        }
+
+        fn preview(&self, _config: &Config) -> String {
+            String::new()
+        }
+
+        fn text(&self) -> String {
+            self.0.unwrap_or_default().to_owned()
+        }
+
+        fn location(&self) -> Option<Location<'_>> {
+            self.0.map(|path| Location { path, lines: 1..=1 })
+        }
+    }
+
+    #[test]
+    fn groups_results_by_file() {
+        let results = [
+            FileItem(Some("a")),
+            FileItem(None),
+            FileItem(Some("b")),
+            FileItem(Some("a")),
+        ];
+        // The second `a` result is displayed right after the first.
+        let rows = Rows::grouped(&results, &[0, 3, 2, 1]);
+        let all = (0..rows.len()).map(|row| rows.get(row)).collect::<Vec<_>>();
+        assert!(
+            all == [
+                Row::Header(0, 2),
+                Row::Item(0),
+                Row::Item(1),
+                Row::Header(2, 1),
+                Row::Item(2),
+                Row::Item(3),
+            ]
+        );
+        assert_eq!(
+            (0..4).map(|i| rows.item_row(i)).collect::<Vec<_>>(),
+            [1, 2, 4, 5]
+        );
+    }
+
+    #[test]
+    fn scrolls_the_selection_into_view() {
+        // The offset is kept while the selection is visible.
+        assert_eq!(visible_offset(&state(Some(5), 0), 100, 10, 0), 0);
+        assert_eq!(visible_offset(&state(Some(9), 0), 100, 10, 0), 0);
+        // The list scrolls just enough to show the selection.
+        assert_eq!(visible_offset(&state(Some(10), 0), 100, 10, 0), 1);
+        assert_eq!(visible_offset(&state(Some(5), 20), 100, 10, 0), 5);
+        assert_eq!(visible_offset(&state(Some(99), 0), 100, 10, 0), 90);
+        // Without a selection, the offset stays within the results.
+        assert_eq!(visible_offset(&state(None, 50), 100, 10, 0), 50);
+        assert_eq!(visible_offset(&state(None, 10), 3, 10, 0), 2);
+        assert_eq!(visible_offset(&state(Some(0), 0), 0, 0, 0), 0);
+    }
+
+    #[test]
+    fn keeps_results_around_the_selection_visible() {
+        assert_eq!(visible_offset(&state(Some(6), 0), 100, 10, 3), 0);
+        assert_eq!(visible_offset(&state(Some(7), 0), 100, 10, 3), 1);
+        assert_eq!(visible_offset(&state(Some(13), 10), 100, 10, 3), 10);
+        assert_eq!(visible_offset(&state(Some(12), 10), 100, 10, 3), 9);
+        // Except at the edges of the list.
+        assert_eq!(visible_offset(&state(Some(1), 0), 100, 10, 3), 0);
+        assert_eq!(visible_offset(&state(Some(98), 80), 100, 10, 3), 90);
+        // The selection stays within the middle of short lists.
+        assert_eq!(visible_offset(&state(Some(3), 0), 100, 4, 10), 1);
+        assert_eq!(visible_offset(&state(Some(2), 0), 100, 4, 10), 0);
+    }
+
+    /// Returns the contents of the spans of each line.
+    fn spans(text: Text<'_>) -> Vec<Vec<String>> {
+        text.lines
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expands_tabs() {
+        let text = Text::from("\tfoo\nab\tc  ");
+        assert_eq!(
+            spans(expand_whitespace(text.clone(), 4, false)),
+            [vec!["    foo"], vec!["ab  c  "]]
+        );
+        assert_eq!(
+            spans(expand_whitespace(text.clone(), 8, false)),
+            [vec!["        foo"], vec!["ab      c  "]]
+        );
+        // Tabs are at least one column wide.
+        assert_eq!(
+            spans(expand_whitespace(text, 0, false)),
+            [vec![" foo"], vec!["ab c  "]]
+        );
+    }
+
+    #[test]
+    fn renders_whitespace() {
+        let text = Text::from("\tfoo bar  ");
+        let rendered = expand_whitespace(text, 4, true);
+        assert_eq!(spans(rendered.clone()), [vec!["→   ", "foo bar", "·", "·"]]);
+        // The glyphs are dimmed, the rest keeps its style.
+        let dimmed = rendered.lines[0]
+            .spans
+            .iter()
+            .map(|span| span.style.add_modifier.contains(Modifier::DIM))
+            .collect::<Vec<_>>();
+        assert_eq!(dimmed, [true, false, true, true]);
+    }
+
+    #[test]
+    fn counts_hidden_preview_lines() {
+        assert_eq!(hidden_lines(100, 20, 0), (0, 80));
+        assert_eq!(hidden_lines(100, 20, 30), (30, 50));
+        assert_eq!(hidden_lines(100, 20, 80), (80, 0));
+        // Short texts fit in the pane.
+        assert_eq!(hidden_lines(10, 20, 0), (0, 0));
+        // Scrolling past the end hides everything above.
+        assert_eq!(hidden_lines(10, 20, 15), (10, 0));
+        assert_eq!(hidden_lines(0, 20, 0), (0, 0));
+    }
+
+    #[test]
+    fn pads_rows() {
+        assert_eq!(padded_rows(10, 0), 10);
+        assert_eq!(padded_rows(10, 1), 5);
+        assert_eq!(padded_rows(10, 2), 3);
+        // At least one result is shown.
+        assert_eq!(padded_rows(2, 5), 1);
+        assert_eq!(padded_rows(0, 0), 1);
+
+        assert_eq!(row_bottom([1, 1, 1], 0, 0), 1);
+        assert_eq!(row_bottom([1, 1, 1], 2, 0), 3);
+        // The padding after the row itself isn't counted.
+        assert_eq!(row_bottom([1, 1, 1], 2, 1), 5);
+        assert_eq!(row_bottom([1, 3, 2], 2, 2), 10);
+        assert_eq!(row_bottom([], 2, 1), 0);
     }
 }
@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use ignore::{gitignore::Gitignore, Match};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::JoinHandle, time};
+
+/// How often a watched file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the files of a watched directory must stop changing before their changes
+/// are notified.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The ignore files respected when watching a directory, like ripgrep does.
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".rgignore"];
+
+/// Watches files, notifying of their changes. Watching stops when the watcher is dropped.
+pub(crate) struct Watcher {
+    changes: mpsc::Receiver<()>,
+    task: JoinHandle<()>,
+    /// The file system watcher of a directory, if watching one.
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl Watcher {
+    /// Starts watching the files in the directory, ignoring the hidden ones and the ones
+    /// excluded by ignore files like ripgrep does. Changes are notified once the files
+    /// stop changing.
+    pub(crate) fn new(dir: PathBuf) -> Result<Self> {
+        let (events_tx, mut events) = mpsc::channel(1);
+        let mut ignored = IgnoredPaths::new(dir.clone());
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                // Reading files (like searching them) doesn't change them.
+                if matches!(event.kind, EventKind::Access(_)) {
+                    return;
+                }
+                if event.paths.iter().any(|path| !ignored.is_ignored(path)) {
+                    // A change is already waiting otherwise.
+                    let _ = events_tx.try_send(());
+                }
+            })
+            .context("Failed to watch the files")?;
+        watcher
+            .watch(&dir, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+        let (tx, changes) = mpsc::channel(1);
+        let task = tokio::spawn(async move {
+            while events.recv().await.is_some() {
+                // Wait for the files to stop changing.
+                while let Ok(Some(())) = time::timeout(DEBOUNCE, events.recv()).await {}
+                if tx.send(()).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Self {
+            changes,
+            task,
+            _watcher: Some(watcher),
+        })
+    }
+
+    /// Starts watching a single file, notifying of each change (including its removal).
+    pub(crate) fn file(path: PathBuf) -> Self {
+        Self::poll(move || {
+            let mut hasher = DefaultHasher::new();
+            fs::metadata(&path)
+                .ok()
+                .map(|metadata| (metadata.len(), metadata.modified().ok()))
+                .hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// Polls the fingerprint of the watched files, notifying when it changes.
+    fn poll(mut fingerprint: impl FnMut() -> u64 + Send + 'static) -> Self {
+        let (tx, changes) = mpsc::channel(1);
+        let task = tokio::spawn(async move {
+            let mut interval = time::interval(POLL_INTERVAL);
+            let mut last = None;
+            loop {
+                interval.tick().await;
+                let fingerprint = fingerprint();
+                let changed = last
+                    .replace(fingerprint)
+                    .is_some_and(|last| last != fingerprint);
+                if changed && tx.send(()).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            changes,
+            task,
+            _watcher: None,
+        }
+    }
+
+    /// Waits for the next change of the watched files.
+    pub(crate) async fn changed(&mut self) -> Option<()> {
+        self.changes.recv().await
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Decides which changed paths of a watched directory are ignored, reading the ignore
+/// files of their directories as needed.
+struct IgnoredPaths {
+    root: PathBuf,
+    /// The rules of the ignore files in each directory read so far.
+    rules: HashMap<PathBuf, Gitignore>,
+}
+
+impl IgnoredPaths {
+    fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            rules: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the path is hidden (like `.git/`) or excluded by the ignore files
+    /// of its directory or of its parents up to the root, the closest ones taking
+    /// precedence.
+    fn is_ignored(&mut self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        if relative
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return true;
+        }
+        // Changed ignore files are read again.
+        if path
+            .file_name()
+            .is_some_and(|name| IGNORE_FILES.iter().any(|file| name == *file))
+        {
+            if let Some(dir) = path.parent() {
+                self.rules.remove(dir);
+            }
+        }
+
+        let is_dir = path.is_dir();
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            let rules = self
+                .rules
+                .entry(dir.to_owned())
+                .or_insert_with(|| ignore_rules(dir));
+            match rules.matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+/// Reads the rules of the ignore files in the directory, ignoring the invalid ones.
+fn ignore_rules(dir: &Path) -> Gitignore {
+    let mut builder = ignore::gitignore::GitignoreBuilder::new(dir);
+    for file in IGNORE_FILES {
+        let path = dir.join(file);
+        if path.is_file() {
+            let _ = builder.add(path);
+        }
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn ignores_paths_like_ripgrep() {
+        let root = env::temp_dir().join(format!("binocular-watch-{}", process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("sub").join(".ignore"), "!keep.log\n").unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+
+        let mut ignored = IgnoredPaths::new(root.clone());
+        let results = [
+            "src/main.rs",
+            "target/debug/binocular",
+            "debug.log",
+            "sub/keep.log",
+            "sub/other.log",
+            ".git/index",
+        ]
+        .map(|path| ignored.is_ignored(&root.join(path)));
+        let outside = ignored.is_ignored(Path::new("/elsewhere/main.rs"));
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(results, [false, true, true, false, true, true]);
+        assert!(outside);
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The workspace file exported to when none was given on the command line.
+pub(crate) const DEFAULT_WORKSPACE_FILE: &str = "binocular.code-workspace";
+
+/// Writes the files opened during the session to the workspace file. A `.code-workspace`
+/// file is written as a VS Code workspace of the folders containing them, and any other
+/// file as a plain list of their paths, one per line. Relative paths are resolved
+/// against `cwd`.
+pub(crate) fn export(path: &Path, files: &[String], cwd: &Path) -> Result<()> {
+    let files = files.iter().map(|file| cwd.join(file)).collect::<Vec<_>>();
+    let contents = if path.extension().is_some_and(|ext| ext == "code-workspace") {
+        code_workspace(&files)
+    } else {
+        files
+            .iter()
+            .map(|file| format!("{}\n", file.display()))
+            .collect()
+    };
+    fs::write(path, contents)
+        .with_context(|| format!("Failed to export the workspace to {}", path.display()))
+}
+
+/// Returns the VS Code workspace of the folders containing the files, leaving out the
+/// ones nested in another.
+fn code_workspace(files: &[PathBuf]) -> String {
+    let mut dirs = files
+        .iter()
+        .filter_map(|file| file.parent())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.dedup();
+    let folders = dirs
+        .iter()
+        .filter(|dir| {
+            !dirs
+                .iter()
+                .any(|other| other != *dir && dir.starts_with(other))
+        })
+        .map(|dir| json!({ "path": dir }))
+        .collect::<Vec<_>>();
+    let workspace = json!({ "folders": folders, "settings": {} });
+    format!("{:#}\n", workspace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn exports_the_opened_files() {
+        let dir = env::temp_dir().join(format!("binocular-workspace-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [
+            "src/app.rs".to_owned(),
+            "src/pickers/grep.rs".to_owned(),
+            "/etc/hosts".to_owned(),
+        ];
+
+        let list = dir.join("files.txt");
+        export(&list, &files, Path::new("/project")).unwrap();
+        assert_eq!(
+            fs::read_to_string(&list).unwrap(),
+            "/project/src/app.rs\n/project/src/pickers/grep.rs\n/etc/hosts\n"
+        );
+
+        // Nested folders are covered by their parent.
+        let workspace = dir.join("triage.code-workspace");
+        export(&workspace, &files, Path::new("/project")).unwrap();
+        let workspace: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&workspace).unwrap()).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(
+            workspace["folders"],
+            json!([{ "path": "/etc" }, { "path": "/project/src" }])
+        );
+    }
+}